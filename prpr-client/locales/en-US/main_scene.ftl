@@ -4,3 +4,7 @@ cannot-find-chart = Beatmap not found
 
 delete-success = Deleted successfully
 delete-failed = Failed to delete
+
+calibration-failed = Failed to start calibration
+calibration-done = Offset set to { $offset }s (confidence { $confidence })
+calibration-unreliable = Taps were too inconsistent, offset unchanged
@@ -16,6 +16,12 @@ aggressive-opt = Aggressive optimization
 low-perf-mode = Low performance mode
 player-rks = Player RKS
 offset = Offset (s)
+auto-calibrate = Calibrate offset
+keyboard-only = Keyboard only
+tap-keys = Tap keys
+lane-keys = Lane keys
+flick-key = Flick key
+invalid-keys = Unknown or reserved keys (Space, Left, Right and Q are reserved)
 speed = Speed
 note-size = Note size
 music-vol = Music volume
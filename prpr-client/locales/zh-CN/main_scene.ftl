@@ -4,3 +4,7 @@ cannot-find-chart = 找不到谱面
 
 delete-success = 已删除
 delete-failed = 删除失败
+
+calibration-failed = 无法开始校准
+calibration-done = 延迟已设为 { $offset }s（置信度 { $confidence }）
+calibration-unreliable = 打击不够稳定，延迟未修改
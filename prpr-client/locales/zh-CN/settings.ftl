@@ -16,6 +16,12 @@ aggressive-opt = 激进优化
 low-perf-mode = 低性能模式
 player-rks = 玩家 RKS
 offset = 偏移(s)
+auto-calibrate = 自动校准偏移
+keyboard-only = 仅键盘游玩
+tap-keys = 点击键
+lane-keys = 轨道键
+flick-key = 划键
+invalid-keys = 按键未知或被占用（空格、左右方向键和 Q 被占用）
 speed = 速度
 note-size = 音符大小
 music-vol = 音乐音量
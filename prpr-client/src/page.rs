@@ -43,6 +43,8 @@ const CARD_PADDING: f32 = 0.02;
 const SIDE_PADDING: f32 = 0.02;
 
 pub static SHOULD_UPDATE: AtomicBool = AtomicBool::new(false);
+/// Set by the settings page to have [`MainScene`](crate::scene::MainScene) start offset calibration.
+pub static START_CALIBRATION: AtomicBool = AtomicBool::new(false);
 
 pub fn illustration_task(path: String) -> Task<Result<(DynamicImage, Option<DynamicImage>)>> {
     Task::new(async move {
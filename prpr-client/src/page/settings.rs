@@ -1,10 +1,11 @@
 prpr::tl_file!("settings");
 
-use super::{Page, SharedState};
+use super::{Page, SharedState, START_CALIBRATION};
 use crate::{dir, get_data, get_data_mut, save_data, sync_lang, data::THEMES};
 use anyhow::{Context, Result};
 use macroquad::prelude::*;
 use prpr::{
+    config::KeyBindings,
     core::{ParticleEmitter, ResourcePack, JUDGE_LINE_PERFECT_COLOR, NOTE_WIDTH_RATIO_BASE},
     ext::{create_audio_manger, poll_future, LocalTask, RectExt, SafeTexture},
     scene::{request_file, return_file, show_error, show_message, take_file},
@@ -12,7 +13,7 @@ use prpr::{
     ui::{RectButton, Ui},
 };
 use sasa::{AudioClip, AudioManager, Music, MusicParams, PlaySfxParams, Sfx};
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::atomic::Ordering};
 
 const RESET_WAIT: f32 = 0.8;
 
@@ -306,6 +307,42 @@ impl Page for SettingsPage {
                         self.reset_time = t;
                     }
                 }
+                ui.dy(r.h + s * 2.);
+                r.x -= r.w + 0.01;
+                r.w = 0.4;
+                if ui.button("calibrate", r, tl!("auto-calibrate")) {
+                    START_CALIBRATION.store(true, Ordering::SeqCst);
+                }
+                ui.dy(r.h + s * 2.);
+                let keys = &mut config.key_bindings;
+                let r = ui.checkbox(tl!("keyboard-only"), &mut keys.keyboard_only);
+                ui.dy(r.h + s);
+                for (label, list) in [(tl!("tap-keys"), &mut keys.tap), (tl!("lane-keys"), &mut keys.lanes)] {
+                    let old = list.join(", ");
+                    let mut input = old.clone();
+                    let r = ui.input(label, &mut input, 0.3);
+                    if input != old {
+                        match KeyBindings::parse_keys(&input) {
+                            Some(value) => *list = value,
+                            None => {
+                                show_message(tl!("invalid-keys"));
+                            }
+                        }
+                    }
+                    ui.dy(r.h + s * 2.);
+                }
+                let old = keys.flick.clone().unwrap_or_default();
+                let mut input = old.clone();
+                ui.input(tl!("flick-key"), &mut input, 0.3);
+                if input != old {
+                    match KeyBindings::parse_keys(&input).as_deref() {
+                        Some([]) => keys.flick = None,
+                        Some([key]) => keys.flick = Some(key.clone()),
+                        _ => {
+                            show_message(tl!("invalid-keys"));
+                        }
+                    }
+                }
             });
 
             let ct = (0.9, ui.top * 1.5);
@@ -5,7 +5,7 @@ use crate::{
     cloud::{LCFile, UserManager},
     data::THEMES,
     dir, get_data, get_data_mut,
-    page::{self, ChartItem, Page, SharedState},
+    page::{self, ChartItem, Page, SharedState, START_CALIBRATION},
     save_data,
 };
 use anyhow::Result;
@@ -15,9 +15,12 @@ use lyon::{
 };
 use macroquad::{prelude::*, texture::RenderTarget};
 use prpr::{
+    calibration::{self, OffsetEstimate},
+    config::Config,
     core::Tweenable,
-    ext::{screen_aspect, SafeTexture, ScaleType},
-    scene::{show_error, show_message, NextScene, Scene},
+    ext::{poll_future, screen_aspect, LocalTask, SafeTexture, ScaleType},
+    fs,
+    scene::{show_error, show_message, GameMode, LoadingScene, NextScene, Scene},
     time::TimeManager,
     ui::{RectButton, Scroll, Ui},
 };
@@ -31,6 +34,9 @@ const SIDE_PADDING: f32 = 0.02;
 const CARD_PADDING: f32 = 0.02;
 pub const CHARTS_BAR_HEIGHT: f32 = 0.08;
 
+/// Calibration results less trustworthy than this are not applied.
+const MIN_CALIBRATION_CONFIDENCE: f32 = 0.5;
+
 const SWITCH_TIME: f32 = 0.4;
 const TRANSIT_TIME: f32 = 0.4;
 
@@ -59,6 +65,9 @@ pub struct MainScene {
 
     shared_state: SharedState,
     pages: [Box<dyn Page>; PAGE_NUM],
+
+    scene_task: LocalTask<Result<LoadingScene>>,
+    calibrating: bool,
 }
 
 impl MainScene {
@@ -100,9 +109,40 @@ impl MainScene {
                 Box::new(page::SettingsPage::new().await?),
                 Box::new(page::AboutPage::new()),
             ],
+
+            scene_task: None,
+            calibrating: false,
         })
     }
 
+    fn start_calibration(&mut self) -> Result<()> {
+        for page in &mut self.pages {
+            page.pause()?;
+        }
+        self.calibrating = true;
+        let fs = fs::fs_from_assets("")?;
+        self.scene_task = Some(Box::pin(async move {
+            LoadingScene::new(
+                GameMode::Calibration,
+                calibration::chart_info(),
+                Config {
+                    res_pack_path: get_data()
+                        .config
+                        .res_pack_path
+                        .as_ref()
+                        .map(|it| format!("{}/{it}", dir::root().unwrap())),
+                    ..get_data().config.clone()
+                },
+                fs,
+                (None, None),
+                None,
+                None,
+            )
+            .await
+        }));
+        Ok(())
+    }
+
     fn ui(&mut self, ui: &mut Ui, t: f32, rt: f32) {
         let px = SIDE_PADDING;
         ui.scope(|ui| {
@@ -198,6 +238,11 @@ impl Scene for MainScene {
         self.target = target;
         if let Some((.., st, _, true)) = &mut self.shared_state.transit {
             *st = tm.now() as _;
+        } else if self.calibrating {
+            self.calibrating = false;
+            for page in &mut self.pages {
+                page.resume()?;
+            }
         } else {
             tm.seek_to(rand::gen_range(1., 10.));
             show_message(tl!("welcome"));
@@ -253,6 +298,52 @@ impl Scene for MainScene {
         for (id, page) in self.pages.iter_mut().enumerate() {
             page.update(id == self.page_index, &mut self.shared_state)?;
         }
+        if START_CALIBRATION.fetch_and(false, Ordering::SeqCst) && self.scene_task.is_none() {
+            self.start_calibration()?;
+        }
+        if let Some(future) = &mut self.scene_task {
+            if let Some(scene) = poll_future(future.as_mut()) {
+                self.scene_task = None;
+                match scene {
+                    Ok(scene) => self.next_scene = Some(NextScene::Overlay(Box::new(scene))),
+                    Err(err) => {
+                        show_error(err.context(tl!("calibration-failed")));
+                        self.calibrating = false;
+                        for page in &mut self.pages {
+                            page.resume()?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_result(&mut self, _tm: &mut TimeManager, result: Box<dyn std::any::Any>) -> Result<()> {
+        let result = match result.downcast::<anyhow::Error>() {
+            Ok(error) => {
+                show_error(error.context(tl!("calibration-failed")));
+                return Ok(());
+            }
+            Err(res) => res,
+        };
+        if let Ok(estimate) = result.downcast::<Option<OffsetEstimate>>() {
+            match *estimate {
+                Some(estimate) if estimate.confidence >= MIN_CALIBRATION_CONFIDENCE => {
+                    get_data_mut().config.offset = estimate.offset;
+                    save_data()?;
+                    show_message(tl!(
+                        "calibration-done",
+                        "offset" => format!("{:.3}", estimate.offset),
+                        "confidence" => format!("{:.0}%", estimate.confidence * 100.)
+                    ))
+                    .ok();
+                }
+                _ => {
+                    show_message(tl!("calibration-unreliable")).warn();
+                }
+            }
+        }
         Ok(())
     }
 
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use prpr::{
+    fs,
+    lint::{lint, LintReport, Severity},
+};
+use std::path::Path;
+
+fn print_report(path: &str, report: &LintReport) {
+    println!("{path}");
+    for diag in &report.diagnostics {
+        println!("  {diag}");
+    }
+    println!(
+        "  {} error(s), {} warning(s), {} info(s)",
+        report.count(Severity::Error),
+        report.count(Severity::Warning),
+        report.count(Severity::Info)
+    );
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args();
+    let program = args.next().unwrap();
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg == "--json" {
+            json = true;
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.is_empty() {
+        anyhow::bail!("Usage: {program} [--json] <chart>...");
+    }
+
+    let rt = tokio::runtime::Builder::new_multi_thread().worker_threads(4).enable_all().build()?;
+    let mut failed = false;
+    let mut results = Vec::new();
+    for path in paths {
+        let report = rt.block_on(async {
+            let mut fs = fs::fs_from_file(Path::new(&path))?;
+            lint(fs.as_mut()).await
+        });
+        match report {
+            Ok(report) => {
+                failed |= report.has_errors();
+                if json {
+                    results.push(serde_json::json!({ "path": path, "report": report }));
+                } else {
+                    print_report(&path, &report);
+                }
+            }
+            Err(err) => {
+                failed = true;
+                if json {
+                    results.push(serde_json::json!({ "path": path, "error": format!("{err:?}") }));
+                } else {
+                    eprintln!("{path}: {err:?}");
+                }
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
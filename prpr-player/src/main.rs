@@ -2,9 +2,11 @@ use anyhow::{Context, Result};
 use macroquad::prelude::*;
 use prpr::{
     build_conf,
+    calibration::{self, OffsetEstimate},
     core::init_assets,
     fs,
-    scene::{show_error, GameMode, LoadingScene, NextScene, Scene},
+    replay::Replay,
+    scene::{show_error, show_message, GameMode, LoadingScene, NextScene, Scene},
     time::TimeManager,
     ui::{FontArc, TextPainter, Ui},
     Main,
@@ -14,7 +16,16 @@ use std::ops::DerefMut;
 struct BaseScene(Option<NextScene>, bool);
 impl Scene for BaseScene {
     fn on_result(&mut self, _tm: &mut TimeManager, result: Box<dyn std::any::Any>) -> Result<()> {
-        show_error(result.downcast::<anyhow::Error>().unwrap().context("加载谱面失败"));
+        match result.downcast::<Option<OffsetEstimate>>() {
+            Ok(estimate) => {
+                if let Some(estimate) = *estimate {
+                    show_message(format!("Recommended offset: {:.3}s (confidence {:.0}%)", estimate.offset, estimate.confidence * 100.)).ok();
+                } else {
+                    show_message("Not enough taps to calibrate");
+                }
+            }
+            Err(result) => show_error(result.downcast::<anyhow::Error>().unwrap().context("加载谱面失败")),
+        }
         self.1 = true;
         Ok(())
     }
@@ -40,7 +51,7 @@ async fn main() -> Result<()> {
     init_assets();
 
     #[cfg(target_arch = "wasm32")]
-    let (mut fs, config) = {
+    let (mut fs, config, replay, calibrate) = {
         fn js_err(err: wasm_bindgen::JsValue) -> anyhow::Error {
             anyhow::Error::msg(format!("{err:?}"))
         }
@@ -52,22 +63,34 @@ async fn main() -> Result<()> {
                 autoplay: false,
                 ..Default::default()
             }),
+            None,
+            false,
         )
     };
     #[cfg(any(target_os = "android", target_os = "ios"))]
-    let (mut fs, config) = (fs::fs_from_assets("charts/moment/")?, None);
+    let (mut fs, config, replay, calibrate) = (fs::fs_from_assets("charts/moment/")?, None, None, false);
     #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android"), not(target_os = "ios")))]
-    let (mut fs, config) = {
+    let (mut fs, config, replay, calibrate) = {
         let mut args = std::env::args();
         let program = args.next().unwrap();
         let Some(path) = args.next() else {
-            anyhow::bail!("Usage: {program} <chart>");
+            anyhow::bail!("Usage: {program} <chart | --calibrate> [config] [replay]");
         };
+        let calibrate = path == "--calibrate";
         let mut config = None;
-        if let Some(config_path) = args.next() {
+        if let Some(config_path) = args.next().filter(|it| !it.is_empty()) {
             config = Some(serde_yaml::from_str(&std::fs::read_to_string(config_path).context("Cannot read from config file")?)?);
         }
-        (fs::fs_from_file(std::path::Path::new(&path))?, config)
+        let mut replay = None;
+        if let Some(replay_path) = args.next() {
+            replay = Some(Replay::decode(&std::fs::read(replay_path).context("Cannot read from replay file")?)?);
+        }
+        let fs = if calibrate {
+            fs::fs_from_assets("")?
+        } else {
+            fs::fs_from_file(std::path::Path::new(&path))?
+        };
+        (fs, config, replay, calibrate)
     };
 
     let _guard = {
@@ -90,7 +113,11 @@ async fn main() -> Result<()> {
     let font = FontArc::try_from_vec(load_file("font.ttf").await?)?;
     let mut painter = TextPainter::new(font);
 
-    let info = fs::load_info(fs.deref_mut()).await?;
+    let info = if calibrate {
+        calibration::chart_info()
+    } else {
+        fs::load_info(fs.deref_mut()).await?
+    };
     let config = config.unwrap_or_default();
 
     let mut fps_time = -1;
@@ -99,7 +126,22 @@ async fn main() -> Result<()> {
     let ctm = TimeManager::from_config(&config); // strange variable name...
     let mut main = Main::new(
         Box::new(BaseScene(
-            Some(NextScene::Overlay(Box::new(LoadingScene::new(GameMode::Normal, info, config, fs, (None, None), None, None).await?))),
+            Some(NextScene::Overlay(Box::new(
+                LoadingScene::new(
+                    if calibrate {
+                        GameMode::Calibration
+                    } else {
+                        replay.map_or(GameMode::Normal, GameMode::Replay)
+                    },
+                    info,
+                    config,
+                    fs,
+                    (None, None),
+                    None,
+                    None,
+                )
+                .await?,
+            ))),
             false,
         )),
         ctm,
@@ -0,0 +1,107 @@
+use serde::Deserialize;
+
+/// The format of the rendered file, which decides how ffmpeg encodes it.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// H.264 + AAC in MP4.
+    #[default]
+    H264,
+    /// H.265 + AAC in MP4.
+    H265,
+    /// VP9 + Opus in WebM.
+    Vp9,
+    /// AV1 + Opus in WebM.
+    Av1,
+    /// FFV1 + FLAC in Matroska, for further editing.
+    Lossless,
+    /// PNG image sequence, written into the output folder along with `audio.wav`.
+    Png,
+    /// The mixed audio only, as WAV.
+    Wav,
+    /// The mixed audio only, as FLAC.
+    Flac,
+}
+
+impl OutputFormat {
+    pub const ALL: [Self; 8] = [
+        Self::H264,
+        Self::H265,
+        Self::Vp9,
+        Self::Av1,
+        Self::Lossless,
+        Self::Png,
+        Self::Wav,
+        Self::Flac,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::H264 => "h264",
+            Self::H265 => "h265",
+            Self::Vp9 => "vp9",
+            Self::Av1 => "av1",
+            Self::Lossless => "lossless",
+            Self::Png => "png",
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn default_output(self) -> &'static str {
+        match self {
+            Self::H264 | Self::H265 => "out.mp4",
+            Self::Vp9 | Self::Av1 => "out.webm",
+            Self::Lossless => "out.mkv",
+            Self::Png => "out",
+            Self::Wav => "out.wav",
+            Self::Flac => "out.flac",
+        }
+    }
+
+    pub fn has_video(self) -> bool {
+        !matches!(self, Self::Wav | Self::Flac)
+    }
+
+    /// Whether the output is written by the render pass directly, without a final pass to merge audio.
+    pub fn is_sequence(self) -> bool {
+        matches!(self, Self::Png)
+    }
+
+    /// The ffmpeg muxer of the final output.
+    pub fn container(self) -> &'static str {
+        match self {
+            Self::H264 | Self::H265 => "mp4",
+            Self::Vp9 | Self::Av1 => "webm",
+            Self::Lossless => "matroska",
+            Self::Png => "image2",
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+        }
+    }
+
+    /// Video codec arguments of the final pass, which reads the lossless intermediate.
+    pub fn video_args(self, bitrate: &str) -> String {
+        match self {
+            Self::H264 => format!("-c:v libx264 -pix_fmt yuv420p -b:v {bitrate}"),
+            Self::H265 => format!("-c:v libx265 -pix_fmt yuv420p -tag:v hvc1 -b:v {bitrate}"),
+            Self::Vp9 => format!("-c:v libvpx-vp9 -pix_fmt yuv420p -b:v {bitrate}"),
+            Self::Av1 => format!("-c:v libaom-av1 -cpu-used 6 -row-mt 1 -pix_fmt yuv420p -b:v {bitrate}"),
+            Self::Lossless => "-c:v copy".to_owned(),
+            Self::Png | Self::Wav | Self::Flac => String::new(),
+        }
+    }
+
+    pub fn audio_codec(self) -> &'static str {
+        match self {
+            Self::H264 | Self::H265 => "aac",
+            Self::Vp9 | Self::Av1 => "libopus",
+            Self::Lossless | Self::Flac => "flac",
+            Self::Png | Self::Wav => "pcm_s16le",
+        }
+    }
+}
@@ -1,24 +1,28 @@
+mod encoder;
 mod scene;
 
-use crate::scene::MainScene;
+use crate::{encoder::OutputFormat, scene::MainScene};
 use anyhow::{bail, Context, Result};
 use macroquad::{miniquad::TextureFormat, prelude::*};
 use prpr::{
     build_conf,
     config::Config,
-    core::{init_assets, MSRenderTarget, NoteKind},
+    core::{init_assets, BpmList, MSRenderTarget, NoteKind},
     fs::{self, PatchedFileSystem},
+    judge::{HitSound, Judge},
+    replay::Replay,
     scene::{GameMode, GameScene, LoadingScene, BILLBOARD},
     time::TimeManager,
     ui::{ChartInfoEdit, FontArc, TextPainter, Ui},
     Main,
 };
 use sasa::AudioClip;
+use serde::Deserialize;
 use std::{
     cell::RefCell,
     io::{BufWriter, Write},
     ops::DerefMut,
-    process::{Command, Stdio},
+    process::{Command, ExitStatus, Stdio},
     rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -28,13 +32,48 @@ use std::{
 };
 use std::{fmt::Write as _, path::Path};
 
-#[derive(Clone)]
+/// A point in the chart, either in seconds or in beats.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum ChartTime {
+    Seconds(f32),
+    Beats { beats: f32 },
+}
+
+impl ChartTime {
+    /// Parses `12.5` as seconds and `32b` as beats.
+    fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim().strip_suffix('b') {
+            Some(beats) => Self::Beats { beats: beats.parse()? },
+            None => Self::Seconds(s.trim().parse()?),
+        })
+    }
+
+    fn seconds(self, bpm_list: &mut BpmList) -> f32 {
+        match self {
+            Self::Seconds(time) => time,
+            Self::Beats { beats } => bpm_list.time_beats(beats),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
 struct VideoConfig {
     fps: u32,
     resolution: (u32, u32),
     hardware_accel: bool,
     ending_length: f64,
     bitrate: String,
+    format: OutputFormat,
+    output: Option<String>,
+
+    start: Option<ChartTime>,
+    end: Option<ChartTime>,
+    skip_loading: bool,
+    skip_ending: bool,
+    fade: f64,
+    screenshot: Option<ChartTime>,
 }
 
 impl Default for VideoConfig {
@@ -45,8 +84,159 @@ impl Default for VideoConfig {
             hardware_accel: false,
             ending_length: 27.5,
             bitrate: "7M".to_string(),
+            format: OutputFormat::default(),
+            output: None,
+
+            start: None,
+            end: None,
+            skip_loading: false,
+            skip_ending: false,
+            fade: 0.5,
+            screenshot: None,
+        }
+    }
+}
+
+impl VideoConfig {
+    /// Checks the final values, including the ones from a job file which don't go through the checks of the flags.
+    fn validate(&self) -> Result<()> {
+        if self.fps == 0 {
+            bail!("帧率应当为正数");
+        }
+        if self.resolution.0 == 0 || self.resolution.1 == 0 {
+            bail!("分辨率的宽高应当为正数");
+        }
+        if !self.ending_length.is_finite() || self.ending_length < 0. {
+            bail!("结尾时长不能为负数");
+        }
+        if !self.fade.is_finite() || self.fade < 0. {
+            bail!("淡入淡出时长不能为负数");
+        }
+        Ok(())
+    }
+}
+
+fn parse_resolution(s: &str) -> Result<(u32, u32)> {
+    if let Some((w, h)) = s.split_once(['x', 'X', '×', '*']) {
+        let (w, h) = (w.trim().parse::<u32>()?, h.trim().parse::<u32>()?);
+        if w == 0 || h == 0 {
+            bail!("宽高应当为正数");
+        }
+        Ok((w, h))
+    } else {
+        bail!("格式应当为 “宽x高”")
+    }
+}
+
+struct Args {
+    path: String,
+    replay: Option<Replay>,
+    config_path: String,
+    headless: bool,
+    v_config: VideoConfig,
+}
+
+const USAGE: &str = "用法：prpr-render <谱面> [回放] [--config conf.yml] [--job job.yml] [--headless] \
+    [--resolution 1920x1080] [--fps 60] [--bitrate 7M] [--ending-length 27.5] [--hardware-accel] [--format h264] [--output out.mp4] \
+    [--start 12.5|32b] [--end 40|96b] [--skip-loading] [--skip-ending] [--fade 0.5] [--screenshot 12.5|32b]";
+
+fn parse_args() -> Result<Args> {
+    let mut args = std::env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut config_path = "conf.yml".to_owned();
+    let mut job = None;
+    let mut headless = false;
+    let mut overrides: Vec<(String, String)> = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{arg} 缺少参数\n{USAGE}"));
+        match arg.as_str() {
+            "--headless" => headless = true,
+            "--hardware-accel" | "--skip-loading" | "--skip-ending" => overrides.push((arg, String::new())),
+            "--config" => config_path = value()?,
+            "--job" => {
+                job = Some(value()?);
+                headless = true;
+            }
+            "--resolution" | "--fps" | "--bitrate" | "--ending-length" | "--format" | "--output" | "--start" | "--end" | "--fade"
+            | "--screenshot" => {
+                let value = value()?;
+                overrides.push((arg, value));
+            }
+            _ if arg.starts_with("--") => bail!("未知参数 {arg}\n{USAGE}"),
+            _ => positional.push(arg),
         }
     }
+    let mut positional = positional.into_iter();
+    let Some(path) = positional.next() else {
+        bail!("请将谱面文件或文件夹拖动到该软件上！\n{USAGE}");
+    };
+    let replay = positional
+        .next()
+        .map(|path| -> Result<Replay> { Replay::decode(&std::fs::read(path).context("无法读取回放文件")?) })
+        .transpose()
+        .context("加载回放失败")?;
+    if replay.as_ref().map_or(false, |it| (it.speed - 1.).abs() > 1e-3) {
+        bail!("暂不支持渲染非原速的回放");
+    }
+
+    let mut v_config: VideoConfig = match job {
+        Some(job) => {
+            serde_yaml::from_str(&std::fs::read_to_string(&job).with_context(|| format!("无法读取任务文件 {job}"))?).context("任务文件格式错误")?
+        }
+        None => VideoConfig::default(),
+    };
+    for (key, value) in overrides {
+        let invalid = || format!("{key} 的值 `{value}` 非法");
+        match key.as_str() {
+            "--resolution" => v_config.resolution = parse_resolution(&value).with_context(invalid)?,
+            "--fps" => {
+                let fps: u32 = value.parse().with_context(invalid)?;
+                if fps == 0 {
+                    bail!(invalid());
+                }
+                v_config.fps = fps;
+            }
+            "--bitrate" => v_config.bitrate = value,
+            "--ending-length" => {
+                let length: f64 = value.parse().with_context(invalid)?;
+                if !length.is_finite() || length < 0. {
+                    bail!(invalid());
+                }
+                v_config.ending_length = length;
+            }
+            "--hardware-accel" => v_config.hardware_accel = true,
+            "--format" => v_config.format = OutputFormat::from_name(&value).with_context(invalid)?,
+            "--output" => v_config.output = Some(value),
+            "--start" => v_config.start = Some(ChartTime::parse(&value).with_context(invalid)?),
+            "--end" => v_config.end = Some(ChartTime::parse(&value).with_context(invalid)?),
+            "--skip-loading" => v_config.skip_loading = true,
+            "--skip-ending" => v_config.skip_ending = true,
+            "--fade" => {
+                let fade: f64 = value.parse().with_context(invalid)?;
+                if !fade.is_finite() || fade < 0. {
+                    bail!(invalid());
+                }
+                v_config.fade = fade;
+            }
+            "--screenshot" => v_config.screenshot = Some(ChartTime::parse(&value).with_context(invalid)?),
+            _ => unreachable!(),
+        }
+    }
+    v_config.validate()?;
+    Ok(Args {
+        path,
+        replay,
+        config_path,
+        headless,
+        v_config,
+    })
+}
+
+fn check_ffmpeg(status: ExitStatus) -> Result<()> {
+    if !status.success() {
+        bail!("ffmpeg 异常退出：{status}");
+    }
+    Ok(())
 }
 
 static INFO_EDIT: Mutex<Option<ChartInfoEdit>> = Mutex::new(None);
@@ -58,7 +248,9 @@ compile_error!("WASM target is not supported");
 async fn the_main() -> Result<()> {
     init_assets();
     set_panic_handler(|msg, backtrace| async move {
+        eprintln!("发生错误：{msg}\n\n详细堆栈：\n{backtrace}");
         let _ = std::fs::write("错误信息.txt", format!("发生错误：{msg}\n\n详细堆栈：\n{backtrace}"));
+        std::process::exit(1);
     });
 
     let ffmpeg = if cfg!(target_os = "windows") {
@@ -82,21 +274,21 @@ async fn the_main() -> Result<()> {
     let font = FontArc::try_from_vec(load_file("font.ttf").await?)?;
     let mut painter = TextPainter::new(font);
 
-    let (path, config) = {
-        let mut args = std::env::args().skip(1);
-        let Some(path) = args.next() else {
-            bail!("请将谱面文件或文件夹拖动到该软件上！");
+    let Args {
+        path,
+        replay,
+        config_path,
+        headless,
+        v_config,
+    } = parse_args()?;
+    let config =
+        match (|| -> Result<Config> { Ok(serde_yaml::from_str(&std::fs::read_to_string(&config_path).context("无法加载配置文件")?)?) })() {
+            Err(err) => {
+                warn!("无法加载配置文件：{:?}", err);
+                Config::default()
+            }
+            Ok(config) => config,
         };
-        let config =
-            match (|| -> Result<Config> { Ok(serde_yaml::from_str(&std::fs::read_to_string("conf.yml").context("无法加载配置文件")?)?) })() {
-                Err(err) => {
-                    warn!("无法加载配置文件：{:?}", err);
-                    Config::default()
-                }
-                Ok(config) => config,
-            };
-        (path, config)
-    };
 
     let mut fs = fs::fs_from_file(std::path::Path::new(&path)).context("加载谱面失败")?;
     let info = fs::load_info(fs.deref_mut()).await.context("加载谱面信息失败")?;
@@ -117,84 +309,89 @@ async fn the_main() -> Result<()> {
 
     let mut gl = unsafe { get_internal_gl() };
 
-    let texture = miniquad::Texture::new_render_texture(
-        gl.quad_context,
-        miniquad::TextureParams {
-            width: 1080,
-            height: 608,
-            format: TextureFormat::RGB8,
-            ..Default::default()
-        },
-    );
-    let target = Some({
-        let render_pass = miniquad::RenderPass::new(gl.quad_context, texture, None);
-        RenderTarget {
-            texture: Texture2D::from_miniquad_texture(texture),
-            render_pass,
-        }
-    });
-    let tex = Texture2D::from_miniquad_texture(texture);
-    let mut main = Main::new(Box::new(MainScene::new(target, info, config.clone(), fs.clone_box())), TimeManager::default(), None).await?;
-    let width = texture.width as f32 / 2.;
-    loop {
-        if main.scenes.len() == 1 {
-            gl.quad_gl.viewport(Some((0, 0, texture.width as _, texture.height as _)));
-            let sw = screen_width();
-            let lf = (sw - width) / 2.;
-            main.update_with_mutate(|touch| {
-                touch.position.x -= lf / texture.width as f32 * 2.;
-            })?;
-            main.show_billboard = false;
-            main.render(&mut Ui::new(&mut painter))?;
-            gl.flush();
-            set_camera(&Camera2D {
-                zoom: vec2(1., -screen_width() / screen_height()),
+    let (edit, v_config) = if headless {
+        (ChartInfoEdit::new(info), v_config)
+    } else {
+        let texture = miniquad::Texture::new_render_texture(
+            gl.quad_context,
+            miniquad::TextureParams {
+                width: 1080,
+                height: 608,
+                format: TextureFormat::RGB8,
                 ..Default::default()
-            });
-            let mut ui = Ui::new(&mut painter);
-            clear_background(GRAY);
-            draw_texture_ex(
-                tex,
-                -1. + lf / sw * 2.,
-                -ui.top,
-                WHITE,
-                DrawTextureParams {
-                    flip_y: true,
-                    dest_size: Some(vec2(texture.width as f32, texture.height as f32) * (2. / sw)),
+            },
+        );
+        let target = Some({
+            let render_pass = miniquad::RenderPass::new(gl.quad_context, texture, None);
+            RenderTarget {
+                texture: Texture2D::from_miniquad_texture(texture),
+                render_pass,
+            }
+        });
+        let tex = Texture2D::from_miniquad_texture(texture);
+        let mut main =
+            Main::new(Box::new(MainScene::new(target, info, config.clone(), fs.clone_box(), replay.clone(), v_config)), TimeManager::default(), None)
+                .await?;
+        let width = texture.width as f32 / 2.;
+        loop {
+            if main.scenes.len() == 1 {
+                gl.quad_gl.viewport(Some((0, 0, texture.width as _, texture.height as _)));
+                let sw = screen_width();
+                let lf = (sw - width) / 2.;
+                main.update_with_mutate(|touch| {
+                    touch.position.x -= lf / texture.width as f32 * 2.;
+                })?;
+                main.show_billboard = false;
+                main.render(&mut Ui::new(&mut painter))?;
+                gl.flush();
+                set_camera(&Camera2D {
+                    zoom: vec2(1., -screen_width() / screen_height()),
                     ..Default::default()
-                },
-            );
-            BILLBOARD.with(|it| {
-                let mut guard = it.borrow_mut();
-                let t = guard.1.now() as f32;
-                guard.0.render(&mut ui, t);
-            });
-        } else {
-            main.update()?;
-            gl.quad_gl.viewport(None);
-            gl.quad_gl.render_pass(None);
-            main.render(&mut Ui::new(&mut painter))?;
-        }
-        if main.should_exit() {
-            break;
-        }
+                });
+                let mut ui = Ui::new(&mut painter);
+                clear_background(GRAY);
+                draw_texture_ex(
+                    tex,
+                    -1. + lf / sw * 2.,
+                    -ui.top,
+                    WHITE,
+                    DrawTextureParams {
+                        flip_y: true,
+                        dest_size: Some(vec2(texture.width as f32, texture.height as f32) * (2. / sw)),
+                        ..Default::default()
+                    },
+                );
+                BILLBOARD.with(|it| {
+                    let mut guard = it.borrow_mut();
+                    let t = guard.1.now() as f32;
+                    guard.0.render(&mut ui, t);
+                });
+            } else {
+                main.update()?;
+                gl.quad_gl.viewport(None);
+                gl.quad_gl.render_pass(None);
+                main.render(&mut Ui::new(&mut painter))?;
+            }
+            if main.should_exit() {
+                break;
+            }
 
+            next_frame().await;
+        }
+        clear_background(BLACK);
         next_frame().await;
-    }
-    clear_background(BLACK);
-    next_frame().await;
+        (INFO_EDIT.lock().unwrap().take().unwrap(), VIDEO_CONFIG.lock().unwrap().take().unwrap())
+    };
 
-    let edit = INFO_EDIT.lock().unwrap().take().unwrap();
     let volume_music = config.volume_music;
     let volume_sfx = config.volume_sfx;
     let config = Config {
-        autoplay: true,
+        autoplay: replay.is_none(),
         volume_music: 0.,
         volume_sfx: 0.,
         ..config
     };
 
-    let v_config = VIDEO_CONFIG.lock().unwrap().take().unwrap();
     let (vw, vh) = v_config.resolution;
 
     let length = track_length - chart.offset.min(0.) as f64 + 1.;
@@ -203,17 +400,180 @@ async fn the_main() -> Result<()> {
 
     let render_start_time = Instant::now();
 
-    info!("[1] 混音中…");
+    let is_replay = replay.is_some();
+    let mode = match replay {
+        Some(replay) => {
+            Judge::log_hit_sounds();
+            GameMode::Replay(replay)
+        }
+        None => GameMode::Normal,
+    };
+    let mst = Rc::new(MSRenderTarget::new((vw, vh), config.sample_count));
+    let my_time: Rc<RefCell<f64>> = Rc::new(RefCell::new(0.));
+    let tm = TimeManager::manual(Box::new({
+        let my_time = Rc::clone(&my_time);
+        move || *(*my_time).borrow()
+    }));
+    let fs = Box::new(PatchedFileSystem(fs, edit.to_patches().await?));
+    static MSAA: AtomicBool = AtomicBool::new(false);
+    let mut main =
+        Main::new(Box::new(LoadingScene::new(mode, edit.info, config, fs, (None, None), Some(Rc::new(move || (vw, vh))), None).await?), tm, {
+            let mut cnt = 0;
+            let mst = Rc::clone(&mst);
+            move || {
+                cnt += 1;
+                if cnt == 1 || cnt == 3 {
+                    MSAA.store(true, Ordering::SeqCst);
+                    Some(mst.input())
+                } else {
+                    MSAA.store(false, Ordering::SeqCst);
+                    Some(mst.output())
+                }
+            }
+        })
+        .await?;
+    main.show_billboard = false;
+
+    const O: f64 = LoadingScene::TOTAL_TIME as f64 + GameScene::BEFORE_TIME as f64;
+    const A: f64 = 0.7 + 0.3 + 0.4;
+
+    let fps = v_config.fps;
+    let frame_delta = 1. / fps as f32;
+
+    // chart time to video time, see where hit sounds are placed below
+    let (range_start, range_end, screenshot) = {
+        let mut bpm_list = chart.bpm_list.borrow_mut();
+        let mut to_video = |time: Option<ChartTime>| time.map(|it| (O + offset as f64 + it.seconds(&mut bpm_list) as f64).clamp(0., video_length));
+        let start = to_video(v_config.start).unwrap_or(if v_config.skip_loading { LoadingScene::TOTAL_TIME as f64 } else { 0. });
+        let end = to_video(v_config.end).unwrap_or(if v_config.skip_ending {
+            O + length - 1. + GameScene::FADEOUT_TIME as f64
+        } else {
+            video_length
+        });
+        (start, end, to_video(v_config.screenshot))
+    };
+    if screenshot.is_none() && range_start >= range_end {
+        bail!("渲染区间为空");
+    }
+    let (first_frame, frames) = match screenshot {
+        Some(time) => {
+            let frame = (time / frame_delta as f64).round() as u64;
+            (frame, frame + 1)
+        }
+        None => ((range_start / frame_delta as f64).round() as u64, (range_end / frame_delta as f64).ceil() as u64),
+    };
+
+    let format = v_config.format;
+    let output = v_config
+        .output
+        .clone()
+        .unwrap_or_else(|| if screenshot.is_some() { "out.png" } else { format.default_output() }.to_owned());
+    let encode_video = screenshot.is_some() || format.has_video();
+    let temp_dir = tempfile::tempdir().context("无法创建临时文件夹")?;
+    let temp_video = temp_dir
+        .path()
+        .join(if format == OutputFormat::Lossless { "video.mkv" } else { "video.mp4" });
+    let temp_audio = temp_dir.path().join("audio.wav");
+
+    // audio-only exports still need the render pass to judge replays
+    if encode_video || is_replay {
+        println!("[1] 渲染视频…");
+        let mut proc = if encode_video {
+            let codecs = String::from_utf8(Command::new(&ffmpeg).arg("-codecs").output().context("无法执行 ffmpeg")?.stdout)?;
+            let use_cuda = v_config.hardware_accel && codecs.contains("h264_nvenc");
+            let has_qsv = v_config.hardware_accel && codecs.contains("h264_qsv");
+
+            let mut args = "-y -f rawvideo -c:v rawvideo".to_owned();
+            if use_cuda {
+                args += " -hwaccel_output_format cuda";
+            }
+            let codec = match format {
+                _ if screenshot.is_some() => "-c:v png -f image2 -update 1".to_owned(),
+                OutputFormat::Png => "-c:v png -f image2".to_owned(),
+                OutputFormat::Lossless => "-c:v ffv1".to_owned(),
+                _ => format!(
+                    "-c:v {} -qp 0",
+                    if use_cuda {
+                        "h264_nvenc"
+                    } else if has_qsv {
+                        "h264_qsv"
+                    } else if v_config.hardware_accel {
+                        bail!("不支持硬件加速！");
+                    } else {
+                        "libx264 -preset ultrafast"
+                    }
+                ),
+            };
+            write!(&mut args, " -s {vw}x{vh} -r {fps} -pix_fmt rgb24 -i - {codec} -vf vflip")?;
+            let dest = if screenshot.is_some() {
+                Path::new(&output).to_path_buf()
+            } else if format.is_sequence() {
+                std::fs::create_dir_all(&output).context("无法创建输出文件夹")?;
+                Path::new(&output).join("%05d.png")
+            } else {
+                temp_video.clone()
+            };
+
+            Some(
+                Command::new(&ffmpeg)
+                    .args(args.split_whitespace())
+                    .arg(dest)
+                    .stdin(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .context("无法执行 ffmpeg")?,
+            )
+        } else {
+            None
+        };
+        let mut input = proc.as_mut().map(|it| it.stdin.take().unwrap());
+
+        let mut bytes = vec![0; vw as usize * vh as usize * 3];
+
+        let start_time = Instant::now();
+
+        for frame in 0..frames {
+            *my_time.borrow_mut() = (frame as f32 * frame_delta).max(0.) as f64;
+            gl.quad_gl.render_pass(Some(mst.output().render_pass));
+            clear_background(BLACK);
+            main.update()?;
+            main.render(&mut Ui::new(&mut painter))?;
+            // TODO magic. can't remove this line.
+            draw_rectangle(0., 0., 0., 0., Color::default());
+            gl.flush();
+
+            if let Some(input) = input.as_mut().filter(|_| frame >= first_frame) {
+                if MSAA.load(Ordering::SeqCst) {
+                    mst.blit();
+                }
+                mst.output().texture.raw_miniquad_texture_handle().read_pixels(&mut bytes);
+                input.write_all(&bytes)?;
+            }
+            if frame % 100 == 0 {
+                println!("{frame} / {frames}, {:.2}fps", frame as f64 / start_time.elapsed().as_secs_f64());
+            }
+        }
+        drop(input);
+        if let Some(mut proc) = proc {
+            check_ffmpeg(proc.wait()?)?;
+        }
+    }
+    if screenshot.is_some() {
+        println!("截图完成！输出至 {output}");
+        return Ok(());
+    }
+
+    println!("[2] 混音中…");
     let sample_rate = 44100;
     assert_eq!(sample_rate, ending.sample_rate());
     assert_eq!(sample_rate, sfx_click.sample_rate());
     assert_eq!(sample_rate, sfx_drag.sample_rate());
     assert_eq!(sample_rate, sfx_flick.sample_rate());
-    let mut output = vec![0.0_f32; (video_length * sample_rate as f64).ceil() as usize * 2];
+    let mut samples = vec![0.0_f32; (video_length * sample_rate as f64).ceil() as usize * 2];
     {
         let pos = O - chart.offset.min(0.) as f64;
         let count = (music.length() as f64 * sample_rate as f64) as usize;
-        let mut it = output[((pos * sample_rate as f64).round() as usize * 2)..].iter_mut();
+        let mut it = samples[((pos * sample_rate as f64).round() as usize * 2)..].iter_mut();
         let ratio = 1. / sample_rate as f64;
         for frame in 0..count {
             let position = frame as f64 * ratio;
@@ -224,7 +584,7 @@ async fn the_main() -> Result<()> {
     }
     let mut place = |pos: f64, clip: &AudioClip, volume: f32| {
         let position = (pos * sample_rate as f64).round() as usize * 2;
-        let slice = &mut output[position..];
+        let slice = &mut samples[position..];
         let len = (slice.len() / 2).min(clip.frame_count());
         let mut it = slice.iter_mut();
         // TODO optimize?
@@ -235,142 +595,105 @@ async fn the_main() -> Result<()> {
             *dst += frame.1 * volume;
         }
     };
-    for note in chart.lines.iter().flat_map(|it| it.notes.iter()).filter(|it| !it.fake) {
-        place(
-            O + note.time as f64 + offset as f64,
-            match note.kind {
-                NoteKind::Click | NoteKind::Hold { .. } => &sfx_click,
-                NoteKind::Drag => &sfx_drag,
-                NoteKind::Flick => &sfx_flick,
-            },
-            volume_sfx,
-        )
+    if is_replay {
+        // sounds of the actual play, as judged while rendering
+        for (time, sound) in Judge::take_hit_sounds() {
+            place(
+                O + time as f64 + offset as f64,
+                match sound {
+                    HitSound::Click => &sfx_click,
+                    HitSound::Drag => &sfx_drag,
+                    HitSound::Flick => &sfx_flick,
+                },
+                volume_sfx,
+            )
+        }
+    } else {
+        for note in chart.lines.iter().flat_map(|it| it.notes.iter()).filter(|it| !it.fake) {
+            place(
+                O + note.time as f64 + offset as f64,
+                match note.kind {
+                    NoteKind::Click | NoteKind::Hold { .. } => &sfx_click,
+                    NoteKind::Drag => &sfx_drag,
+                    NoteKind::Flick => &sfx_flick,
+                },
+                volume_sfx,
+            )
+        }
     }
     place(O + length + A, &ending, volume_music);
+    let samples = {
+        let index = |time: f64| ((time * sample_rate as f64).round() as usize * 2).min(samples.len());
+        let mut samples = samples[index(range_start)..index(range_end)].to_vec();
+        let frame_count = samples.len() / 2;
+        let fade = ((v_config.fade * sample_rate as f64) as usize).min(frame_count / 2);
+        let fade_in = range_start > 0.;
+        let fade_out = range_end < video_length;
+        for (i, frame) in samples.chunks_exact_mut(2).enumerate() {
+            let mut volume = 1.;
+            if fade_in && i < fade {
+                volume = i as f32 / fade as f32;
+            }
+            if fade_out && frame_count - i <= fade {
+                volume = volume.min((frame_count - i - 1) as f32 / fade as f32);
+            }
+            frame[0] *= volume;
+            frame[1] *= volume;
+        }
+        samples
+    };
+    let (audio_dest, audio_args) = match format {
+        OutputFormat::Wav | OutputFormat::Flac => {
+            (Path::new(&output).to_path_buf(), format!("-c:a {} -f {}", format.audio_codec(), format.container()))
+        }
+        OutputFormat::Png => (Path::new(&output).join("audio.wav"), format!("-c:a {} -f wav", format.audio_codec())),
+        _ => (temp_audio.clone(), "-c:a pcm_f32le -f wav".to_owned()),
+    };
     let mut proc = Command::new(&ffmpeg)
-        .args("-y -f f32le -ar 44100 -ac 2 -i - -c:a mp3 t_audio.mp3".split_whitespace())
+        .args("-y -f f32le -ar 44100 -ac 2 -i -".split_whitespace())
+        .args(audio_args.split_whitespace())
+        .arg(audio_dest)
         .stdin(Stdio::piped())
         .stderr(Stdio::null())
         .spawn()
         .context("无法执行 ffmpeg")?;
     let input = proc.stdin.as_mut().unwrap();
     let mut writer = BufWriter::new(input);
-    for sample in output.into_iter() {
+    for sample in samples.into_iter() {
         writer.write_all(&sample.to_le_bytes())?;
     }
     drop(writer);
-    proc.wait()?;
-
-    info!("[2] 渲染视频…");
-    let mst = Rc::new(MSRenderTarget::new((vw, vh), config.sample_count));
-    let my_time: Rc<RefCell<f64>> = Rc::new(RefCell::new(0.));
-    let tm = TimeManager::manual(Box::new({
-        let my_time = Rc::clone(&my_time);
-        move || *(*my_time).borrow()
-    }));
-    let fs = Box::new(PatchedFileSystem(fs, edit.to_patches().await?));
-    static MSAA: AtomicBool = AtomicBool::new(false);
-    let mut main = Main::new(
-        Box::new(LoadingScene::new(GameMode::Normal, edit.info, config, fs, (None, None), Some(Rc::new(move || (vw, vh))), None).await?),
-        tm,
-        {
-            let mut cnt = 0;
-            let mst = Rc::clone(&mst);
-            move || {
-                cnt += 1;
-                if cnt == 1 || cnt == 3 {
-                    MSAA.store(true, Ordering::SeqCst);
-                    Some(mst.input())
-                } else {
-                    MSAA.store(false, Ordering::SeqCst);
-                    Some(mst.output())
-                }
-            }
-        },
-    )
-    .await?;
-    main.show_billboard = false;
-
-    const O: f64 = LoadingScene::TOTAL_TIME as f64 + GameScene::BEFORE_TIME as f64;
-    const A: f64 = 0.7 + 0.3 + 0.4;
-
-    let fps = v_config.fps;
-    let frame_delta = 1. / fps as f32;
-
-    let codecs = String::from_utf8(Command::new(&ffmpeg).arg("-codecs").output().context("无法执行 ffmpeg")?.stdout)?;
-    let use_cuda = v_config.hardware_accel && codecs.contains("h264_nvenc");
-    let has_qsv = v_config.hardware_accel && codecs.contains("h264_qsv");
-
-    let mut args = "-y -f rawvideo -c:v rawvideo".to_owned();
-    if use_cuda {
-        args += " -hwaccel_output_format cuda";
-    }
-    write!(
-        &mut args,
-        " -s {vw}x{vh} -r {fps} -pix_fmt rgb24 -i - -i t_audio.mp3 -c:a copy -c:v {} -map 0:v:0 -map 1:a:0 -qp 0 -vf vflip t_video.mp4",
-        if use_cuda {
-            "h264_nvenc"
-        } else if has_qsv {
-            "h264_qsv"
-        } else if v_config.hardware_accel {
-            bail!("不支持硬件加速！");
-        } else {
-            "libx264 -preset ultrafast"
-        },
-    )?;
-
-    let mut proc = Command::new(&ffmpeg)
-        .args(args.split_whitespace())
-        .stdin(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-        .context("无法执行 ffmpeg")?;
-    let mut input = proc.stdin.take().unwrap();
-
-    let mut bytes = vec![0; vw as usize * vh as usize * 3];
-
-    let frames = (video_length / frame_delta as f64).ceil() as u64;
-    let start_time = Instant::now();
-
-    for frame in 0..frames {
-        *my_time.borrow_mut() = (frame as f32 * frame_delta).max(0.) as f64;
-        gl.quad_gl.render_pass(Some(mst.output().render_pass));
-        clear_background(BLACK);
-        main.update()?;
-        main.render(&mut Ui::new(&mut painter))?;
-        // TODO magic. can't remove this line.
-        draw_rectangle(0., 0., 0., 0., Color::default());
-        gl.flush();
-
-        if MSAA.load(Ordering::SeqCst) {
-            mst.blit();
-        }
-        mst.output().texture.raw_miniquad_texture_handle().read_pixels(&mut bytes);
-        input.write_all(&bytes)?;
-        if frame % 100 == 0 {
-            info!("{frame} / {frames}, {:.2}fps", frame as f64 / start_time.elapsed().as_secs_f64());
-        }
+    check_ffmpeg(proc.wait()?)?;
+
+    if format.has_video() && !format.is_sequence() {
+        println!("[3] 合并 & 转码 & 压制");
+        check_ffmpeg(
+            Command::new(&ffmpeg)
+                .arg("-y")
+                .arg("-i")
+                .arg(&temp_video)
+                .arg("-i")
+                .arg(&temp_audio)
+                .args("-map 0:v:0 -map 1:a:0".split_whitespace())
+                .args(format.video_args(&v_config.bitrate).split_whitespace())
+                .args(["-c:a", format.audio_codec(), "-f", format.container()])
+                .arg(&output)
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .status()
+                .context("无法执行 ffmpeg")?,
+        )?;
     }
-    drop(input);
-    proc.wait()?;
-
-    info!("[3] 合并 & 转码 & 压制");
-    let _ = Command::new(&ffmpeg)
-        .args("-y -i t_video.mp4 -c:a copy -pix_fmt yuv420p -b:v".split_whitespace())
-        .arg(v_config.bitrate)
-        .arg("out.mp4")
-        .stdin(Stdio::null())
-        .stderr(Stdio::inherit())
-        .status()
-        .context("无法执行 ffmpeg")?;
 
-    info!("渲染完成！耗时：{:.2}s", render_start_time.elapsed().as_secs_f64());
+    println!("渲染完成！耗时：{:.2}s，输出至 {output}", render_start_time.elapsed().as_secs_f64());
     Ok(())
 }
 
 #[macroquad::main(build_conf)]
 async fn main() {
     if let Err(err) = the_main().await {
+        eprintln!("发生错误：{err:?}");
         let _ = std::fs::write("错误信息.txt", format!("发生错误：{err:?}"));
+        std::process::exit(1);
     }
 }
@@ -1,11 +1,12 @@
-use crate::{VideoConfig, INFO_EDIT, VIDEO_CONFIG};
-use anyhow::{bail, Result};
+use crate::{encoder::OutputFormat, parse_resolution, VideoConfig, INFO_EDIT, VIDEO_CONFIG};
+use anyhow::Result;
 use macroquad::prelude::*;
 use prpr::{
     config::Config,
     ext::{poll_future, screen_aspect, LocalTask},
     fs::{FileSystem, PatchedFileSystem},
     info::ChartInfo,
+    replay::Replay,
     scene::{show_error, show_message, GameMode, LoadingScene, NextScene, Scene},
     time::TimeManager,
     ui::{render_chart_info, ChartInfoEdit, Scroll, Ui},
@@ -20,12 +21,20 @@ pub struct MainScene {
     fs: Box<dyn FileSystem>,
     next_scene: Option<NextScene>,
     v_config: VideoConfig,
+    replay: Option<Replay>,
 
     loading_scene_task: LocalTask<Result<LoadingScene>>,
 }
 
 impl MainScene {
-    pub fn new(target: Option<RenderTarget>, info: ChartInfo, config: Config, fs: Box<dyn FileSystem>) -> Self {
+    pub fn new(
+        target: Option<RenderTarget>,
+        info: ChartInfo,
+        config: Config,
+        fs: Box<dyn FileSystem>,
+        replay: Option<Replay>,
+        v_config: VideoConfig,
+    ) -> Self {
         Self {
             target,
 
@@ -34,7 +43,8 @@ impl MainScene {
             config,
             fs,
             next_scene: None,
-            v_config: VideoConfig::default(),
+            v_config,
+            replay,
 
             loading_scene_task: None,
         }
@@ -83,17 +93,9 @@ impl Scene for MainScene {
                 let config = self.config.clone();
                 let fs = self.fs.clone_box();
                 let edit = self.edit.clone();
+                let mode = self.replay.clone().map_or(GameMode::Normal, GameMode::Replay);
                 self.loading_scene_task = Some(Box::pin(async move {
-                    LoadingScene::new(
-                        GameMode::Normal,
-                        info,
-                        config,
-                        Box::new(PatchedFileSystem(fs, edit.to_patches().await?)),
-                        (None, None),
-                        None,
-                        None,
-                    )
-                    .await
+                    LoadingScene::new(mode, info, config, Box::new(PatchedFileSystem(fs, edit.to_patches().await?)), (None, None), None, None).await
                 }));
             }
             r.x += dx;
@@ -115,13 +117,7 @@ impl Scene for MainScene {
                     let res = self.v_config.resolution;
                     let mut string = format!("{}x{}", res.0, res.1);
                     let r = ui.input("分辨率", &mut string, 0.8);
-                    match || -> Result<(u32, u32)> {
-                        if let Some((w, h)) = string.split_once(['x', 'X', '×', '*']) {
-                            Ok((w.parse::<u32>()?, h.parse::<u32>()?))
-                        } else {
-                            bail!("格式应当为 “宽x高”")
-                        }
-                    }() {
+                    match parse_resolution(&string) {
                         Err(_) => {
                             show_message("输入非法");
                         }
@@ -171,6 +167,22 @@ impl Scene for MainScene {
                     ui.dy(r.h + pad);
                     h += r.h + pad;
 
+                    let mut string = self.v_config.format.name().to_owned();
+                    let old = string.clone();
+                    let r = ui.input("格式", &mut string, 0.8);
+                    if string != old {
+                        match OutputFormat::from_name(&string) {
+                            None => {
+                                show_message("输入非法");
+                            }
+                            Some(value) => {
+                                self.v_config.format = value;
+                            }
+                        }
+                    }
+                    ui.dy(r.h + pad);
+                    h += r.h + pad;
+
                     let r = ui.checkbox("启用硬件加速", &mut self.v_config.hardware_accel);
                     ui.dy(r.h + pad);
                     h += r.h + pad;
@@ -5,3 +5,11 @@ upload-cancel = Cancel upload
 upload-retry = Retry
 
 still-uploading = Uploading result, please wait…
+
+timing-hint = Tap for timing details
+timing-back = Tap to go back
+timing-title = Hit Error
+timing-no-data = No timing data
+timing-stats = Mean { $mean }  SD { $sd }
+timing-suggested-offset = Suggested offset: { $offset } (confidence { $confidence })
+timing-timeline = Accuracy
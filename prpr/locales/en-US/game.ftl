@@ -5,5 +5,9 @@ offset-reset = Reset
 offset-save = Save
 
 ex-time-out-of-range = Time is out of range
-ex-invalid-format = Invalid format
+ex-invalid-format = Invalid format, use hh:mm:ss or beats like 32b
 ex-time-set = Time changed
+
+ex-speed = Speed
+ex-ramp-up = Speed up after clean loops
+ex-speed-up = Speed raised to { $speed }
@@ -5,3 +5,11 @@ upload-cancel = 取消上传
 upload-retry = 重试
 
 still-uploading = 尚在上传成绩
+
+timing-hint = 点击查看打击偏差
+timing-back = 点击返回
+timing-title = 打击偏差
+timing-no-data = 暂无数据
+timing-stats = 平均 { $mean }  标准差 { $sd }
+timing-suggested-offset = 建议延迟：{ $offset }（置信度 { $confidence }）
+timing-timeline = 准确率
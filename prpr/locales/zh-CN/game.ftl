@@ -5,5 +5,9 @@ offset-reset = 重置
 offset-save = 保存
 
 ex-time-out-of-range = 时间不在范围内
-ex-invalid-format = 格式有误
+ex-invalid-format = 格式有误，请使用 hh:mm:ss 或拍数（如 32b）
 ex-time-set = 设置成功
+
+ex-speed = 速度
+ex-ramp-up = 无失误后自动提速
+ex-speed-up = 速度提升至 { $speed }
@@ -0,0 +1,232 @@
+//! Automatic offset calibration.
+//!
+//! [`GameMode::Calibration`](crate::scene::GameMode::Calibration) plays a metronome chart generated here and estimates the
+//! offset from the timing of the taps. [`OffsetEstimate`] works on the timing errors of any play as well.
+
+use crate::{
+    config::Config,
+    core::{AnimFloat, BpmList, Chart, ChartExtra, ChartSettings, JudgeLine, JudgeLineCache, JudgeLineKind, Keyframe, Note, NoteKind, Object},
+    fs::{FileSystem, PatchedFileSystem},
+    info::ChartInfo,
+    judge::{JudgeStatus, TimingStats},
+};
+use std::{cell::RefCell, collections::HashMap, f32::consts::PI};
+
+const BPM: f32 = 120.;
+/// Beats before the first note, so that the player can catch the rhythm.
+const LEAD_IN: u32 = 4;
+const NOTES: u32 = 32;
+const NOTE_SPEED: f32 = 1.2;
+
+const MUSIC: &str = "calibration.wav";
+const SAMPLE_RATE: u32 = 44100;
+
+/// Fewer samples than this are not worth estimating from.
+const MIN_SAMPLES: usize = 8;
+/// The standard error at which the confidence drops to zero.
+const MAX_STD_ERROR: f32 = 0.02;
+
+#[derive(Clone, Copy, Debug)]
+pub struct OffsetEstimate {
+    /// The recommended value of [`Config::offset`].
+    pub offset: f32,
+    /// How much the estimate can be trusted, from 0 to 1.
+    pub confidence: f32,
+    /// Number of timing errors the estimate is based on, after rejecting outliers.
+    pub samples: usize,
+}
+
+impl OffsetEstimate {
+    /// Estimates from the timing errors (as in [`PlayResult::diffs`](crate::judge::PlayResult::diffs)) of a play with `config`.
+    ///
+    /// Taps far from the median (mistakes rather than latency) are ignored.
+    pub fn new(diffs: impl IntoIterator<Item = f32>, config: &Config) -> Option<Self> {
+        let mut diffs: Vec<_> = diffs.into_iter().collect();
+        if diffs.len() < MIN_SAMPLES {
+            return None;
+        }
+        let total = diffs.len();
+        let median = median(&mut diffs);
+        let mut deviations: Vec<_> = diffs.iter().map(|it| (it - median).abs()).collect();
+        // 1.4826 scales the median absolute deviation to the standard deviation of a normal distribution
+        let limit = (median(&mut deviations) * 1.4826 * 3.).max(0.01);
+        diffs.retain(|it| (it - median).abs() <= limit);
+
+        let stats = TimingStats::new(diffs);
+        let std_error = stats.std_dev / (stats.count as f32).sqrt();
+        Some(Self {
+            // a late hit means the notes arrive too early, which a larger offset delays
+            offset: config.offset + stats.mean * config.speed,
+            confidence: (1. - std_error / MAX_STD_ERROR).max(0.) * stats.count as f32 / total as f32,
+            samples: stats.count,
+        })
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.
+    } else {
+        values[mid]
+    }
+}
+
+fn beat_time(beat: u32) -> f32 {
+    beat as f32 * 60. / BPM
+}
+
+pub fn chart_info() -> ChartInfo {
+    ChartInfo {
+        name: "Calibration".to_owned(),
+        level: "Calibration".to_owned(),
+        charter: String::new(),
+        composer: String::new(),
+        illustrator: String::new(),
+        music: MUSIC.to_owned(),
+        illustration: String::new(),
+        ..Default::default()
+    }
+}
+
+/// Serves the metronome track of the chart, which is synthesized instead of loaded.
+pub fn patch_fs(fs: Box<dyn FileSystem>) -> Box<dyn FileSystem> {
+    Box::new(PatchedFileSystem(fs, HashMap::from([(MUSIC.to_owned(), metronome_wav())])))
+}
+
+/// A single line with a click note on every beat after the lead-in.
+pub fn chart() -> Chart {
+    let end = beat_time(LEAD_IN + NOTES) + 1.;
+    let mut notes: Vec<_> = (LEAD_IN..LEAD_IN + NOTES)
+        .map(|beat| {
+            let time = beat_time(beat);
+            Note {
+                object: Object::default(),
+                kind: NoteKind::Click,
+                time,
+                height: time * NOTE_SPEED,
+                speed: 1.,
+
+                above: true,
+                multiple_hint: false,
+                fake: false,
+                judge: JudgeStatus::NotJudged,
+            }
+        })
+        .collect();
+    let cache = JudgeLineCache::new(&mut notes);
+    let line = JudgeLine {
+        object: Object::default(),
+        ctrl_obj: RefCell::default(),
+        kind: JudgeLineKind::Normal,
+        height: AnimFloat::new(vec![Keyframe::new(0., 0., 2), Keyframe::new(end, end * NOTE_SPEED, 0)]),
+        incline: AnimFloat::default(),
+        notes,
+        color: Default::default(),
+        parent: None,
+        z_index: 0,
+        show_below: true,
+        attach_ui: None,
+        name: String::new(),
+        group: None,
+        links: Vec::new(),
+
+        cache,
+    };
+    Chart::new(0., vec![line], BpmList::new(vec![(0., BPM)]), ChartSettings::default(), ChartExtra::default())
+}
+
+/// Clicks on every beat (accented on the first of every four), as 16-bit mono WAV.
+fn metronome_wav() -> Vec<u8> {
+    const CLICK_LENGTH: f32 = 0.04;
+    let length = beat_time(LEAD_IN + NOTES) + 2.;
+    let mut samples = vec![0i16; (length * SAMPLE_RATE as f32) as usize];
+    for beat in 0..LEAD_IN + NOTES {
+        let freq = if beat % 4 == 0 { 1760. } else { 1320. };
+        let start = (beat_time(beat) * SAMPLE_RATE as f32) as usize;
+        for (i, sample) in samples[start..].iter_mut().take((CLICK_LENGTH * SAMPLE_RATE as f32) as usize).enumerate() {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let amp = (-t / CLICK_LENGTH * 5.).exp() * 0.6;
+            *sample = ((2. * PI * freq * t).sin() * amp * i16::MAX as f32) as i16;
+        }
+    }
+
+    let data_len = samples.len() as u32 * 2;
+    let mut res = Vec::with_capacity(44 + data_len as usize);
+    res.extend_from_slice(b"RIFF");
+    res.extend_from_slice(&(36 + data_len).to_le_bytes());
+    res.extend_from_slice(b"WAVEfmt ");
+    res.extend_from_slice(&16u32.to_le_bytes());
+    res.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    res.extend_from_slice(&1u16.to_le_bytes()); // mono
+    res.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    res.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    res.extend_from_slice(&2u16.to_le_bytes());
+    res.extend_from_slice(&16u16.to_le_bytes());
+    res.extend_from_slice(b"data");
+    res.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        res.extend_from_slice(&sample.to_le_bytes());
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(diffs: &[f32]) -> Option<OffsetEstimate> {
+        OffsetEstimate::new(diffs.iter().copied(), &Config::default())
+    }
+
+    /// `count` timing errors alternating around `mean`.
+    fn spread(mean: f32, dev: f32, count: usize) -> Vec<f32> {
+        (0..count).map(|i| if i % 2 == 0 { mean + dev } else { mean - dev }).collect()
+    }
+
+    #[test]
+    fn min_samples() {
+        assert!(estimate(&[0.01; MIN_SAMPLES - 1]).is_none());
+        let res = estimate(&[0.01; MIN_SAMPLES]).unwrap();
+        assert_eq!(res.samples, MIN_SAMPLES);
+        assert!(res.confidence > 0.999, "{}", res.confidence);
+    }
+
+    #[test]
+    fn outliers() {
+        let mut diffs = spread(0.03, 0.005, 10);
+        diffs.extend([0.5, -0.4]);
+        let res = estimate(&diffs).unwrap();
+        assert_eq!(res.samples, 10);
+        assert!((res.offset - 0.03).abs() < 1e-5, "{}", res.offset);
+        // rejected samples lower the confidence
+        assert!(res.confidence < 10. / 12. + 1e-5);
+    }
+
+    #[test]
+    fn sign() {
+        let config = Config {
+            offset: 0.1,
+            ..Default::default()
+        };
+        let late = OffsetEstimate::new([0.05; 10], &config).unwrap();
+        assert!((late.offset - 0.15).abs() < 1e-5, "{}", late.offset);
+        let early = OffsetEstimate::new([-0.05; 10], &config).unwrap();
+        assert!((early.offset - 0.05).abs() < 1e-5, "{}", early.offset);
+        let fast = Config { speed: 2., ..config };
+        let late = OffsetEstimate::new([0.05; 10], &fast).unwrap();
+        assert!((late.offset - 0.2).abs() < 1e-5, "{}", late.offset);
+    }
+
+    #[test]
+    fn confidence() {
+        // the standard error is dev / 4 with 16 samples
+        let half = estimate(&spread(0., MAX_STD_ERROR * 2., 16)).unwrap();
+        assert!((half.confidence - 0.5).abs() < 1e-3, "{}", half.confidence);
+        let edge = estimate(&spread(0., MAX_STD_ERROR * 4., 16)).unwrap();
+        assert!(edge.confidence < 1e-3, "{}", edge.confidence);
+        let beyond = estimate(&spread(0., MAX_STD_ERROR * 5., 16)).unwrap();
+        assert_eq!((beyond.samples, beyond.confidence), (16, 0.));
+    }
+}
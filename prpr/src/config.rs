@@ -1,3 +1,5 @@
+use crate::judge::{JudgeProfile, Scoring};
+use macroquad::prelude::KeyCode;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +16,180 @@ pub enum ChallengeModeColor {
     Rainbow,
 }
 
+/// Optional changes to how a chart is played.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+#[serde(rename_all = "camelCase")]
+pub struct Modifiers {
+    /// Fails the play on the first miss.
+    pub sudden_death: bool,
+    /// Fails the play on the first judgement other than perfect.
+    pub ap_challenge: bool,
+    /// Notes fade out as they approach the judge line.
+    pub hidden: bool,
+    /// Notes only fade in as they approach the judge line.
+    pub fade_in: bool,
+    /// Flips the chart horizontally.
+    pub mirror: bool,
+    /// Moves notes to the other side of their lines.
+    pub swap_sides: bool,
+    /// Places notes at random X positions, chosen with this seed.
+    pub random_seed: Option<u64>,
+}
+
+impl Modifiers {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn flags(&self) -> [(bool, &'static str); 7] {
+        [
+            (self.sudden_death, "SD"),
+            (self.ap_challenge, "AP"),
+            (self.hidden, "HD"),
+            (self.fade_in, "FI"),
+            (self.mirror, "MR"),
+            (self.swap_sides, "SW"),
+            (self.random_seed.is_some(), "RD"),
+        ]
+    }
+
+    /// Short names of the active modifiers.
+    pub fn tags(&self) -> impl Iterator<Item = &'static str> {
+        self.flags().into_iter().filter(|it| it.0).map(|it| it.1)
+    }
+
+    /// The active modifiers as bit flags. The random seed is not included, only whether there is one.
+    pub fn bits(&self) -> u8 {
+        self.flags().iter().enumerate().fold(0, |bits, (i, it)| bits | (it.0 as u8) << i)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let bit = |i: u8| bits & (1 << i) != 0;
+        Self {
+            sudden_death: bit(0),
+            ap_challenge: bit(1),
+            hidden: bit(2),
+            fade_in: bit(3),
+            mirror: bit(4),
+            swap_sides: bit(5),
+            random_seed: None,
+        }
+    }
+
+    /// Whether the judgement counts so far fail the play.
+    pub fn failed(&self, counts: [u32; 4]) -> bool {
+        (self.sudden_death && counts[3] != 0) || (self.ap_challenge && counts[1..].iter().any(|it| *it != 0))
+    }
+}
+
+#[rustfmt::skip]
+const KEY_NAMES: [(KeyCode, &str); 58] = {
+    use KeyCode::*;
+    [
+        (A, "A"), (B, "B"), (C, "C"), (D, "D"), (E, "E"), (F, "F"), (G, "G"), (H, "H"), (I, "I"), (J, "J"), (K, "K"), (L, "L"), (M, "M"),
+        (N, "N"), (O, "O"), (P, "P"), (Q, "Q"), (R, "R"), (S, "S"), (T, "T"), (U, "U"), (V, "V"), (W, "W"), (X, "X"), (Y, "Y"), (Z, "Z"),
+        (Key0, "0"), (Key1, "1"), (Key2, "2"), (Key3, "3"), (Key4, "4"), (Key5, "5"), (Key6, "6"), (Key7, "7"), (Key8, "8"), (Key9, "9"),
+        (Space, "Space"), (Comma, ","), (Period, "."), (Slash, "/"), (Semicolon, ";"), (Apostrophe, "'"), (LeftBracket, "["),
+        (RightBracket, "]"), (Backslash, "\\"), (Minus, "-"), (Equal, "="), (GraveAccent, "`"), (Enter, "Enter"), (Tab, "Tab"),
+        (Up, "Up"), (Down, "Down"), (Left, "Left"), (Right, "Right"), (LeftShift, "LeftShift"), (RightShift, "RightShift"),
+        (LeftControl, "LeftControl"), (RightControl, "RightControl"),
+    ]
+};
+
+/// Keys taken by the game scene (pausing, seeking and exiting), which can't be bound.
+pub const RESERVED_KEYS: [KeyCode; 4] = [KeyCode::Space, KeyCode::Left, KeyCode::Right, KeyCode::Q];
+
+/// Keys used to play, by name (case insensitive). See [`KeyBindings::key_code`] for the names.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBindings {
+    /// Ignores touches and the mouse, so that only keys judge notes.
+    pub keyboard_only: bool,
+    /// Keys that judge the earliest note, wherever it is.
+    pub tap: Vec<String>,
+    /// Keys of lanes, which split the screen into vertical strips of equal width from left to right. A key judges the
+    /// earliest note whose position on its line falls into the lane.
+    pub lanes: Vec<String>,
+    /// The key for flick notes. Without one, flicks are caught by holding any key.
+    pub flick: Option<String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keyboard_only: false,
+            tap: ["D", "F", "J", "K"].map(str::to_owned).to_vec(),
+            lanes: Vec::new(),
+            flick: Some("L".to_owned()),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Letters, digits, punctuation, arrows and modifiers by their names, e.g. `A`, `0`, `;`, `Up` or `LeftShift`.
+    pub fn key_code(name: &str) -> Option<KeyCode> {
+        let name = name.trim();
+        KEY_NAMES.iter().find(|it| it.1.eq_ignore_ascii_case(name)).map(|it| it.0)
+    }
+
+    pub fn key_name(code: KeyCode) -> Option<&'static str> {
+        KEY_NAMES.iter().find(|it| it.0 == code).map(|it| it.1)
+    }
+
+    /// Parses a comma separated list of keys, rejecting unknown and reserved ones.
+    pub fn parse_keys(text: &str) -> Option<Vec<String>> {
+        text.split(',')
+            .filter(|it| !it.trim().is_empty())
+            .map(|it| {
+                Self::key_code(it)
+                    .filter(|it| !RESERVED_KEYS.contains(it))
+                    .and_then(Self::key_name)
+                    .map(str::to_owned)
+            })
+            .collect()
+    }
+}
+
+/// Gamepad buttons used to play, by their names in `gilrs`, e.g. `South`, `LeftTrigger2` or `DPadUp`. Only read in builds with
+/// the `gamepad` feature. See [`KeyBindings`] for what each binding does.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+#[serde(rename_all = "camelCase")]
+pub struct GamepadBindings {
+    pub tap: Vec<String>,
+    pub lanes: Vec<String>,
+    pub flick: Option<String>,
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        Self {
+            tap: ["South", "East", "West", "North"].map(str::to_owned).to_vec(),
+            lanes: Vec::new(),
+            flick: Some("RightTrigger".to_owned()),
+        }
+    }
+}
+
+/// MIDI notes used to play, by note number. See [`KeyBindings`] for what each binding does.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+#[serde(rename_all = "camelCase")]
+pub struct MidiBindings {
+    /// Opens the first input port whose name contains this, or the first port if unset. Needs the `midi` feature.
+    pub port: Option<String>,
+    /// Creates a virtual input port named `prpr` for other programs to connect to instead (not on Windows).
+    #[serde(rename = "virtual")]
+    pub virtual_port: bool,
+    /// Reads note events from a recorded file instead of a port, see [`MidiFileSource`](crate::judge::input::MidiFileSource).
+    pub recording: Option<String>,
+    pub tap: Vec<u8>,
+    pub lanes: Vec<u8>,
+    pub flick: Option<u8>,
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 #[serde(default)]
 #[serde(rename_all = "camelCase")]
@@ -26,11 +202,20 @@ pub struct Config {
     pub challenge_color: ChallengeModeColor,
     pub challenge_rank: u32,
     pub debug: bool,
+    /// Line groups of RPE charts covered by the debug overlay, all lines if empty. Press `F3` while playing to cycle through them.
+    pub debug_groups: Vec<String>,
     pub disable_effect: bool,
     pub double_click_to_pause: bool,
     pub fix_aspect_ratio: bool,
     pub fxaa: bool,
+    /// Plays with gamepads when set.
+    pub gamepad: Option<GamepadBindings>,
     pub interactive: bool,
+    pub judge_profile: JudgeProfile,
+    pub key_bindings: KeyBindings,
+    /// Plays with a MIDI device when set.
+    pub midi: Option<MidiBindings>,
+    pub modifiers: Modifiers,
     pub multiple_hint: bool,
     pub note_scale: f32,
     pub offset: f32,
@@ -38,8 +223,12 @@ pub struct Config {
     pub player_name: String,
     pub player_rks: f32,
     pub sample_count: u32,
+    pub replay_path: Option<String>,
     pub res_pack_path: Option<String>,
+    pub scoring: Scoring,
     pub speed: f32,
+    /// Where to save the judge trace of each play, see [`JudgeDebug`](crate::judge::JudgeDebug). Only recorded with `debug` on.
+    pub trace_path: Option<String>,
     pub volume_music: f32,
     pub volume_sfx: f32,
 }
@@ -55,22 +244,38 @@ impl Default for Config {
             challenge_color: ChallengeModeColor::Golden,
             challenge_rank: 45,
             debug: false,
+            debug_groups: Vec::new(),
             disable_effect: false,
             double_click_to_pause: true,
             fix_aspect_ratio: false,
             fxaa: false,
+            gamepad: None,
             interactive: true,
+            judge_profile: JudgeProfile::default(),
+            key_bindings: KeyBindings::default(),
+            midi: None,
+            modifiers: Modifiers::default(),
             multiple_hint: true,
             note_scale: 1.0,
             offset: 0.,
+            replay_path: None,
             res_pack_path: None,
             particle: true,
             player_name: "Mivik".to_string(),
             player_rks: 15.,
             sample_count: 4,
+            scoring: Scoring::default(),
             speed: 1.,
+            trace_path: None,
             volume_music: 1.,
             volume_sfx: 1.,
         }
     }
 }
+
+impl Config {
+    /// Whether the debug overlay is shown for a line in `group`.
+    pub fn debug_line(&self, group: Option<&str>) -> bool {
+        self.debug && (self.debug_groups.is_empty() || group.map_or(false, |group| self.debug_groups.iter().any(|it| it == group)))
+    }
+}
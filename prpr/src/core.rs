@@ -13,16 +13,19 @@ pub type Vector = nalgebra::Vector2<f32>;
 pub type Matrix = nalgebra::Matrix3<f32>;
 
 mod anim;
-pub use anim::{Anim, AnimFloat, AnimVector, Keyframe};
+pub use anim::{Anim, AnimExpr, AnimFloat, AnimVector, Keyframe};
 
 mod chart;
-pub use chart::{Chart, ChartExtra, ChartSettings};
+pub use chart::{Chart, ChartExtra, ChartSettings, LineExprs};
 
 mod effect;
-pub use effect::{Effect, Uniform};
+pub use effect::{Effect, ExprUniform, PreviousFrameUniform, TextureUniform, Uniform};
+
+mod expr;
+pub use expr::{Expr, ExprVars};
 
 mod line;
-pub use line::{JudgeLine, JudgeLineCache, JudgeLineKind, UIElement};
+pub use line::{EventLink, JudgeLine, JudgeLineCache, JudgeLineKind, UIElement};
 
 mod note;
 use macroquad::prelude::set_pc_assets_folder;
@@ -32,13 +35,16 @@ mod object;
 pub use object::{CtrlObject, Object};
 
 mod render;
-pub use render::{copy_fbo, MSRenderTarget};
+pub use render::{copy_fbo, FrameTarget, LayerTarget, MSRenderTarget};
 
 mod resource;
 pub use resource::{ParticleEmitter, Resource, ResourcePack, DPI_VALUE};
 
 mod tween;
-pub use tween::{easing_from, BezierTween, ClampedTween, StaticTween, TweenFunction, TweenId, TweenMajor, TweenMinor, Tweenable, TWEEN_FUNCTIONS};
+pub use tween::{
+    easing_from, BezierTween, ClampedCustomTween, ClampedTween, EasingRegistry, PiecewiseTween, StaticTween, StepTween, TweenFunction, TweenId,
+    TweenMajor, TweenMinor, Tweenable, TWEEN_FUNCTIONS,
+};
 
 mod video;
 pub use video::Video;
@@ -55,7 +61,7 @@ pub fn init_assets() {
     set_pc_assets_folder("assets");
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct Triple(i32, u32, u32);
 impl Default for Triple {
     fn default() -> Self {
@@ -67,9 +73,26 @@ impl Triple {
     pub fn beats(&self) -> f32 {
         self.0 as f32 + self.1 as f32 / self.2 as f32
     }
+
+    pub fn from_beats(beats: f32) -> Self {
+        const DENOMINATORS: [u32; 12] = [1, 2, 3, 4, 6, 8, 12, 16, 24, 32, 48, 64];
+        const MAX_DENOMINATOR: u32 = 10000;
+        let int = beats.floor();
+        let frac = beats - int;
+        let den = DENOMINATORS
+            .into_iter()
+            .find(|den| (frac * *den as f32 - (frac * *den as f32).round()).abs() < 1e-3)
+            .unwrap_or(MAX_DENOMINATOR);
+        let num = (frac * den as f32).round() as u32;
+        if num == den {
+            Self(int as i32 + 1, 0, 1)
+        } else {
+            Self(int as i32, num, den)
+        }
+    }
 }
 
-#[derive(Default)] // the default is a dummy
+#[derive(Clone, Default)] // the default is a dummy
 pub struct BpmList {
     elements: Vec<(f32, f32, f32)>, // (beats, time, bpm)
     cursor: usize,
@@ -106,6 +129,15 @@ impl BpmList {
         time + (beats - start_beats) * (60. / bpm)
     }
 
+    /// The time mapping of a line whose BPM is divided by `factor`.
+    pub fn with_factor(&self, factor: f32) -> Self {
+        Self::new(self.ranges().map(|(beats, bpm)| (beats, bpm / factor)).collect())
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.elements.iter().map(|(beats, _, bpm)| (*beats, *bpm))
+    }
+
     pub fn time(&mut self, triple: &Triple) -> f32 {
         self.time_beats(triple.beats())
     }
@@ -1,4 +1,4 @@
-use super::{StaticTween, TweenFunction, TweenId, Tweenable, Vector};
+use super::{BpmList, Expr, ExprVars, StaticTween, TweenFunction, TweenId, Tweenable, Vector};
 use std::rc::Rc;
 
 #[derive(Clone)]
@@ -18,12 +18,34 @@ impl<T> Keyframe<T> {
     }
 }
 
+/// An expression of time and beat driving an [`Anim`] in place of keyframes, see [`AnimFloat::expr`].
+#[derive(Clone)]
+pub struct AnimExpr {
+    expr: Rc<Expr>,
+    bpm: BpmList,
+    /// Multiplies the value of the expression, see [`AnimFloat::scale`].
+    factor: f32,
+    value: f32,
+}
+
+impl AnimExpr {
+    fn set_time(&mut self, time: f32) {
+        let vars = ExprVars {
+            time,
+            beat: self.bpm.beat(time),
+            t: 0.,
+        };
+        self.value = self.expr.eval(&vars) * self.factor;
+    }
+}
+
 #[derive(Clone)]
 pub struct Anim<T: Tweenable> {
     pub time: f32,
     pub keyframes: Box<[Keyframe<T>]>,
     pub cursor: usize,
     pub next: Option<Box<Anim<T>>>,
+    pub expr: Option<Box<AnimExpr>>,
 }
 
 impl<T: Tweenable> Default for Anim<T> {
@@ -33,6 +55,7 @@ impl<T: Tweenable> Default for Anim<T> {
             keyframes: [].into(),
             cursor: 0,
             next: None,
+            expr: None,
         }
     }
 }
@@ -47,6 +70,7 @@ impl<T: Tweenable> Anim<T> {
             time: 0.0,
             cursor: 0,
             next: None,
+            expr: None,
         }
     }
 
@@ -56,11 +80,12 @@ impl<T: Tweenable> Anim<T> {
             time: 0.0,
             cursor: 0,
             next: None,
+            expr: None,
         }
     }
 
     pub fn is_default(&self) -> bool {
-        self.keyframes.is_empty() && self.next.is_none()
+        self.keyframes.is_empty() && self.next.is_none() && self.expr.is_none()
     }
 
     pub fn chain(elements: Vec<Anim<T>>) -> Self {
@@ -77,10 +102,13 @@ impl<T: Tweenable> Anim<T> {
     }
 
     pub fn dead(&self) -> bool {
-        self.cursor + 1 >= self.keyframes.len()
+        self.expr.is_none() && self.cursor + 1 >= self.keyframes.len()
     }
 
     pub fn set_time(&mut self, time: f32) {
+        if let Some(expr) = &mut self.expr {
+            expr.set_time(time);
+        }
         if self.keyframes.is_empty() || time == self.time {
             self.time = time;
             return;
@@ -101,6 +129,9 @@ impl<T: Tweenable> Anim<T> {
     }
 
     fn now_opt_inner(&self) -> Option<T> {
+        if let Some(expr) = &self.expr {
+            return Some(T::from_f32(expr.value));
+        }
         if self.keyframes.is_empty() {
             return None;
         }
@@ -140,6 +171,34 @@ impl<T: Tweenable + Default> Anim<T> {
 }
 
 pub type AnimFloat = Anim<f32>;
+
+impl AnimFloat {
+    /// An animation following `expr`, with `time` being the chart time and `beat` its beat in `bpm`. `t` is always 0.
+    pub fn expr(expr: Expr, bpm: BpmList) -> Self {
+        Self {
+            expr: Some(Box::new(AnimExpr {
+                expr: Rc::new(expr),
+                bpm,
+                factor: 1.,
+                value: 0.,
+            })),
+            ..Self::default()
+        }
+    }
+
+    /// Multiplies the values, including those of expressions unlike [`Anim::map_value`].
+    pub fn scale(&mut self, factor: f32) {
+        self.keyframes.iter_mut().for_each(|it| it.value *= factor);
+        if let Some(expr) = &mut self.expr {
+            expr.factor *= factor;
+            expr.value *= factor;
+        }
+        if let Some(next) = &mut self.next {
+            next.scale(factor);
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct AnimVector(pub AnimFloat, pub AnimFloat);
 
@@ -161,3 +220,38 @@ impl AnimVector {
         Vector::new(self.0.now_opt().unwrap_or(x), self.1.now_opt().unwrap_or(y))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expr(source: &str) -> AnimFloat {
+        AnimFloat::expr(Expr::parse(source).unwrap(), BpmList::new(vec![(0., 120.)]))
+    }
+
+    #[test]
+    fn expr_anim() {
+        let mut anim = expr("time * 2 + beat");
+        assert!(!anim.is_default());
+        assert!(!anim.dead());
+        anim.set_time(1.5);
+        assert!((anim.now() - 6.).abs() < 1e-5);
+        anim.set_time(0.5);
+        assert!((anim.now() - 2.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn expr_scale() {
+        let mut anim = expr("sin(beat * pi / 4)");
+        anim.set_time(1.);
+        anim.scale(-2.);
+        assert!((anim.now() + 2.).abs() < 1e-5);
+        anim.set_time(0.5);
+        assert!((anim.now() + 2f32.sqrt()).abs() < 1e-5);
+
+        let mut anim = AnimFloat::new(vec![Keyframe::new(0., 1., 2), Keyframe::new(1., 3., 0)]);
+        anim.scale(-1.);
+        anim.set_time(1.);
+        assert_eq!(anim.now(), -3.);
+    }
+}
@@ -1,13 +1,47 @@
-use super::{BpmList, Effect, JudgeLine, Matrix, Resource, UIElement, Vector, Video};
-use crate::{judge::JudgeStatus, ui::Ui};
+use super::{AnimFloat, BpmList, EasingRegistry, Effect, JudgeLine, LayerTarget, Matrix, Object, Resource, UIElement, Vector, Video};
+use crate::{ext::get_viewport, judge::JudgeStatus, ui::Ui};
 use macroquad::prelude::*;
-use std::cell::RefCell;
+use std::{cell::RefCell, collections::HashMap};
 
 #[derive(Default)]
 pub struct ChartExtra {
+    /// Effects on the chart, or on a single line if they have a [`Effect::target`].
     pub effects: Vec<Effect>,
     pub global_effects: Vec<Effect>,
     pub videos: Vec<Video>,
+    /// Custom easings, which RPE events can refer to by name.
+    pub easings: EasingRegistry,
+    /// Judge line properties driven by expressions, by line index.
+    pub lines: HashMap<usize, LineExprs>,
+}
+
+/// Properties of a judge line driven by expressions, see [`AnimFloat::expr`]. They replace the keyframes of the chart.
+#[derive(Default)]
+pub struct LineExprs {
+    pub alpha: Option<AnimFloat>,
+    pub x: Option<AnimFloat>,
+    pub y: Option<AnimFloat>,
+    pub rotation: Option<AnimFloat>,
+    pub scale_x: Option<AnimFloat>,
+    pub scale_y: Option<AnimFloat>,
+}
+
+impl LineExprs {
+    pub fn apply(self, object: &mut Object) {
+        let props = [
+            (self.alpha, &mut object.alpha),
+            (self.x, &mut object.translation.0),
+            (self.y, &mut object.translation.1),
+            (self.rotation, &mut object.rotation),
+            (self.scale_x, &mut object.scale.0),
+            (self.scale_y, &mut object.scale.1),
+        ];
+        for (anim, prop) in props {
+            if let Some(anim) = anim {
+                *prop = anim;
+            }
+        }
+    }
 }
 
 #[derive(Default)]
@@ -96,6 +130,46 @@ impl Chart {
         }
     }
 
+    /// Renders a line with its effects onto [`Resource::layer_target`], then draws the result onto the chart.
+    fn render_layer(&self, ui: &mut Ui, res: &mut Resource, bpm_list: &mut BpmList, id: usize, effects: &[&Effect]) {
+        let mut gl = unsafe { get_internal_gl() };
+        // notes are batched, so those of previous lines have to be drawn first
+        res.note_buffer.borrow_mut().draw_all();
+        gl.flush();
+        let vp = get_viewport();
+        let dim = (vp.2 as u32, vp.3 as u32);
+        if res.layer_target.as_ref().map_or(true, |it| it.dim() != dim) {
+            res.layer_target = Some(LayerTarget::new(dim));
+        }
+        let old_pass = gl.quad_gl.get_active_render_pass();
+        gl.quad_gl.render_pass(Some(res.layer_target.as_ref().unwrap().output().render_pass));
+        gl.quad_gl.viewport(None);
+        clear_background(Color::default());
+        self.lines[id].render(ui, res, &self.lines, bpm_list, &self.settings, id);
+        res.note_buffer.borrow_mut().draw_all();
+        gl.flush();
+
+        let top = 1. / res.aspect_ratio;
+        let layer = res.layer_target.as_mut().unwrap();
+        for effect in effects {
+            layer.swap();
+            effect.draw(layer.old().texture, layer.output().render_pass, vec2(1., 1.), top);
+            gl.flush();
+        }
+        gl.quad_gl.render_pass(old_pass);
+        gl.quad_gl.viewport(Some(vp));
+        draw_texture_ex(
+            layer.output().texture,
+            -1.,
+            -top,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(2., top * 2.)),
+                ..Default::default()
+            },
+        );
+    }
+
     pub fn render(&self, ui: &mut Ui, res: &mut Resource) {
         for video in &self.extra.videos {
             video.render(res);
@@ -103,7 +177,16 @@ impl Chart {
         res.apply_model_of(&Matrix::identity().append_nonuniform_scaling(&Vector::new(1.0, -1.0)), |res| {
             let mut guard = self.bpm_list.borrow_mut();
             for id in &self.order {
-                self.lines[*id].render(ui, res, &self.lines, &mut guard, &self.settings, *id);
+                let effects: Vec<_> = if res.no_effect {
+                    Vec::new()
+                } else {
+                    self.extra.effects.iter().filter(|it| it.target == Some(*id) && it.is_active()).collect()
+                };
+                if effects.is_empty() {
+                    self.lines[*id].render(ui, res, &self.lines, &mut guard, &self.settings, *id);
+                } else {
+                    self.render_layer(ui, res, &mut guard, *id, &effects);
+                }
             }
             drop(guard);
             res.note_buffer.borrow_mut().draw_all();
@@ -114,7 +197,7 @@ impl Chart {
                 }
             }
             if !res.no_effect {
-                for effect in &self.extra.effects {
+                for effect in self.extra.effects.iter().filter(|it| it.target.is_none()) {
                     effect.render(res);
                 }
             }
@@ -1,25 +1,32 @@
-use crate::ext::{get_viewport, screen_aspect};
+use crate::ext::{get_viewport, screen_aspect, SafeTexture};
 
-use super::{Anim, Resource, Tweenable};
+use super::{Anim, BpmList, Expr, ExprVars, Resource, Tweenable};
 use anyhow::{anyhow, bail, Result};
 use macroquad::prelude::*;
-use miniquad::UniformType;
+use miniquad::{RenderPass, UniformType};
 use once_cell::sync::Lazy;
 use phf::phf_map;
 use regex::Regex;
 use std::{collections::HashSet, ops::Range};
 
 static SHADERS: phf::Map<&'static str, &'static str> = phf_map! {
+    "bloom" => include_str!("shaders/bloom.glsl"),
     "chromatic" => include_str!("shaders/chromatic.glsl"),
     "circleBlur" => include_str!("shaders/circle_blur.glsl"),
     "fisheye" => include_str!("shaders/fisheye.glsl"),
     "glitch" => include_str!("shaders/glitch.glsl"),
     "grayscale" => include_str!("shaders/grayscale.glsl"),
+    "heatHaze" => include_str!("shaders/heat_haze.glsl"),
+    "kaleidoscope" => include_str!("shaders/kaleidoscope.glsl"),
+    "lut" => include_str!("shaders/lut.glsl"),
     "noise" => include_str!("shaders/noise.glsl"),
     "pixel" => include_str!("shaders/pixel.glsl"),
     "radialBlur" => include_str!("shaders/radial_blur.glsl"),
+    "rgbSplit" => include_str!("shaders/rgb_split.glsl"),
+    "scanlines" => include_str!("shaders/scanlines.glsl"),
     "shockwave" => include_str!("shaders/shockwave.glsl"),
     "vignette" => include_str!("shaders/vignette.glsl"),
+    "wave" => include_str!("shaders/wave.glsl"),
 };
 
 pub trait UniformValue: Clone + Default {
@@ -34,6 +41,15 @@ impl UniformValue for Vec2 {
     const UNIFORM_TYPE: UniformType = UniformType::Float2;
 }
 
+impl UniformValue for Vec3 {
+    const UNIFORM_TYPE: UniformType = UniformType::Float3;
+}
+
+/// Also used for `bool`s.
+impl UniformValue for i32 {
+    const UNIFORM_TYPE: UniformType = UniformType::Int1;
+}
+
 impl UniformValue for Color {
     const UNIFORM_TYPE: UniformType = UniformType::Float4;
 }
@@ -42,6 +58,57 @@ pub trait Uniform {
     fn uniform_pair(&self) -> (String, UniformType);
     fn set_time(&mut self, t: f32);
     fn apply(&self, material: &Material);
+
+    /// The name of the sampler if this is a texture, which is bound as one instead of being declared as a uniform.
+    fn sampler(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether this is a sampler of the previous frame, which [`Effect`] binds itself.
+    fn previous_frame(&self) -> bool {
+        false
+    }
+}
+
+/// A `sampler2D` bound to an image, e.g. one loaded from the chart.
+pub struct TextureUniform(pub String, pub SafeTexture);
+
+impl Uniform for TextureUniform {
+    fn uniform_pair(&self) -> (String, UniformType) {
+        // samplers are ints in GLSL, though this is never declared, see `sampler`
+        (self.0.clone(), UniformType::Int1)
+    }
+
+    fn set_time(&mut self, _t: f32) {}
+
+    fn apply(&self, material: &Material) {
+        material.set_texture(&self.0, *self.1);
+    }
+
+    fn sampler(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+}
+
+/// A `sampler2D` bound to the last frame of the chart, after effects. It's the current one in the first frame.
+pub struct PreviousFrameUniform(pub String);
+
+impl Uniform for PreviousFrameUniform {
+    fn uniform_pair(&self) -> (String, UniformType) {
+        (self.0.clone(), UniformType::Int1)
+    }
+
+    fn set_time(&mut self, _t: f32) {}
+
+    fn apply(&self, _material: &Material) {}
+
+    fn sampler(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+
+    fn previous_frame(&self) -> bool {
+        true
+    }
 }
 
 impl<T: UniformValue> Uniform for (String, T) {
@@ -70,13 +137,78 @@ impl<T: UniformValue + Tweenable> Uniform for (String, Anim<T>) {
     }
 }
 
+/// A uniform driven by expressions, one for a `float`, two for a `vec2`, three for a `vec3` and four for a `vec4`.
+pub struct ExprUniform {
+    name: String,
+    exprs: Vec<Expr>,
+    bpm: BpmList,
+    /// The time range of the effect, which `t` is the progress of.
+    range: Range<f32>,
+    values: [f32; 4],
+}
+
+impl ExprUniform {
+    pub fn new(name: String, exprs: Vec<Expr>, bpm: BpmList, range: Range<f32>) -> Result<Self> {
+        if !(1..=4).contains(&exprs.len()) {
+            bail!("Expected 1 to 4 expressions, got {}", exprs.len());
+        }
+        Ok(Self {
+            name,
+            exprs,
+            bpm,
+            range,
+            values: [0.; 4],
+        })
+    }
+}
+
+impl Uniform for ExprUniform {
+    fn uniform_pair(&self) -> (String, UniformType) {
+        (
+            self.name.clone(),
+            match self.exprs.len() {
+                1 => UniformType::Float1,
+                2 => UniformType::Float2,
+                3 => UniformType::Float3,
+                _ => UniformType::Float4,
+            },
+        )
+    }
+
+    fn set_time(&mut self, t: f32) {
+        let len = self.range.end - self.range.start;
+        let vars = ExprVars {
+            time: t,
+            beat: self.bpm.beat(t),
+            t: if len > 0. { (t - self.range.start) / len } else { 0. },
+        };
+        for (value, expr) in self.values.iter_mut().zip(&self.exprs) {
+            *value = expr.eval(&vars);
+        }
+    }
+
+    fn apply(&self, material: &Material) {
+        let [x, y, z, w] = self.values;
+        match self.exprs.len() {
+            1 => material.set_uniform(&self.name, x),
+            2 => material.set_uniform(&self.name, vec2(x, y)),
+            3 => material.set_uniform(&self.name, vec3(x, y, z)),
+            _ => material.set_uniform(&self.name, Color::new(x, y, z, w)),
+        }
+    }
+}
+
 pub struct Effect {
     time_range: Range<f32>,
     t: f32,
     material: Material,
     defaults: Vec<Box<dyn Uniform>>,
     uniforms: Vec<Box<dyn Uniform>>,
+    previous_frame: Option<Texture2D>,
     pub global: bool,
+    /// The judge line (along with its notes) this effect applies to, instead of the whole chart. The line is drawn onto a
+    /// [`LayerTarget`](super::LayerTarget) which the effect runs on, and the result is drawn back onto the chart.
+    pub target: Option<usize>,
 }
 
 impl Effect {
@@ -84,8 +216,21 @@ impl Effect {
         SHADERS.get(name).copied()
     }
 
+    /// The type `name` is declared as in `shader`, e.g. `float`.
+    pub fn uniform_type<'a>(shader: &'a str, name: &str) -> Option<&'a str> {
+        static UNIFORM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"uniform\s+(?:\w+\s+)?(\w+)\s+(\w+)\s*;").unwrap());
+        UNIFORM_REGEX
+            .captures_iter(shader)
+            .find(|caps| &caps[2] == name)
+            .map(|caps| caps.get(1).unwrap().as_str())
+    }
+
     pub fn new(time_range: Range<f32>, shader: &str, uniforms: Vec<Box<dyn Uniform>>, global: bool) -> Result<Self> {
         static DEF_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"uniform\s+(\w+)\s+(\w+);\s+//\s+%([^%]+)%").unwrap());
+        fn floats<const N: usize>(value: &str) -> Result<[f32; N]> {
+            let values = value.split(',').map(|it| Ok(it.trim().parse()?)).collect::<Result<Vec<f32>>>()?;
+            values.try_into().map_err(|_| anyhow!("Expected {N} comma-separated values"))
+        }
         let defaults = DEF_REGEX
             .captures_iter(shader)
             .map(|caps| -> Result<Box<dyn Uniform>> {
@@ -94,17 +239,11 @@ impl Effect {
                 let value = caps.get(3).unwrap().as_str();
                 Ok(match type_name {
                     "float" => Box::new((name, value.parse::<f32>()?)),
-                    "vec2" => Box::new((name, {
-                        let (x, y) = value.split_once(',').ok_or_else(|| anyhow!("Expected x,y"))?;
-                        vec2(x.trim().parse()?, y.trim().parse()?)
-                    })),
-                    "vec4" => Box::new((name, {
-                        let values: Vec<_> = value.split(',').map(|it| it.trim()).collect();
-                        if values.len() != 4 {
-                            bail!("Expected r,g,b,a");
-                        }
-                        Color::new(values[0].parse()?, values[1].parse()?, values[2].parse()?, values[3].parse()?)
-                    })),
+                    "int" => Box::new((name, value.trim().parse::<i32>()?)),
+                    "bool" => Box::new((name, value.trim().parse::<bool>()? as i32)),
+                    "vec2" => Box::new((name, Vec2::from(floats::<2>(value)?))),
+                    "vec3" => Box::new((name, Vec3::from(floats::<3>(value)?))),
+                    "vec4" => Box::new((name, Color::from(floats::<4>(value)?))),
                     _ => bail!("Unknown type: {type_name}"),
                 })
             })
@@ -122,8 +261,20 @@ impl Effect {
         add_uniform(("time".to_owned(), UniformType::Float1));
         add_uniform(("screenSize".to_owned(), UniformType::Float2));
         add_uniform(("UVScale".to_owned(), UniformType::Float2));
+        let mut textures = vec!["screenTexture".to_owned()];
         for u in &uniforms {
-            add_uniform(u.uniform_pair());
+            if let Some(name) = u.sampler() {
+                textures.push(name.to_owned());
+            } else {
+                add_uniform(u.uniform_pair());
+            }
+        }
+        static SAMPLER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"uniform\s+sampler2D\s+(\w+);").unwrap());
+        for caps in SAMPLER_REGEX.captures_iter(shader) {
+            let name = caps.get(1).unwrap().as_str();
+            if !textures.iter().any(|it| it == name) {
+                bail!("Texture {name} is not set");
+            }
         }
         Ok(Self {
             time_range,
@@ -134,18 +285,21 @@ impl Effect {
                 shader,
                 MaterialParams {
                     uniforms: new_uniforms,
-                    textures: vec!["screenTexture".to_owned()],
+                    textures,
                     ..Default::default()
                 },
             )?,
             uniforms,
+            previous_frame: None,
             global,
+            target: None,
         })
     }
 
     pub fn update(&mut self, res: &Resource) {
         let t = res.time;
         self.t = t;
+        self.previous_frame = res.previous_frame.as_ref().map(|it| it.texture());
         if self.time_range.contains(&t) {
             for uniform in &mut self.uniforms {
                 uniform.set_time(t);
@@ -153,33 +307,49 @@ impl Effect {
         }
     }
 
+    /// Whether a sampler is bound to the previous frame, which has to be kept then, see [`Resource::keep_frame`].
+    pub fn uses_previous_frame(&self) -> bool {
+        self.uniforms.iter().any(|it| it.previous_frame())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.time_range.contains(&self.t)
+    }
+
     pub fn render(&self, res: &mut Resource) {
-        if !self.time_range.contains(&self.t) {
+        if !self.is_active() {
             return;
         }
-        let mut gl = unsafe { get_internal_gl() };
-        gl.flush();
+        unsafe { get_internal_gl() }.flush();
+        let target = res.chart_target.as_mut().unwrap();
+        target.swap();
+        let tex = target.old().texture;
+        let vp = get_viewport();
+        let uv_scale = vec2(vp.2 as _, vp.3 as _) / vec2(tex.width(), tex.height());
+        let top = 1. / if self.global { screen_aspect() } else { res.aspect_ratio };
+        self.draw(tex, target.output().render_pass, uv_scale, top);
+    }
 
+    /// Draws `texture` through the shader onto `pass`, covering the chart (or the screen if `top` is that of the screen).
+    pub(crate) fn draw(&self, texture: Texture2D, pass: RenderPass, uv_scale: Vec2, top: f32) {
+        let mut gl = unsafe { get_internal_gl() };
         for def in &self.defaults {
             def.apply(&self.material);
         }
         for uniform in &self.uniforms {
             uniform.apply(&self.material);
+            if uniform.previous_frame() {
+                self.material
+                    .set_texture(uniform.sampler().unwrap(), self.previous_frame.unwrap_or(texture));
+            }
         }
         self.material.set_uniform("time", self.t);
-        let target = res.chart_target.as_mut().unwrap();
-        target.swap();
-        let tex = target.old().texture;
-        self.material.set_texture("screenTexture", tex);
-        let screen_dim = vec2(tex.width(), tex.height());
-        self.material.set_uniform("screenSize", screen_dim);
-        gl.quad_gl.render_pass(Some(target.output().render_pass));
-
-        let vp = get_viewport();
-        self.material.set_uniform("UVScale", vec2(vp.2 as _, vp.3 as _) / screen_dim);
+        self.material.set_texture("screenTexture", texture);
+        self.material.set_uniform("screenSize", vec2(texture.width(), texture.height()));
+        gl.quad_gl.render_pass(Some(pass));
+        self.material.set_uniform("UVScale", uv_scale);
 
         gl_use_material(self.material);
-        let top = 1. / if self.global { screen_aspect() } else { res.aspect_ratio };
         draw_rectangle(-1., -top, 2., top * 2., WHITE);
         gl_use_default_material();
     }
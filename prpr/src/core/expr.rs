@@ -0,0 +1,393 @@
+//! Math expressions of time, evaluated every frame, e.g. `sin(beat * pi) * 0.02`.
+//!
+//! Expressions have numbers, the variables `time` (chart time in seconds), `beat` and `t` (progress of the effect from 0 to
+//! 1), the constants `pi`, `tau` and `e`, the operators `+ - * / % ^` and parentheses, and the functions below. There are no
+//! loops nor side effects, and nesting is limited, so evaluation always finishes quickly.
+//!
+//! Shader effect variables (see [`ExprUniform`](super::ExprUniform)) and judge line properties (see
+//! [`AnimFloat::expr`](super::AnimFloat::expr)) can be expressions.
+
+use anyhow::{bail, Result};
+
+/// Maximum nesting of parentheses, calls and operators.
+const MAX_DEPTH: usize = 32;
+const MAX_LENGTH: usize = 1024;
+
+/// The values of the variables.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExprVars {
+    pub time: f32,
+    pub beat: f32,
+    pub t: f32,
+}
+
+#[derive(Clone, Copy)]
+enum Var {
+    Time,
+    Beat,
+    T,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Const(f32),
+    Var(Var),
+    Unary(fn(f32) -> f32),
+    Binary(fn(f32, f32) -> f32),
+    Ternary(fn(f32, f32, f32) -> f32),
+}
+
+enum Func {
+    Unary(fn(f32) -> f32),
+    Binary(fn(f32, f32) -> f32),
+    Ternary(fn(f32, f32, f32) -> f32),
+}
+
+fn func(name: &str) -> Option<Func> {
+    use Func::*;
+    Some(match name {
+        "sin" => Unary(f32::sin),
+        "cos" => Unary(f32::cos),
+        "tan" => Unary(f32::tan),
+        "asin" => Unary(f32::asin),
+        "acos" => Unary(f32::acos),
+        "atan" => Unary(f32::atan),
+        "abs" => Unary(f32::abs),
+        "sign" => Unary(|x| if x == 0. { 0. } else { x.signum() }),
+        "floor" => Unary(f32::floor),
+        "ceil" => Unary(f32::ceil),
+        "round" => Unary(f32::round),
+        "fract" => Unary(|x| x - x.floor()),
+        "sqrt" => Unary(f32::sqrt),
+        "exp" => Unary(f32::exp),
+        "ln" => Unary(f32::ln),
+        "log2" => Unary(f32::log2),
+        "log10" => Unary(f32::log10),
+        "atan2" => Binary(f32::atan2),
+        "pow" => Binary(f32::powf),
+        "min" => Binary(f32::min),
+        "max" => Binary(f32::max),
+        "mod" => Binary(|x, y| x.rem_euclid(y)),
+        "step" => Binary(|edge, x| if x < edge { 0. } else { 1. }),
+        "clamp" => Ternary(|x, lo, hi| x.max(lo).min(hi)),
+        "mix" => Ternary(|a, b, t| a + (b - a) * t),
+        "smoothstep" => Ternary(|lo, hi, x| {
+            let t = ((x - lo) / (hi - lo)).clamp(0., 1.);
+            t * t * (3. - 2. * t)
+        }),
+        _ => return None,
+    })
+}
+
+/// A compiled expression.
+#[derive(Clone)]
+pub struct Expr {
+    /// Postfix order.
+    ops: Vec<Op>,
+    stack_size: usize,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self> {
+        if source.len() > MAX_LENGTH {
+            bail!("Expression is too long");
+        }
+        let mut parser = Parser {
+            source,
+            pos: 0,
+            depth: 0,
+            ops: Vec::new(),
+        };
+        parser.expr()?;
+        parser.skip_whitespace();
+        if parser.pos != source.len() {
+            bail!("Unexpected character at {} in expression {source:?}", parser.pos);
+        }
+        let mut size = 0;
+        let mut stack_size = 0;
+        for op in &parser.ops {
+            size = match op {
+                Op::Const(_) | Op::Var(_) => size + 1,
+                Op::Unary(_) => size,
+                Op::Binary(_) => size - 1,
+                Op::Ternary(_) => size - 2,
+            };
+            stack_size = stack_size.max(size);
+        }
+        Ok(Self { ops: parser.ops, stack_size })
+    }
+
+    pub fn eval(&self, vars: &ExprVars) -> f32 {
+        let mut stack = Vec::with_capacity(self.stack_size);
+        for op in &self.ops {
+            let value = match *op {
+                Op::Const(value) => value,
+                Op::Var(var) => match var {
+                    Var::Time => vars.time,
+                    Var::Beat => vars.beat,
+                    Var::T => vars.t,
+                },
+                Op::Unary(f) => {
+                    let x = stack.pop().unwrap();
+                    f(x)
+                }
+                Op::Binary(f) => {
+                    let y = stack.pop().unwrap();
+                    let x = stack.pop().unwrap();
+                    f(x, y)
+                }
+                Op::Ternary(f) => {
+                    let z = stack.pop().unwrap();
+                    let y = stack.pop().unwrap();
+                    let x = stack.pop().unwrap();
+                    f(x, y, z)
+                }
+            };
+            stack.push(value);
+        }
+        stack.pop().unwrap()
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    depth: usize,
+    ops: Vec<Op>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        self.pos += self.rest().len() - self.rest().trim_start().len();
+    }
+
+    fn rest(&self) -> &str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if !self.eat(c) {
+            bail!("Expected '{c}' at {} in expression {:?}", self.pos, self.source);
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            bail!("Expression is nested too deeply");
+        }
+        f(self)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn expr(&mut self) -> Result<()> {
+        self.nested(|p| {
+            p.term()?;
+            loop {
+                let op: fn(f32, f32) -> f32 = if p.eat('+') {
+                    |x, y| x + y
+                } else if p.eat('-') {
+                    |x, y| x - y
+                } else {
+                    return Ok(());
+                };
+                p.term()?;
+                p.ops.push(Op::Binary(op));
+            }
+        })
+    }
+
+    fn term(&mut self) -> Result<()> {
+        self.unary()?;
+        loop {
+            let op: fn(f32, f32) -> f32 = if self.eat('*') {
+                |x, y| x * y
+            } else if self.eat('/') {
+                |x, y| x / y
+            } else if self.eat('%') {
+                |x, y| x % y
+            } else {
+                return Ok(());
+            };
+            self.unary()?;
+            self.ops.push(Op::Binary(op));
+        }
+    }
+
+    fn unary(&mut self) -> Result<()> {
+        self.nested(|p| {
+            if p.eat('-') {
+                p.unary()?;
+                p.ops.push(Op::Unary(|x| -x));
+                Ok(())
+            } else if p.eat('+') {
+                p.unary()
+            } else {
+                p.atom()?;
+                if p.eat('^') {
+                    // right associative, and binds tighter than a leading minus: -2^2 = -4
+                    p.unary()?;
+                    p.ops.push(Op::Binary(f32::powf));
+                }
+                Ok(())
+            }
+        })
+    }
+
+    fn atom(&mut self) -> Result<()> {
+        let next = self.peek();
+        let start = self.pos;
+        match next {
+            Some('(') => {
+                self.pos += 1;
+                self.expr()?;
+                self.expect(')')
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+                    .unwrap_or(self.rest().len());
+                let mut end = self.pos + len;
+                // exponent sign, as in 1e-3
+                if self.source[..end].ends_with(['e', 'E']) && self.source[end..].starts_with(['-', '+']) {
+                    end += 1 + self.source[end + 1..]
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(self.source.len() - end - 1);
+                }
+                let Ok(value) = self.source[self.pos..end].parse() else {
+                    bail!("Invalid number at {start} in expression {:?}", self.source);
+                };
+                self.pos = end;
+                self.ops.push(Op::Const(value));
+                Ok(())
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(self.rest().len());
+                let name = &self.source[self.pos..self.pos + len];
+                self.pos += len;
+                if self.eat('(') {
+                    let Some(func) = func(name) else {
+                        bail!("Unknown function {name} in expression {:?}", self.source);
+                    };
+                    let (arity, op) = match func {
+                        Func::Unary(f) => (1, Op::Unary(f)),
+                        Func::Binary(f) => (2, Op::Binary(f)),
+                        Func::Ternary(f) => (3, Op::Ternary(f)),
+                    };
+                    let mut count = 0;
+                    if !self.eat(')') {
+                        loop {
+                            self.expr()?;
+                            count += 1;
+                            if !self.eat(',') {
+                                break;
+                            }
+                        }
+                        self.expect(')')?;
+                    }
+                    if count != arity {
+                        bail!("Function {name} takes {arity} argument(s), got {count} in expression {:?}", self.source);
+                    }
+                    self.ops.push(op);
+                    return Ok(());
+                }
+                self.ops.push(match name {
+                    "time" => Op::Var(Var::Time),
+                    "beat" => Op::Var(Var::Beat),
+                    "t" => Op::Var(Var::T),
+                    "pi" => Op::Const(std::f32::consts::PI),
+                    "tau" => Op::Const(std::f32::consts::TAU),
+                    "e" => Op::Const(std::f32::consts::E),
+                    _ => bail!("Unknown variable {name} in expression {:?}", self.source),
+                });
+                Ok(())
+            }
+            _ => bail!("Expected a value at {start} in expression {:?}", self.source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> f32 {
+        Expr::parse(source).unwrap().eval(&ExprVars {
+            time: 2.,
+            beat: 0.5,
+            t: 0.25,
+        })
+    }
+
+    fn error(source: &str) -> String {
+        Expr::parse(source).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("-2^2"), -4.);
+        assert_eq!(eval("(-2)^2"), 4.);
+        assert_eq!(eval("2^3^2"), 512.);
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), 5.);
+        assert_eq!(eval("7 % 4 * 2"), 6.);
+        assert_eq!(eval("2 - -1"), 3.);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(eval("1e-3"), 1e-3);
+        assert!((eval("2e-3*1000") - 2.).abs() < 1e-6);
+        assert_eq!(eval("1.5E+2"), 150.);
+        assert_eq!(eval(".5"), 0.5);
+        assert!(Expr::parse("1e-").is_err());
+        assert!(Expr::parse("1.2.3").is_err());
+    }
+
+    #[test]
+    fn vars_and_functions() {
+        assert!((eval("sin(beat * pi) * 0.02") - 0.02).abs() < 1e-6);
+        assert_eq!(eval("time + t"), 2.25);
+        assert_eq!(eval("clamp(time, 0, 1)"), 1.);
+        assert_eq!(eval("max(1, pow(2, 3))"), 8.);
+        assert!(error("foo").contains("Unknown variable"));
+        assert!(error("foo(1)").contains("Unknown function"));
+    }
+
+    #[test]
+    fn arity() {
+        for source in ["sin()", "sin(1, 2)", "pow(1)", "clamp(1, 2)", "mix(1, 2, 3, 4)"] {
+            assert!(error(source).contains("argument(s)"), "{source}");
+        }
+        assert!(Expr::parse("pow(1,").is_err());
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(eval(&nested(10)), 1.);
+        assert!(error(&nested(40)).contains("nested too deeply"));
+        assert!(error(&format!("{}1", "-".repeat(40))).contains("nested too deeply"));
+        assert!(error(&format!("{}1", "sin(".repeat(40))).contains("nested too deeply"));
+        assert!(error(&"1+".repeat(600)).contains("too long"));
+    }
+}
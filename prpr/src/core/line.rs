@@ -7,10 +7,10 @@ use crate::{
 use macroquad::prelude::*;
 use miniquad::{RenderPass, Texture, TextureParams, TextureWrap};
 use nalgebra::Rotation2;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 
-#[derive(Clone, Copy, Deserialize)]
+#[derive(Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[repr(usize)]
 pub enum UIElement {
@@ -27,7 +27,7 @@ pub enum UIElement {
 pub enum JudgeLineKind {
     #[default]
     Normal,
-    Texture(SafeTexture),
+    Texture(SafeTexture, String),
     Text(Anim<String>),
     Paint(Anim<f32>, RefCell<(Option<RenderPass>, bool)>),
 }
@@ -81,6 +81,16 @@ impl JudgeLineCache {
     }
 }
 
+/// An RPE event in a link group, kept so that exported charts stay linked in the editor.
+#[derive(Clone, Debug)]
+pub struct EventLink {
+    /// The RPE name of the event list, e.g. `moveXEvents`.
+    pub events: &'static str,
+    /// Start time of the event, which identifies it on export.
+    pub time: f32,
+    pub group: i32,
+}
+
 pub struct JudgeLine {
     pub object: Object,
     pub ctrl_obj: RefCell<CtrlObject>,
@@ -93,6 +103,10 @@ pub struct JudgeLine {
     pub z_index: i32,
     pub show_below: bool,
     pub attach_ui: Option<UIElement>,
+    /// Empty if the format has no line names.
+    pub name: String,
+    pub group: Option<String>,
+    pub links: Vec<EventLink>,
 
     pub cache: JudgeLineCache,
 }
@@ -161,9 +175,13 @@ impl JudgeLine {
         let alpha = self.object.alpha.now_opt().unwrap_or(1.0) * res.alpha;
         let color = self.color.now_opt();
         res.with_model(self.now_transform(res, lines), |res| {
-            if res.config.debug {
+            if res.config.debug_line(self.group.as_deref()) {
                 res.apply_model(|_| {
-                    ui.text(id.to_string()).pos(0., -0.01).anchor(0.5, 1.).size(0.8).draw();
+                    let text = match &self.group {
+                        Some(group) => format!("{id} ({group})"),
+                        None => id.to_string(),
+                    };
+                    ui.text(text).pos(0., -0.01).anchor(0.5, 1.).size(0.8).draw();
                 });
             }
             res.with_model(self.object.now_scale(), |res| {
@@ -174,7 +192,7 @@ impl JudgeLine {
                         let len = res.info.line_length;
                         draw_line(-len, 0., len, 0., 0.01, color);
                     }
-                    JudgeLineKind::Texture(texture) => {
+                    JudgeLineKind::Texture(texture, _) => {
                         let mut color = color.unwrap_or(WHITE);
                         color.a = alpha.max(0.0);
                         let hf = vec2(texture.width() / res.aspect_ratio, texture.height() / res.aspect_ratio);
@@ -1,11 +1,26 @@
 use super::{chart::ChartSettings, BpmList, CtrlObject, JudgeLine, Matrix, Object, Point, Resource, JUDGE_LINE_GOOD_COLOR, JUDGE_LINE_PERFECT_COLOR};
-use crate::{judge::JudgeStatus, parse::RPE_HEIGHT};
+use crate::{config::Modifiers, judge::JudgeStatus, parse::RPE_HEIGHT};
 use macroquad::prelude::*;
 
 const HOLD_PARTICLE_INTERVAL: f32 = 0.15;
 const FADEOUT_TIME: f32 = 0.16;
 const BAD_TIME: f32 = 0.5;
 
+// distances to the judge line in half screen heights, between which notes fade with the hidden & fade-in modifiers
+const HIDDEN_RANGE: (f32, f32) = (0.4, 0.8);
+const FADE_IN_RANGE: (f32, f32) = (0.5, 0.9);
+
+fn modifier_alpha(modifiers: &Modifiers, dist: f32) -> f32 {
+    let mut alpha = 1.;
+    if modifiers.hidden {
+        alpha *= ((dist - HIDDEN_RANGE.0) / (HIDDEN_RANGE.1 - HIDDEN_RANGE.0)).clamp(0., 1.);
+    }
+    if modifiers.fade_in {
+        alpha *= ((FADE_IN_RANGE.1 - dist) / (FADE_IN_RANGE.1 - FADE_IN_RANGE.0)).clamp(0., 1.);
+    }
+    alpha
+}
+
 #[derive(Clone, Debug)]
 pub enum NoteKind {
     Click,
@@ -197,6 +212,7 @@ impl Note {
         let height = self.height / res.aspect_ratio * spd;
 
         let base = height - line_height;
+        color.a *= modifier_alpha(&res.config.modifiers, base * res.aspect_ratio);
         if !config.draw_below
             && ((res.time - FADEOUT_TIME >= self.time) || (self.fake && res.time >= self.time) || (self.time > res.time && base <= -1e-5))
             && !matches!(self.kind, NoteKind::Hold { .. })
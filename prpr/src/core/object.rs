@@ -29,6 +29,12 @@ impl Object {
         self.translation.1.set_time(time);
     }
 
+    /// Flips horizontally around the origin of the parent.
+    pub fn mirror(&mut self) {
+        self.translation.0.scale(-1.);
+        self.rotation.scale(-1.);
+    }
+
     pub fn dead(&self) -> bool {
         self.alpha.dead()
             && self.scale.0.dead()
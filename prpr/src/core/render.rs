@@ -2,7 +2,7 @@ use macroquad::{
     texture::{RenderTarget, Texture2D},
     window::get_internal_gl,
 };
-use miniquad::{gl::GLuint, RenderPass, Texture, TextureFormat};
+use miniquad::{gl::GLuint, FilterMode, RenderPass, Texture, TextureFormat, TextureParams, TextureWrap};
 
 pub struct MSRenderTarget {
     dim: (u32, u32),
@@ -121,3 +121,106 @@ impl Drop for MSRenderTarget {
         }
     }
 }
+
+/// A pair of transparent render targets to draw a single judge line onto and run effects on it, see
+/// [`Effect::target`](super::Effect::target).
+pub struct LayerTarget {
+    dim: (u32, u32),
+    targets: [RenderTarget; 2],
+}
+
+impl LayerTarget {
+    pub fn new(dim: (u32, u32)) -> Self {
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        let targets = std::array::from_fn(|_| {
+            let texture = Texture::new_render_texture(
+                ctx,
+                TextureParams {
+                    width: dim.0,
+                    height: dim.1,
+                    format: TextureFormat::RGBA8,
+                    filter: FilterMode::Linear,
+                    wrap: TextureWrap::Clamp,
+                },
+            );
+            RenderTarget {
+                texture: Texture2D::from_miniquad_texture(texture),
+                render_pass: RenderPass::new(ctx, texture, None),
+            }
+        });
+        Self { dim, targets }
+    }
+
+    pub fn dim(&self) -> (u32, u32) {
+        self.dim
+    }
+
+    pub fn swap(&mut self) {
+        self.targets.swap(0, 1);
+    }
+
+    pub fn output(&self) -> RenderTarget {
+        self.targets[0]
+    }
+
+    pub fn old(&self) -> RenderTarget {
+        self.targets[1]
+    }
+}
+
+impl Drop for LayerTarget {
+    fn drop(&mut self) {
+        for target in &self.targets {
+            target.delete();
+        }
+    }
+}
+
+/// A copy of the last frame of the chart, for effects sampling it, see
+/// [`PreviousFrameUniform`](super::PreviousFrameUniform).
+pub struct FrameTarget {
+    dim: (u32, u32),
+    target: RenderTarget,
+}
+
+impl FrameTarget {
+    pub fn new(dim: (u32, u32)) -> Self {
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        let texture = Texture::new_render_texture(
+            ctx,
+            TextureParams {
+                width: dim.0,
+                height: dim.1,
+                format: TextureFormat::RGB8,
+                filter: FilterMode::Linear,
+                wrap: TextureWrap::Clamp,
+            },
+        );
+        Self {
+            dim,
+            target: RenderTarget {
+                texture: Texture2D::from_miniquad_texture(texture),
+                render_pass: RenderPass::new(ctx, texture, None),
+            },
+        }
+    }
+
+    pub fn dim(&self) -> (u32, u32) {
+        self.dim
+    }
+
+    pub fn texture(&self) -> Texture2D {
+        self.target.texture
+    }
+
+    /// Copies `source`, which has to be of the same size.
+    pub fn copy_from(&self, source: RenderTarget) -> bool {
+        copy_fbo(internal_id(source), internal_id(self.target), self.dim)
+    }
+}
+
+impl Drop for FrameTarget {
+    fn drop(&mut self) {
+        self.target.delete();
+    }
+}
@@ -1,4 +1,4 @@
-use super::{MSRenderTarget, Matrix, Point, JUDGE_LINE_PERFECT_COLOR, NOTE_WIDTH_RATIO_BASE};
+use super::{FrameTarget, LayerTarget, MSRenderTarget, Matrix, Point, JUDGE_LINE_PERFECT_COLOR, NOTE_WIDTH_RATIO_BASE};
 use crate::{
     config::Config,
     ext::{create_audio_manger, nalgebra_to_glm, SafeTexture},
@@ -327,6 +327,10 @@ pub struct Resource {
     pub sfx_flick: Sfx,
 
     pub chart_target: Option<MSRenderTarget>,
+    /// Where lines with effects are drawn, see [`Effect::target`](super::Effect::target).
+    pub layer_target: Option<LayerTarget>,
+    /// The last frame of the chart, kept by [`Self::keep_frame`].
+    pub previous_frame: Option<FrameTarget>,
     pub no_effect: bool,
 
     pub note_buffer: RefCell<NoteBuffer>,
@@ -452,6 +456,8 @@ impl Resource {
             sfx_flick,
 
             chart_target: None,
+            layer_target: None,
+            previous_frame: None,
             no_effect,
 
             note_buffer: RefCell::new(NoteBuffer::default()),
@@ -470,6 +476,19 @@ impl Resource {
             .emit_at(vec2(pt.x, -pt.y), if self.res_pack.info.hit_fx_rotate { rotation.to_radians() } else { 0. }, color);
     }
 
+    /// Copies the chart as rendered into [`Self::previous_frame`], for effects sampling it. Returns whether copying works.
+    pub fn keep_frame(&mut self) -> bool {
+        let Some(target) = &self.chart_target else {
+            return true;
+        };
+        let dim = self.last_screen_size;
+        if self.previous_frame.as_ref().map_or(true, |it| it.dim() != dim) {
+            self.previous_frame = Some(FrameTarget::new(dim));
+        }
+        unsafe { get_internal_gl() }.flush();
+        self.previous_frame.as_ref().unwrap().copy_from(target.output())
+    }
+
     pub fn update_size(&mut self, dim: (u32, u32)) -> bool {
         if self.last_screen_size == dim {
             return false;
@@ -0,0 +1,35 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform vec2 screenSize;
+uniform sampler2D screenTexture;
+
+uniform float threshold; // %0.7% 0..1
+uniform float intensity; // %1.0%
+uniform float radius; // %8.0%
+uniform float sampleCount; // %4% int 1..8
+
+vec4 bright(vec2 p) {
+  vec4 c = texture2D(screenTexture, p);
+  float lum = dot(c.rgb, vec3(0.299, 0.587, 0.114));
+  return c * smoothstep(threshold, 1.0, lum);
+}
+
+void main() {
+  vec4 glow = vec4(0.0);
+  float total = 0.0;
+  vec2 texel = radius / screenSize;
+  float n = sampleCount;
+  for (float i = -8.0; i <= 8.0; ++i) {
+    if (abs(i) > n) continue;
+    for (float j = -8.0; j <= 8.0; ++j) {
+      if (abs(j) > n) continue;
+      vec2 d = vec2(i, j) / n;
+      float w = exp(-dot(d, d) * 2.0);
+      glow += bright(uv + d * texel) * w;
+      total += w;
+    }
+  }
+  gl_FragColor = min(texture2D(screenTexture, uv) + glow / total * intensity, 1.0);
+}
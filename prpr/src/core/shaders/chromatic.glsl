@@ -28,4 +28,5 @@ void main() {
     c += slice * texture2D(screenTexture, uv + slice_offset).rgb;
   }
   gl_FragColor.rgb = c / sum;
+  gl_FragColor.a = texture2D(screenTexture, uv).a;
 }
@@ -8,8 +8,9 @@ uniform sampler2D screenTexture;
 uniform float factor; // %1.0% 0..1
 
 void main() {
-  vec3 color = texture2D(screenTexture, uv).xyz;
+  vec4 c = texture2D(screenTexture, uv);
+  vec3 color = c.rgb;
   vec3 lum = vec3(0.299, 0.587, 0.114);
   vec3 gray = vec3(dot(lum, color));
-  gl_FragColor = vec4(mix(color, gray, factor), 1.0);
+  gl_FragColor = vec4(mix(color, gray, factor), c.a);
 }
@@ -0,0 +1,20 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform sampler2D screenTexture;
+uniform float time;
+
+uniform float strength; // %0.004%
+uniform float scale; // %40.0%
+uniform float speed; // %2.0%
+
+void main() {
+  vec2 p = uv * scale;
+  float t = time * speed;
+  vec2 offset = vec2(
+    sin(p.y + t) + sin(p.y * 1.7 - t * 1.3) * 0.5,
+    cos(p.x * 1.3 + t * 0.7) + sin(p.x * 2.3 - p.y + t) * 0.5
+  );
+  gl_FragColor = texture2D(screenTexture, uv + offset * strength);
+}
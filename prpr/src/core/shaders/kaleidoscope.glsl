@@ -0,0 +1,24 @@
+#version 100
+// a single segment mirrors the screen across the line through the center at the rotation
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform vec2 screenSize;
+uniform sampler2D screenTexture;
+
+uniform float segments; // %6% int 1..32
+uniform float rotation; // %0.0%
+uniform float centerX; // %0.5% 0..1
+uniform float centerY; // %0.5% 0..1
+
+void main() {
+  float aspect = screenSize.x / screenSize.y;
+  vec2 center = vec2(centerX, centerY);
+  vec2 d = (uv - center) * vec2(aspect, 1.0);
+  float angle = radians(rotation);
+  float segment = 6.28318530718 / floor(segments);
+  float a = mod(atan(d.y, d.x) - angle, segment);
+  a = min(a, segment - a) + angle;
+  vec2 p = center + vec2(cos(a), sin(a)) * length(d) / vec2(aspect, 1.0);
+  gl_FragColor = texture2D(screenTexture, p);
+}
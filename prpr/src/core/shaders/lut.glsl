@@ -0,0 +1,27 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform sampler2D screenTexture;
+// a strip of lutSize squares of lutSize * lutSize pixels. Red grows rightwards and green downwards in a square, and blue
+// grows square by square
+uniform sampler2D lut;
+
+uniform float lutSize; // %16% int 2..64
+uniform float strength; // %1.0% 0..1
+
+vec3 lookup(vec3 c) {
+  float n = lutSize;
+  float b = clamp(c.b, 0.0, 1.0) * (n - 1.0);
+  float b0 = floor(b);
+  float b1 = min(b0 + 1.0, n - 1.0);
+  vec2 p = (clamp(c.rg, 0.0, 1.0) * (n - 1.0) + 0.5) / vec2(n * n, n);
+  vec3 c0 = texture2D(lut, p + vec2(b0 / n, 0.0)).rgb;
+  vec3 c1 = texture2D(lut, p + vec2(b1 / n, 0.0)).rgb;
+  return mix(c0, c1, b - b0);
+}
+
+void main() {
+  vec4 c = texture2D(screenTexture, uv);
+  gl_FragColor = vec4(mix(c.rgb, lookup(c.rgb), strength), c.a);
+}
@@ -0,0 +1,16 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform sampler2D screenTexture;
+
+uniform float amount; // %0.01%
+uniform float angle; // %0.0%
+
+void main() {
+  vec2 offset = vec2(cos(radians(angle)), sin(radians(angle))) * amount;
+  vec4 c = texture2D(screenTexture, uv);
+  c.r = texture2D(screenTexture, uv + offset).r;
+  c.b = texture2D(screenTexture, uv - offset).b;
+  gl_FragColor = c;
+}
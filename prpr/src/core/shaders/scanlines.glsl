@@ -0,0 +1,17 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform sampler2D screenTexture;
+uniform float time;
+
+uniform float count; // %240.0%
+uniform float intensity; // %0.3% 0..1
+uniform float speed; // %0.0%
+
+void main() {
+  vec4 c = texture2D(screenTexture, uv);
+  float s = sin((uv.y + time * speed) * count * 3.14159265359);
+  c.rgb *= 1.0 - intensity * (s * 0.5 + 0.5);
+  gl_FragColor = c;
+}
@@ -0,0 +1,16 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+uniform sampler2D screenTexture;
+uniform float time;
+
+uniform float amplitude; // %0.01%
+uniform float frequency; // %20.0%
+uniform float speed; // %3.0%
+uniform float vertical; // %0.0% 0..1
+
+void main() {
+  vec2 offset = vec2(sin(uv.y * frequency + time * speed), sin(uv.x * frequency + time * speed)) * amplitude;
+  gl_FragColor = texture2D(screenTexture, uv + offset * vec2(1.0 - vertical, vertical));
+}
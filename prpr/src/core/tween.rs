@@ -1,6 +1,6 @@
-use macroquad::prelude::{vec2, Color, Vec2};
+use macroquad::prelude::{vec2, vec3, Color, Vec2, Vec3};
 use once_cell::sync::Lazy;
-use std::{ops::Range, rc::Rc};
+use std::{any::Any, collections::HashMap, ops::Range, rc::Rc};
 
 pub type TweenId = u8;
 
@@ -121,6 +121,7 @@ thread_local! {
 
 pub trait TweenFunction {
     fn y(&self, x: f32) -> f32;
+    fn as_any(&self) -> &dyn Any;
 }
 
 pub struct StaticTween(pub TweenId);
@@ -128,6 +129,10 @@ impl TweenFunction for StaticTween {
     fn y(&self, x: f32) -> f32 {
         TWEEN_FUNCTIONS[self.0 as usize](x)
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 impl StaticTween {
@@ -136,19 +141,144 @@ impl StaticTween {
     }
 }
 
-// TODO assuming monotone, but actually they're not (e.g. Back tween)
-pub struct ClampedTween(TweenId, Range<f32>, Range<f32>);
+/// The part of a curve a clamped tween evaluates.
+///
+/// The part is scaled so that it goes from 0 to 1. Curves that overshoot (e.g. Back or Elastic) may end close to where
+/// they start within the range, and scaling would blow their swing up. Once the ends are less than [`Clamp::RATIO`] of
+/// the swing apart, the swing is damped instead, down to a linear tween when both ends meet.
+struct Clamp {
+    start: f32,
+    dy: f32,
+    scale: f32,
+}
+
+impl Clamp {
+    const RATIO: f32 = 0.25;
+    const SAMPLES: usize = 64;
+
+    fn new(f: impl Fn(f32) -> f32, x_range: &Range<f32>) -> Self {
+        let start = f(x_range.start);
+        let dy = f(x_range.end) - start;
+        let (min, max) = (0..=Self::SAMPLES)
+            .map(|i| f(f32::tween(&x_range.start, &x_range.end, i as f32 / Self::SAMPLES as f32)))
+            .fold((start, start), |(min, max), y| (min.min(y), max.max(y)));
+        let div = dy.abs().max((max - min) * Self::RATIO);
+        // x + (y - x * dy) / dy is just y / dy, the damping multiplies the swing by (dy / div)²
+        let scale = if div > 0. { dy / (div * div) } else { 0. };
+        Self { start, dy, scale }
+    }
+
+    fn y(&self, f: impl Fn(f32) -> f32, x_range: &Range<f32>, x: f32) -> f32 {
+        let y = f(f32::tween(&x_range.start, &x_range.end, x)) - self.start;
+        x + (y - x * self.dy) * self.scale
+    }
+}
+
+pub struct ClampedTween(pub TweenId, pub Range<f32>, Clamp);
 impl TweenFunction for ClampedTween {
     fn y(&self, x: f32) -> f32 {
-        (TWEEN_FUNCTIONS[self.0 as usize](f32::tween(&self.1.start, &self.1.end, x)) - self.2.start) / (self.2.end - self.2.start)
+        self.2.y(TWEEN_FUNCTIONS[self.0 as usize], &self.1, x)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
 impl ClampedTween {
     pub fn new(tween: TweenId, range: Range<f32>) -> Self {
-        let f = TWEEN_FUNCTIONS[tween as usize];
-        let y_range = f(range.start)..f(range.end);
-        Self(tween, range, y_range)
+        let clamp = Clamp::new(TWEEN_FUNCTIONS[tween as usize], &range);
+        Self(tween, range, clamp)
+    }
+}
+
+/// [`ClampedTween`] of any tween function, used for custom easings.
+pub struct ClampedCustomTween(pub Rc<dyn TweenFunction>, pub Range<f32>, Clamp);
+impl TweenFunction for ClampedCustomTween {
+    fn y(&self, x: f32) -> f32 {
+        self.2.y(|x| self.0.y(x), &self.1, x)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl ClampedCustomTween {
+    pub fn new(tween: Rc<dyn TweenFunction>, range: Range<f32>) -> Self {
+        let clamp = Clamp::new(|x| tween.y(x), &range);
+        Self(tween, range, clamp)
+    }
+}
+
+/// Linear interpolation between points, sorted by x. Constant beyond the first and the last point.
+pub struct PiecewiseTween(Vec<(f32, f32)>);
+impl TweenFunction for PiecewiseTween {
+    fn y(&self, x: f32) -> f32 {
+        let id = self.0.partition_point(|it| it.0 <= x);
+        if id == 0 {
+            return self.0[0].1;
+        }
+        let Some(&(x2, y2)) = self.0.get(id) else {
+            return self.0[id - 1].1;
+        };
+        let (x1, y1) = self.0[id - 1];
+        f32::tween(&y1, &y2, (x - x1) / (x2 - x1))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl PiecewiseTween {
+    /// Panics if `points` is empty.
+    pub fn new(mut points: Vec<(f32, f32)>) -> Self {
+        assert!(!points.is_empty());
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self(points)
+    }
+
+    /// Samples evenly spaced from 0 to 1.
+    pub fn from_samples(samples: &[f32]) -> Self {
+        let step = 1. / (samples.len() - 1).max(1) as f32;
+        Self::new(samples.iter().enumerate().map(|(i, y)| (i as f32 * step, *y)).collect())
+    }
+}
+
+/// Jumps in `count` equal steps, like CSS `steps()`. With `start`, the first jump happens at the start instead of the
+/// last one at the end. A single step without `start` holds the start value.
+pub struct StepTween {
+    pub count: u32,
+    pub start: bool,
+}
+
+impl TweenFunction for StepTween {
+    fn y(&self, x: f32) -> f32 {
+        if x >= 1. {
+            return 1.;
+        }
+        let n = self.count.max(1) as f32;
+        let step = (x.max(0.) * n).floor() + if self.start { 1. } else { 0. };
+        (step / n).min(1.)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Custom easings by name, declared in `extra.json`.
+#[derive(Clone, Default)]
+pub struct EasingRegistry(HashMap<String, Rc<dyn TweenFunction>>);
+
+impl EasingRegistry {
+    pub fn insert(&mut self, name: String, tween: Rc<dyn TweenFunction>) {
+        self.0.insert(name, tween);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn TweenFunction>> {
+        self.0.get(name).cloned()
     }
 }
 
@@ -164,14 +294,18 @@ const SLOPE_EPS: f32 = 1e-7;
 
 pub struct BezierTween {
     sample_table: [f32; SAMPLE_TABLE_SIZE],
-    p1: (f32, f32),
-    p2: (f32, f32),
+    pub p1: (f32, f32),
+    pub p2: (f32, f32),
 }
 
 impl TweenFunction for BezierTween {
     fn y(&self, x: f32) -> f32 {
         Self::sample(self.p1.1, self.p2.1, self.t_for_x(x))
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 impl BezierTween {
@@ -275,6 +409,10 @@ pub trait Tweenable: Clone {
     fn add(_x: &Self, _y: &Self) -> Self {
         unimplemented!()
     }
+    /// The value of an expression, see [`AnimFloat::expr`](super::AnimFloat::expr).
+    fn from_f32(_x: f32) -> Self {
+        unimplemented!()
+    }
 }
 
 impl Tweenable for f32 {
@@ -285,6 +423,10 @@ impl Tweenable for f32 {
     fn add(x: &Self, y: &Self) -> Self {
         x + y
     }
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
 }
 
 impl Tweenable for Vec2 {
@@ -297,6 +439,27 @@ impl Tweenable for Vec2 {
     }
 }
 
+impl Tweenable for Vec3 {
+    fn tween(x: &Self, y: &Self, t: f32) -> Self {
+        vec3(f32::tween(&x.x, &y.x, t), f32::tween(&x.y, &y.y, t), f32::tween(&x.z, &y.z, t))
+    }
+
+    fn add(x: &Self, y: &Self) -> Self {
+        *x + *y
+    }
+}
+
+/// Rounded to the nearest integer.
+impl Tweenable for i32 {
+    fn tween(x: &Self, y: &Self, t: f32) -> Self {
+        f32::tween(&(*x as f32), &(*y as f32), t).round() as i32
+    }
+
+    fn add(x: &Self, y: &Self) -> Self {
+        x + y
+    }
+}
+
 impl Tweenable for Color {
     fn tween(x: &Self, y: &Self, t: f32) -> Self {
         Self::new(f32::tween(&x.r, &y.r, t), f32::tween(&x.g, &y.g, t), f32::tween(&x.b, &y.b, t), f32::tween(&x.a, &y.a, t))
@@ -317,3 +480,109 @@ impl Tweenable for String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASE_IN_BACK: TweenId = 24;
+    const EASE_IN_OUT_ELASTIC: TweenId = 29;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{a} != {b}");
+    }
+
+    #[test]
+    fn clamped_full_range() {
+        for tween in [EASE_IN_BACK, EASE_IN_OUT_ELASTIC] {
+            let f = TWEEN_FUNCTIONS[tween as usize];
+            let clamped = ClampedTween::new(tween, 0.0..1.0);
+            for i in 0..=10 {
+                let x = i as f32 / 10.;
+                assert_close(clamped.y(x), (f(x) - f(0.)) / (f(1.) - f(0.)));
+            }
+        }
+    }
+
+    #[test]
+    fn clamped_ends() {
+        for (tween, range) in [
+            (EASE_IN_BACK, 0.2..0.9),
+            (EASE_IN_BACK, 0.1..0.5),
+            (EASE_IN_OUT_ELASTIC, 0.1..0.4),
+            (EASE_IN_OUT_ELASTIC, 0.3..0.95),
+        ] {
+            let clamped = ClampedTween::new(tween, range);
+            assert_close(clamped.y(0.), 0.);
+            assert_close(clamped.y(1.), 1.);
+            for i in 0..=100 {
+                let y = clamped.y(i as f32 / 100.);
+                assert!(y.is_finite() && y.abs() < 10., "{y}");
+            }
+        }
+    }
+
+    #[test]
+    fn clamped_continuous() {
+        // ease-in Back comes back to 0 here
+        let end = 1.70158 / 2.70158;
+        let below = ClampedTween::new(EASE_IN_BACK, 0.0..end - 1e-5);
+        let above = ClampedTween::new(EASE_IN_BACK, 0.0..end + 1e-5);
+        let flat = ClampedTween::new(EASE_IN_BACK, 0.0..end);
+        for i in 0..=10 {
+            let x = i as f32 / 10.;
+            assert!((below.y(x) - above.y(x)).abs() < 1e-2);
+            assert!((flat.y(x) - x).abs() < 1e-2);
+        }
+        assert_eq!(ClampedTween::new(EASE_IN_OUT_ELASTIC, 0.5..0.5).y(0.3), 0.3);
+    }
+
+    #[test]
+    fn clamped_custom() {
+        let tween = ClampedCustomTween::new(StaticTween::get_rc(EASE_IN_OUT_ELASTIC), 0.3..0.95);
+        let expected = ClampedTween::new(EASE_IN_OUT_ELASTIC, 0.3..0.95);
+        for i in 0..=10 {
+            let x = i as f32 / 10.;
+            assert_eq!(tween.y(x), expected.y(x));
+        }
+    }
+
+    #[test]
+    fn piecewise() {
+        let tween = PiecewiseTween::new(vec![(1., 1.), (0.5, 0.), (0., 0.), (0.5, 1.)]);
+        assert_eq!(tween.y(-1.), 0.);
+        assert_eq!(tween.y(0.25), 0.);
+        assert_eq!(tween.y(0.49), 0.);
+        assert_eq!(tween.y(0.5), 1.);
+        assert_eq!(tween.y(0.75), 1.);
+        assert_eq!(tween.y(2.), 1.);
+
+        let tween = PiecewiseTween::from_samples(&[0., 1., 0.]);
+        assert_eq!(tween.y(-0.5), 0.);
+        assert_close(tween.y(0.25), 0.5);
+        assert_eq!(tween.y(0.5), 1.);
+        assert_eq!(tween.y(1.5), 0.);
+        assert_eq!(PiecewiseTween::from_samples(&[0.3]).y(0.7), 0.3);
+    }
+
+    #[test]
+    fn steps() {
+        let tween = StepTween { count: 4, start: false };
+        assert_eq!(tween.y(0.), 0.);
+        assert_eq!(tween.y(0.24), 0.);
+        assert_eq!(tween.y(0.25), 0.25);
+        assert_eq!(tween.y(0.99), 0.75);
+        assert_eq!(tween.y(1.), 1.);
+
+        let tween = StepTween { count: 4, start: true };
+        assert_eq!(tween.y(0.), 0.25);
+        assert_eq!(tween.y(0.5), 0.75);
+        assert_eq!(tween.y(0.75), 1.);
+        assert_eq!(tween.y(1.), 1.);
+
+        for count in [0, 1] {
+            assert_eq!(StepTween { count, start: false }.y(0.99), 0.);
+            assert_eq!(StepTween { count, start: true }.y(0.), 1.);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::judge::JudgeProfile;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -38,6 +39,8 @@ pub struct ChartInfo {
     pub tags: Vec<String>,
 
     pub hold_partial_cover: bool,
+    /// Overrides the judge profile in the configuration.
+    pub judge_profile: Option<JudgeProfile>,
 }
 
 impl Default for ChartInfo {
@@ -68,6 +71,7 @@ impl Default for ChartInfo {
             tags: Vec::new(),
 
             hold_partial_cover: false,
+            judge_profile: None,
         }
     }
 }
@@ -1,8 +1,10 @@
 use crate::{
-    config::Config,
-    core::{BadNote, Chart, NoteKind, Point, Resource, Vector, JUDGE_LINE_GOOD_COLOR, JUDGE_LINE_PERFECT_COLOR},
+    config::{Config, Modifiers},
+    core::{BadNote, Chart, Matrix, NoteKind, Point, Resource, Vector, JUDGE_LINE_GOOD_COLOR, JUDGE_LINE_PERFECT_COLOR},
     ext::{get_viewport, NotNanExt},
+    replay::ReplayFrame,
 };
+use anyhow::{bail, Result};
 use macroquad::prelude::{
     utils::{register_input_subscriber, repeat_all_miniquad_input},
     *,
@@ -10,18 +12,103 @@ use macroquad::prelude::{
 use miniquad::{EventHandler, MouseButton};
 use once_cell::sync::Lazy;
 use sasa::{PlaySfxParams, Sfx};
+use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
     collections::{HashMap, VecDeque},
     num::FpCategory,
 };
 
-pub const FLICK_SPEED_THRESHOLD: f32 = 1.8;
-pub const LIMIT_PERFECT: f32 = 0.08;
-pub const LIMIT_GOOD: f32 = 0.16;
-pub const LIMIT_BAD: f32 = 0.22;
+mod debug;
+pub use debug::{Decision, JudgeDebug, TraceEntry};
+
+pub mod input;
+use input::{Button, InputSource, RawInput, MAX_LANES};
+
+mod scoring;
+pub use scoring::{AccuracyScoring, ApOnlyScoring, ExScoring, PhigrosScoring, ScoreState, Scoring, ScoringRule};
+
 pub const UP_TOLERANCE: f32 = 0.01;
-pub const DIST_FACTOR: f32 = 0.2;
+/// How far (horizontally, in the coordinates of the line) a touch reaches.
+const X_DIFF_MAX: f32 = 0.21 / (16. / 9.) * 2.;
+
+/// Timing windows (in seconds) and thresholds used by [`Judge`].
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JudgeWindows {
+    pub perfect: f32,
+    pub good: f32,
+    pub bad: f32,
+    /// Minimum speed of a flick in screen units per second at 275 DPI.
+    pub flick_speed_threshold: f32,
+    /// How much the horizontal distance to a note counts when choosing the closest one.
+    pub dist_factor: f32,
+}
+
+impl JudgeWindows {
+    pub const STANDARD: Self = Self {
+        perfect: 0.08,
+        good: 0.16,
+        bad: 0.22,
+        flick_speed_threshold: 1.8,
+        dist_factor: 0.2,
+    };
+    pub const CHALLENGE: Self = Self {
+        perfect: 0.045,
+        good: 0.09,
+        bad: 0.15,
+        flick_speed_threshold: 2.,
+        dist_factor: 0.2,
+    };
+    pub const CASUAL: Self = Self {
+        perfect: 0.1,
+        good: 0.2,
+        bad: 0.25,
+        flick_speed_threshold: 1.5,
+        dist_factor: 0.25,
+    };
+
+    /// The flick speed threshold scaled by the DPI of the screen.
+    pub fn flick_threshold(&self, res: &Resource) -> f32 {
+        self.flick_speed_threshold * (res.dpi as f32 / 275.)
+    }
+}
+
+impl Default for JudgeWindows {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum JudgeProfile {
+    #[default]
+    Standard,
+    Challenge,
+    Casual,
+    Custom(JudgeWindows),
+}
+
+impl JudgeProfile {
+    pub fn windows(&self) -> JudgeWindows {
+        match self {
+            Self::Standard => JudgeWindows::STANDARD,
+            Self::Challenge => JudgeWindows::CHALLENGE,
+            Self::Casual => JudgeWindows::CASUAL,
+            Self::Custom(windows) => *windows,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Standard => "Standard",
+            Self::Challenge => "Challenge",
+            Self::Casual => "Casual",
+            Self::Custom(_) => "Custom",
+        }
+    }
+}
 
 pub fn play_sfx(sfx: &mut Sfx, config: &Config) {
     if config.volume_sfx <= 1e-2 {
@@ -32,6 +119,29 @@ pub fn play_sfx(sfx: &mut Sfx, config: &Config) {
     });
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum HitSound {
+    Click,
+    Drag,
+    Flick,
+}
+
+fn play_hit_sound(res: &mut Resource, sound: HitSound, time: f32) {
+    HIT_SOUNDS.with(|it| {
+        if let Some(log) = it.borrow_mut().as_mut() {
+            log.push((time, sound));
+        }
+    });
+    play_sfx(
+        match sound {
+            HitSound::Click => &mut res.sfx_click,
+            HitSound::Drag => &mut res.sfx_drag,
+            HitSound::Flick => &mut res.sfx_flick,
+        },
+        &res.config,
+    );
+}
+
 pub struct VelocityTracker {
     movements: VecDeque<(f32, Point)>,
     last_dir: Vector,
@@ -112,10 +222,10 @@ impl VelocityTracker {
         b
     }
 
-    pub fn has_flick(&mut self, res: &Resource) -> bool {
+    pub fn has_flick(&mut self, res: &Resource, windows: &JudgeWindows) -> bool {
         let spd = self.speed();
         let norm = spd.norm();
-        let threshold = FLICK_SPEED_THRESHOLD * (res.dpi as f32 / 275.);
+        let threshold = windows.flick_threshold(res);
         if self.wait && (norm <= threshold * (1.2 / 1.8) || (self.last_dir.dot(&spd.unscale(norm)) - 1.).abs() > 0.4) {
             self.wait = false;
         }
@@ -145,10 +255,26 @@ pub enum Judgement {
     Miss,
 }
 
+/// A judged note.
+#[derive(Clone, Debug)]
+pub struct NoteHit {
+    pub line: usize,
+    pub id: u32,
+    pub kind: NoteKind,
+    /// Chart time of the note.
+    pub time: f32,
+    pub judgement: Judgement,
+    /// Signed timing error in seconds, negative means early. `None` for misses and notes judged without exact timing (drags & flicks).
+    pub diff: Option<f32>,
+}
+
+/// Counts judgements for scoring. Builds with the `closed` feature use their own `inner` module instead, which has to provide
+/// the same methods: `new`, `commit` taking a [`NoteHit`], `reset`, `state`, `result` taking the [`ScoringRule`], `combo`, `counts`
+/// and `hits`.
 #[cfg(not(feature = "closed"))]
 #[derive(Default)]
 pub(crate) struct JudgeInner {
-    diffs: Vec<f32>,
+    hits: Vec<NoteHit>,
 
     combo: u32,
     max_combo: u32,
@@ -160,7 +286,7 @@ pub(crate) struct JudgeInner {
 impl JudgeInner {
     pub fn new(num_of_notes: u32) -> Self {
         Self {
-            diffs: Vec::new(),
+            hits: Vec::new(),
 
             combo: 0,
             max_combo: 0,
@@ -169,11 +295,10 @@ impl JudgeInner {
         }
     }
 
-    pub fn commit(&mut self, what: Judgement, diff: Option<f32>) {
+    pub fn commit(&mut self, hit: NoteHit) {
         use Judgement::*;
-        if let Some(diff) = diff {
-            self.diffs.push(diff);
-        }
+        let what = hit.judgement;
+        self.hits.push(hit);
         self.counts[what as usize] += 1;
         match what {
             Perfect | Good => {
@@ -192,33 +317,44 @@ impl JudgeInner {
         self.combo = 0;
         self.max_combo = 0;
         self.counts = [0; 4];
-        self.diffs.clear();
-    }
-
-    pub fn accuracy(&self) -> f64 {
-        (self.counts[0] as f64 + self.counts[1] as f64 * 0.65) / self.num_of_notes as f64
+        self.hits.clear();
     }
 
-    pub fn score(&self) -> u32 {
-        const TOTAL: u32 = 1000000;
-        if self.counts[0] == self.num_of_notes {
-            TOTAL
-        } else {
-            let score = (0.9 * self.accuracy() + self.max_combo as f64 / self.num_of_notes as f64 * 0.1) * TOTAL as f64;
-            score.round() as u32
+    pub fn state(&self) -> ScoreState {
+        ScoreState {
+            num_of_notes: self.num_of_notes,
+            counts: self.counts,
+            combo: self.combo,
+            max_combo: self.max_combo,
+            hits: &self.hits,
         }
     }
 
-    pub fn result(&self) -> PlayResult {
-        let early = self.diffs.iter().filter(|it| **it < 0.).count() as u32;
+    pub fn result(&self, scoring: &dyn ScoringRule) -> PlayResult {
+        // early & late only count goods and bads
+        let (early, late) = self
+            .hits
+            .iter()
+            .filter(|it| matches!(it.judgement, Judgement::Good | Judgement::Bad))
+            .filter_map(|it| it.diff)
+            .fold((0, 0), |(early, late), diff| if diff < 0. { (early + 1, late) } else { (early, late + 1) });
+        let state = self.state();
+        let score = scoring.score(&state);
         PlayResult {
-            score: self.score(),
-            accuracy: self.accuracy(),
+            score,
+            accuracy: scoring.accuracy(&state),
             max_combo: self.max_combo,
             num_of_notes: self.num_of_notes,
             counts: self.counts,
             early,
-            late: self.diffs.len() as u32 - early,
+            late,
+            judge_profile: JudgeProfile::default(),
+            modifiers: Modifiers::default(),
+            scoring: scoring.name(),
+            score_text: scoring.format(score),
+            max_score: scoring.max_score(&state),
+            failed: scoring.failed(&state),
+            hits: self.hits.clone(),
         }
     }
 
@@ -229,6 +365,10 @@ impl JudgeInner {
     pub fn counts(&self) -> [u32; 4] {
         self.counts
     }
+
+    pub fn hits(&self) -> &[NoteHit] {
+        &self.hits
+    }
 }
 
 #[cfg(feature = "closed")]
@@ -247,15 +387,34 @@ pub struct Judge {
     key_down_count: u32,
 
     pub(crate) inner: JudgeInner,
+
+    // new fields go below, so that the layout above stays the same for `inner`
+    pub profile: JudgeProfile,
+    pub modifiers: Modifiers,
+    windows: JudgeWindows,
+    scoring: Box<dyn ScoringRule>,
+
+    sources: Vec<Box<dyn InputSource>>,
+    /// Number of held buttons of each lane.
+    lane_counts: [u32; MAX_LANES],
+    /// Number of lanes of the input sources.
+    pub lanes: usize,
+    /// Whether held buttons catch flicks, which is the case when no source has a flick button.
+    pub key_flicks: bool,
+    /// Inputs of every update since the last reset, see [`crate::replay`].
+    pub record: Vec<ReplayFrame>,
+    /// Only recorded with [`Config::debug`] on.
+    pub debug: Option<JudgeDebug>,
 }
 
 static SUBSCRIBER_ID: Lazy<usize> = Lazy::new(register_input_subscriber);
 thread_local! {
-    static TOUCHES: RefCell<(Vec<Touch>, i32, u32)> = RefCell::default();
+    static TOUCHES: RefCell<Vec<Touch>> = RefCell::default();
+    static HIT_SOUNDS: RefCell<Option<Vec<(f32, HitSound)>>> = RefCell::default();
 }
 
 impl Judge {
-    pub fn new(chart: &Chart) -> Self {
+    pub fn new(chart: &Chart, profile: JudgeProfile, modifiers: Modifiers, scoring: Box<dyn ScoringRule>) -> Self {
         let notes = chart
             .lines
             .iter()
@@ -269,8 +428,18 @@ impl Judge {
             notes,
             trackers: HashMap::new(),
             last_time: 0.,
+            profile,
+            modifiers,
+            windows: profile.windows(),
+            scoring,
 
+            sources: Vec::new(),
+            lane_counts: [0; MAX_LANES],
+            lanes: 0,
+            key_flicks: true,
             key_down_count: 0,
+            record: Vec::new(),
+            debug: None,
 
             inner: JudgeInner::new(chart.lines.iter().map(|it| it.notes.iter().filter(|it| !it.fake).count() as u32).sum()),
         }
@@ -279,29 +448,65 @@ impl Judge {
     pub fn reset(&mut self) {
         self.notes.iter_mut().for_each(|it| it.1 = 0);
         self.trackers.clear();
+        self.last_time = 0.;
+        self.key_down_count = 0;
+        self.lane_counts = [0; MAX_LANES];
+        self.record.clear();
+        self.debug = None;
         self.inner.reset();
     }
 
-    pub fn commit(&mut self, what: Judgement, diff: Option<f32>) {
-        self.inner.commit(what, diff);
+    pub fn commit(&mut self, hit: NoteHit) {
+        self.inner.commit(hit);
+    }
+
+    /// Adds a source to take input from, see [`input::sources`]. Replays set [`Judge::lanes`] and [`Judge::key_flicks`] as recorded
+    /// instead.
+    ///
+    /// Lanes split the screen by their count, so sources with lanes have to agree on it. A source with a different count is rejected.
+    pub fn add_source(&mut self, source: Box<dyn InputSource>) -> Result<()> {
+        let lanes = source.lanes().min(MAX_LANES);
+        if lanes != 0 {
+            if self.lanes != 0 && self.lanes != lanes {
+                bail!("The source has {lanes} lanes, but other sources have {}", self.lanes);
+            }
+            self.lanes = lanes;
+        }
+        self.key_flicks &= !source.has_flick();
+        self.sources.push(source);
+        Ok(())
     }
 
     #[inline]
     pub fn accuracy(&self) -> f64 {
-        self.inner.accuracy()
+        self.scoring.accuracy(&self.inner.state())
     }
 
     #[inline]
     pub fn score(&self) -> u32 {
-        self.inner.score()
+        self.scoring.score(&self.inner.state())
+    }
+
+    pub fn score_text(&self) -> String {
+        self.scoring.format(self.score())
+    }
+
+    /// Whether the scoring rule or the modifiers consider the play failed.
+    pub fn failed(&self) -> bool {
+        self.scoring.failed(&self.inner.state()) || self.modifiers.failed(self.inner.counts())
+    }
+
+    /// Notes judged so far, in order of judgement.
+    pub fn hits(&self) -> &[NoteHit] {
+        self.inner.hits()
     }
 
     pub(crate) fn on_new_frame() {
-        let mut handler = Handler(Vec::new(), 0, 0);
+        let mut handler = Handler(Vec::new());
         repeat_all_miniquad_input(&mut handler, *SUBSCRIBER_ID);
         handler.finalize();
         TOUCHES.with(|it| {
-            *it.borrow_mut() = (handler.0, handler.1, handler.2);
+            *it.borrow_mut() = handler.0;
         });
     }
 
@@ -314,11 +519,19 @@ impl Judge {
         }
     }
 
+    /// Starts logging every hit sound played by the judge along with its chart time, until [`Judge::take_hit_sounds`] is called.
+    pub fn log_hit_sounds() {
+        HIT_SOUNDS.with(|it| *it.borrow_mut() = Some(Vec::new()));
+    }
+
+    pub fn take_hit_sounds() -> Vec<(f32, HitSound)> {
+        HIT_SOUNDS.with(|it| it.borrow_mut().take().unwrap_or_default())
+    }
+
     pub fn get_touches() -> Vec<Touch> {
         TOUCHES.with(|it| {
             let tr = Self::touch_transform();
             it.borrow()
-                .0
                 .iter()
                 .cloned()
                 .map(|mut it| {
@@ -334,61 +547,76 @@ impl Judge {
             self.auto_play_update(res, chart);
             return;
         }
-        const X_DIFF_MAX: f32 = 0.21 / (16. / 9.) * 2.;
-        let spd = res.config.speed;
+        let frame = self.collect_input(res.time);
+        self.update_with(res, chart, bad_notes, &frame);
+        self.record.push(frame);
+    }
 
-        let t = res.time;
-        // TODO optimize
-        let mut touches: HashMap<u64, Touch> = {
-            let mut touches = touches();
-            let btn = MouseButton::Left;
-            let id = button_to_id(btn);
-            if is_mouse_button_pressed(btn) {
-                let p = mouse_position();
-                touches.push(Touch {
-                    id,
-                    phase: TouchPhase::Started,
-                    position: vec2(p.0, p.1),
-                });
-            } else if is_mouse_button_down(btn) {
-                let p = mouse_position();
-                touches.push(Touch {
-                    id,
-                    phase: TouchPhase::Moved,
-                    position: vec2(p.0, p.1),
-                });
-            } else if is_mouse_button_released(btn) {
-                let p = mouse_position();
-                touches.push(Touch {
-                    id,
-                    phase: TouchPhase::Ended,
-                    position: vec2(p.0, p.1),
-                });
-            }
-            let tr = Self::touch_transform();
-            touches
-                .into_iter()
-                .map(|mut it| {
-                    tr(&mut it);
-                    (it.id, it)
-                })
-                .collect()
+    fn collect_input(&mut self, time: f32) -> ReplayFrame {
+        let mut input = RawInput::default();
+        for source in &mut self.sources {
+            source.poll(time, &mut input);
+        }
+        let mut frame = ReplayFrame {
+            time,
+            touches: input.touches,
+            events: input.events,
+            key_delta: 0,
+            keys_down: 0,
+            lanes_down: 0,
+            lanes_held: 0,
+            flicks: 0,
         };
-        let (events, keys_down) = TOUCHES.with(|it| {
-            let guard = it.borrow();
-            (guard.0.clone(), guard.2)
-        });
-        self.key_down_count = self.key_down_count.saturating_add_signed(TOUCHES.with(|it| it.borrow().1));
-        {
-            fn to_local(Vec2 { x, y }: Vec2) -> Point {
-                Point::new(x / screen_width() * 2. - 1., y / screen_height() * 2. - 1.)
+        for (button, down) in input.buttons {
+            match (button, down) {
+                (Button::Tap, true) => {
+                    frame.keys_down += 1;
+                    frame.key_delta += 1;
+                }
+                (Button::Tap, false) => frame.key_delta -= 1,
+                (Button::Lane(lane), true) => {
+                    frame.lanes_down |= 1 << lane;
+                    self.lane_counts[lane as usize] += 1;
+                }
+                (Button::Lane(lane), false) => {
+                    let count = &mut self.lane_counts[lane as usize];
+                    *count = count.saturating_sub(1);
+                }
+                (Button::Flick, true) => frame.flicks += 1,
+                (Button::Flick, false) => {}
             }
+        }
+        frame.lanes_held = self
+            .lane_counts
+            .iter()
+            .enumerate()
+            .filter(|it| *it.1 != 0)
+            .fold(0, |bits, (i, _)| bits | 1 << i);
+        frame
+    }
+
+    /// Judges a single frame of input. This is what [`Judge::update`] does with live input, and what replays are fed through.
+    pub fn update_with(&mut self, res: &mut Resource, chart: &mut Chart, bad_notes: &mut Vec<BadNote>, frame: &ReplayFrame) {
+        let spd = res.config.speed;
+        let JudgeWindows {
+            perfect: limit_perfect,
+            good: limit_good,
+            bad: limit_bad,
+            dist_factor,
+            ..
+        } = self.windows;
+
+        let t = frame.time;
+        let mut touches: HashMap<u64, Touch> = frame.touches.iter().map(|it| (it.id, it.clone())).collect();
+        self.key_down_count = self.key_down_count.saturating_add_signed(frame.key_delta);
+        {
+            let events = &frame.events;
             let delta = (t / spd - self.last_time) as f64 / (events.len() + 1) as f64;
             let mut t = self.last_time as f64;
-            for Touch { id, phase, position: p } in events.into_iter() {
+            for Touch { id, phase, position: p } in events.iter().cloned() {
                 t += delta;
                 let t = t as f32;
-                let p = to_local(p);
+                let p = Point::new(p.x, p.y);
                 match phase {
                     TouchPhase::Started => {
                         self.trackers.insert(id, VelocityTracker::new(t, p));
@@ -415,9 +643,15 @@ impl Judge {
         let touches: Vec<Touch> = touches.into_values().collect();
         // pos[line][touch]
         let mut pos = Vec::<Vec<Option<Point>>>::with_capacity(chart.lines.len());
+        let mut trs = Vec::with_capacity(chart.lines.len());
+        // set the time of every line first so that parents are evaluated at the same time
+        for line in &mut chart.lines {
+            line.object.set_time(t);
+        }
         for id in 0..pos.capacity() {
-            chart.lines[id].object.set_time(t);
-            let inv = chart.lines[id].now_transform(res, &chart.lines).try_inverse().unwrap();
+            let tr = chart.lines[id].now_transform(res, &chart.lines);
+            trs.push(tr);
+            let inv = tr.try_inverse().unwrap();
             pos.push(
                 touches
                     .iter()
@@ -436,87 +670,103 @@ impl Judge {
                     .collect(),
             );
         }
+        if res.config.debug {
+            self.debug.get_or_insert_with(JudgeDebug::default).begin_frame();
+        }
         let mut judgements = Vec::new();
         // clicks & flicks
         for (id, touch) in touches.iter().enumerate() {
             let click = touch.phase == TouchPhase::Started;
             let flick = matches!(touch.phase, TouchPhase::Moved | TouchPhase::Stationary)
-                && self.trackers.get_mut(&touch.id).map_or(false, |it| it.has_flick(res));
-            if !(click || flick) {
-                continue;
-            }
-            let mut closest = (None, X_DIFF_MAX, LIMIT_BAD);
-            for (line_id, ((line, pos), (idx, st))) in chart.lines.iter_mut().zip(pos.iter()).zip(self.notes.iter_mut()).enumerate() {
-                let Some(pos) = pos[id] else { continue; };
-                for id in &idx[*st..] {
-                    let note = &mut line.notes[*id as usize];
-                    if !matches!(note.judge, JudgeStatus::NotJudged | JudgeStatus::PreJudge) {
-                        continue;
-                    }
-                    if !click && matches!(note.kind, NoteKind::Click | NoteKind::Hold { .. }) {
-                        continue;
-                    }
-                    let dt = (note.time - t) / spd;
-                    if dt >= closest.2 {
-                        break;
-                    }
-                    let x = &mut note.object.translation.0;
-                    x.set_time(t);
-                    let dist = (x.now() - pos.x).abs();
-                    if dist > X_DIFF_MAX {
+                && self.trackers.get_mut(&touch.id).map_or(false, |it| it.has_flick(res, &self.windows));
+            let mut matched = None;
+            let decision = 'decide: {
+                if !(click || flick) {
+                    break 'decide Decision::Idle;
+                }
+                let mut closest = (None, X_DIFF_MAX, limit_bad);
+                for (line_id, ((line, pos), (idx, st))) in chart.lines.iter_mut().zip(pos.iter()).zip(self.notes.iter_mut()).enumerate() {
+                    let Some(pos) = pos[id] else {
                         continue;
-                    }
-                    if dt.abs()
-                        > if matches!(note.kind, NoteKind::Click) {
-                            LIMIT_BAD - LIMIT_PERFECT * (dist - 0.9).max(0.)
+                    };
+                    for id in &idx[*st..] {
+                        let note = &mut line.notes[*id as usize];
+                        if !matches!(note.judge, JudgeStatus::NotJudged | JudgeStatus::PreJudge) {
+                            continue;
+                        }
+                        if !click && matches!(note.kind, NoteKind::Click | NoteKind::Hold { .. }) {
+                            continue;
+                        }
+                        let dt = (note.time - t) / spd;
+                        if dt >= closest.2 {
+                            break;
+                        }
+                        let x = &mut note.object.translation.0;
+                        x.set_time(t);
+                        let dist = (x.now() - pos.x).abs();
+                        if dist > X_DIFF_MAX {
+                            continue;
+                        }
+                        if dt.abs()
+                            > if matches!(note.kind, NoteKind::Click) {
+                                limit_bad - limit_perfect * (dist - 0.9).max(0.)
+                            } else {
+                                limit_good
+                            }
+                        {
+                            continue;
+                        }
+                        let dt = if matches!(note.kind, NoteKind::Flick | NoteKind::Drag) {
+                            dt + 0.05
                         } else {
-                            LIMIT_GOOD
+                            dt
+                        };
+                        if dt + (dist / res.note_width - 1.).max(0.) * dist_factor
+                            < closest.2 - 0.01 + (closest.1 / res.note_width - 1.).max(0.) * dist_factor
+                        {
+                            closest = (Some((line_id, *id)), dist, dt + 0.01);
                         }
-                    {
-                        continue;
-                    }
-                    let dt = if matches!(note.kind, NoteKind::Flick | NoteKind::Drag) {
-                        dt + 0.05
-                    } else {
-                        dt
-                    };
-                    if dt + (dist / res.note_width - 1.).max(0.) * DIST_FACTOR
-                        < closest.2 - 0.01 + (closest.1 / res.note_width - 1.).max(0.) * DIST_FACTOR
-                    {
-                        closest = (Some((line_id, *id)), dist, dt + 0.01);
                     }
                 }
-            }
-            if let (Some((line_id, id)), _, dt) = closest {
+                let (Some((line_id, id)), _, dt) = closest else {
+                    break 'decide Decision::NoNote;
+                };
+                matched = Some((line_id, id));
                 let line = &mut chart.lines[line_id];
                 if matches!(line.notes[id as usize].kind, NoteKind::Drag) {
-                    continue;
+                    break 'decide Decision::Drag;
                 }
                 if click {
                     // click & hold
                     let note = &mut line.notes[id as usize];
                     if matches!(note.kind, NoteKind::Flick) {
-                        continue; // to next loop
+                        break 'decide Decision::NotFlicked;
                     }
                     let dt = (dt - 0.01).abs();
-                    if dt <= LIMIT_GOOD || matches!(note.kind, NoteKind::Hold { .. }) {
+                    if dt <= limit_good || matches!(note.kind, NoteKind::Hold { .. }) {
                         match note.kind {
                             NoteKind::Click => {
                                 note.judge = JudgeStatus::Judged;
-                                judgements.push((if dt <= LIMIT_PERFECT { Judgement::Perfect } else { Judgement::Good }, line_id, id, None));
+                                let judgement = if dt <= limit_perfect { Judgement::Perfect } else { Judgement::Good };
+                                judgements.push((judgement, line_id, id, None));
+                                Decision::Judged(judgement)
                             }
                             NoteKind::Hold { .. } => {
-                                play_sfx(&mut res.sfx_click, &res.config);
-                                note.judge = JudgeStatus::Hold(dt <= LIMIT_PERFECT, t, (t - note.time) / spd, false, f32::INFINITY);
+                                play_hit_sound(res, HitSound::Click, t);
+                                note.judge = JudgeStatus::Hold(dt <= limit_perfect, t, (t - note.time) / spd, false, f32::INFINITY);
+                                Decision::HoldStarted
                             }
                             _ => unreachable!(),
-                        };
+                        }
                     } else {
                         // prevent extra judgements
                         if matches!(note.judge, JudgeStatus::NotJudged) {
                             // keep the note after bad judgement
                             line.notes[id as usize].judge = JudgeStatus::PreJudge;
                             judgements.push((Judgement::Bad, line_id, id, None));
+                            Decision::Bad
+                        } else {
+                            Decision::Ignored
                         }
                     }
                 } else {
@@ -525,11 +775,35 @@ impl Judge {
                     if let Some(tracker) = self.trackers.get_mut(&touch.id) {
                         tracker.consume_flick();
                     }
+                    Decision::Flicked
                 }
+            };
+            if let Some(debug) = &mut self.debug {
+                let target = matched.map(|(line_id, id)| {
+                    let line = &chart.lines[line_id];
+                    let x = line.notes[id as usize].object.translation.0.now();
+                    line.now_transform(res, &chart.lines).transform_point(&Point::new(x, 0.))
+                });
+                debug.push(
+                    TraceEntry {
+                        time: t,
+                        touch: touch.id,
+                        position: touch.position,
+                        phase: touch.phase,
+                        velocity: self.trackers.get(&touch.id).map_or_else(Vector::default, |it| it.speed()),
+                        note: matched,
+                        decision,
+                    },
+                    target,
+                );
             }
         }
-        for _ in 0..keys_down {
-            // find the earliest not judged click / hold note
+        // tap keys, then lane keys
+        let presses = std::iter::repeat(None)
+            .take(frame.keys_down as usize)
+            .chain((0..self.lanes).filter(|it| frame.lanes_down & 1 << it != 0).map(Some));
+        for lane in presses {
+            // find the earliest not judged click / hold note (in the lane)
             if let Some((line_id, id)) = chart
                 .lines
                 .iter()
@@ -541,7 +815,9 @@ impl Judge {
                         .cloned()
                         .find(|id| {
                             let note = &line.notes[*id as usize];
-                            matches!(note.judge, JudgeStatus::NotJudged) && matches!(note.kind, NoteKind::Click | NoteKind::Hold { .. })
+                            matches!(note.judge, JudgeStatus::NotJudged)
+                                && matches!(note.kind, NoteKind::Click | NoteKind::Hold { .. })
+                                && lane.map_or(true, |lane| lane_of(&trs[line_id], note.object.translation.0.now(), self.lanes) == lane)
                         })
                         .map(|id| (line_id, id))
                 })
@@ -549,14 +825,14 @@ impl Judge {
             {
                 let note = &mut chart.lines[line_id].notes[id as usize];
                 let dt = (t - note.time).abs() / spd;
-                if dt <= if matches!(note.kind, NoteKind::Click) { LIMIT_BAD } else { LIMIT_GOOD } {
+                if dt <= if matches!(note.kind, NoteKind::Click) { limit_bad } else { limit_good } {
                     match note.kind {
                         NoteKind::Click => {
                             note.judge = JudgeStatus::Judged;
                             judgements.push((
-                                if dt <= LIMIT_PERFECT {
+                                if dt <= limit_perfect {
                                     Judgement::Perfect
-                                } else if dt <= LIMIT_GOOD {
+                                } else if dt <= limit_good {
                                     Judgement::Good
                                 } else {
                                     Judgement::Bad
@@ -567,30 +843,58 @@ impl Judge {
                             ));
                         }
                         NoteKind::Hold { .. } => {
-                            play_sfx(&mut res.sfx_click, &res.config);
-                            note.judge = JudgeStatus::Hold(dt <= LIMIT_PERFECT, t, (t - note.time) / spd, false, f32::INFINITY);
+                            play_hit_sound(res, HitSound::Click, t);
+                            note.judge = JudgeStatus::Hold(dt <= limit_perfect, t, (t - note.time) / spd, false, f32::INFINITY);
                         }
                         _ => unreachable!(),
                     };
                 }
-            } else {
+            }
+        }
+        for _ in 0..frame.flicks {
+            // find the earliest not judged flick note
+            let Some((line_id, id)) = chart
+                .lines
+                .iter()
+                .zip(self.notes.iter())
+                .enumerate()
+                .filter_map(|(line_id, (line, (idx, st)))| {
+                    idx[*st..]
+                        .iter()
+                        .cloned()
+                        .find(|id| {
+                            let note = &line.notes[*id as usize];
+                            matches!(note.judge, JudgeStatus::NotJudged) && matches!(note.kind, NoteKind::Flick)
+                        })
+                        .map(|id| (line_id, id))
+                })
+                .min_by_key(|(line_id, id)| chart.lines[*line_id].notes[*id as usize].time.not_nan())
+            else {
+                break;
+            };
+            let note = &mut chart.lines[line_id].notes[id as usize];
+            if (t - note.time).abs() / spd > limit_good {
                 break;
             }
+            note.judge = JudgeStatus::PreJudge;
         }
+        let keys_held = |line_id: usize, x: f32| {
+            self.key_down_count != 0 || (self.lanes != 0 && frame.lanes_held & 1 << lane_of(&trs[line_id], x, self.lanes) != 0)
+        };
         for (line_id, ((line, pos), (idx, st))) in chart.lines.iter_mut().zip(pos.iter()).zip(self.notes.iter()).enumerate() {
             line.object.set_time(t);
             for id in &idx[*st..] {
                 let note = &mut line.notes[*id as usize];
                 if let NoteKind::Hold { end_time, .. } = &note.kind {
                     if let JudgeStatus::Hold(.., ref mut pre_judge, ref mut up_time) = note.judge {
-                        if (*end_time - t) / spd <= LIMIT_BAD {
+                        if (*end_time - t) / spd <= limit_bad {
                             *pre_judge = true;
                             continue;
                         }
                         let x = &mut note.object.translation.0;
                         x.set_time(t);
                         let x = x.now();
-                        if self.key_down_count == 0 && !pos.iter().any(|it| it.map_or(false, |it| (it.x - x).abs() <= X_DIFF_MAX)) {
+                        if !keys_held(line_id, x) && !pos.iter().any(|it| it.map_or(false, |it| (it.x - x).abs() <= X_DIFF_MAX)) {
                             if t > *up_time + UP_TOLERANCE {
                                 note.judge = JudgeStatus::Judged;
                                 judgements.push((Judgement::Miss, line_id, *id, None));
@@ -608,28 +912,30 @@ impl Judge {
                 }
                 // process miss
                 let dt = (t - note.time) / spd;
-                if dt > LIMIT_BAD {
+                if dt > limit_bad {
                     note.judge = JudgeStatus::Judged;
                     judgements.push((Judgement::Miss, line_id, *id, None));
                     continue;
                 }
-                if -dt > LIMIT_BAD {
+                if -dt > limit_bad {
                     break;
                 }
-                if !matches!(note.kind, NoteKind::Drag) && (self.key_down_count == 0 || !matches!(note.kind, NoteKind::Flick)) {
+                let drag = matches!(note.kind, NoteKind::Drag);
+                if !drag && !(self.key_flicks && matches!(note.kind, NoteKind::Flick)) {
                     continue;
                 }
                 let dt = dt.abs();
                 let x = &mut note.object.translation.0;
                 x.set_time(t);
                 let x = x.now();
-                if self.key_down_count != 0
-                    || pos.iter().any(|it| {
-                        it.map_or(false, |it| {
-                            let dx = (it.x - x).abs();
-                            dx <= X_DIFF_MAX && dt <= (LIMIT_BAD - LIMIT_PERFECT * (dx - 0.9).max(0.))
-                        })
-                    })
+                if keys_held(line_id, x)
+                    || (drag
+                        && pos.iter().any(|it| {
+                            it.map_or(false, |it| {
+                                let dx = (it.x - x).abs();
+                                dx <= X_DIFF_MAX && dt <= (limit_bad - limit_perfect * (dx - 0.9).max(0.))
+                            })
+                        }))
                 {
                     note.judge = JudgeStatus::PreJudge;
                 }
@@ -658,8 +964,8 @@ impl Judge {
                     } else {
                         None
                     };
-                    // TODO adjust: t + LIMIT_GOOD
-                    if matches!(note.kind, NoteKind::Click) && t + LIMIT_GOOD >= note.time {
+                    // TODO adjust: t + limit_good
+                    if matches!(note.kind, NoteKind::Click) && t + limit_good >= note.time {
                         note.judge = JudgeStatus::Judged;
                     }else {
                         note.judge = JudgeStatus::Judged;
@@ -676,14 +982,19 @@ impl Judge {
             let line = &chart.lines[line_id];
             let note = &line.notes[id as usize];
             let line_tr = line.now_transform(res, &chart.lines);
-            self.commit(
+            self.commit(NoteHit {
+                line: line_id,
+                id,
+                kind: note.kind.clone(),
+                time: note.time,
                 judgement,
-                if matches!(judgement, Judgement::Good | Judgement::Bad) {
-                    Some(diff.unwrap_or((t - note.time) / spd))
-                } else {
-                    None
+                diff: match judgement {
+                    Judgement::Good | Judgement::Bad => Some(diff.unwrap_or((t - note.time) / spd)),
+                    // drags and flicks are judged when they reach the line, so there's no timing to speak of
+                    Judgement::Perfect if matches!(note.kind, NoteKind::Click) => Some((t - note.time) / spd),
+                    _ => diff,
                 },
-            );
+            });
             if matches!(note.kind, NoteKind::Hold { .. }) {
                 continue;
             }
@@ -721,13 +1032,13 @@ impl Judge {
                 }
                 _ => false,
             } {
-                if let Some(sfx) = match note.kind {
-                    NoteKind::Click => Some(&mut res.sfx_click),
-                    NoteKind::Drag => Some(&mut res.sfx_drag),
-                    NoteKind::Flick => Some(&mut res.sfx_flick),
+                if let Some(sound) = match note.kind {
+                    NoteKind::Click => Some(HitSound::Click),
+                    NoteKind::Drag => Some(HitSound::Drag),
+                    NoteKind::Flick => Some(HitSound::Flick),
                     _ => None,
                 } {
-                    play_sfx(sfx, &res.config);
+                    play_hit_sound(res, sound, t);
                 }
             }
         }
@@ -765,7 +1076,7 @@ impl Judge {
                     break;
                 }
                 note.judge = if matches!(note.kind, NoteKind::Hold { .. }) {
-                    play_sfx(&mut res.sfx_click, &res.config);
+                    play_hit_sound(res, HitSound::Click, t);
                     JudgeStatus::Hold(true, t, (t - note.time) / spd, false, f32::INFINITY)
                 } else {
                     judgements.push((line_id, *id));
@@ -780,33 +1091,45 @@ impl Judge {
             }
         }
         for (line_id, id) in judgements.into_iter() {
-            self.commit(Judgement::Perfect, None);
             let (note_transform, note_kind) = {
                 let line = &mut chart.lines[line_id];
                 let note = &mut line.notes[id as usize];
                 let nt = if matches!(note.kind, NoteKind::Hold { .. }) { t } else { note.time };
                 line.object.set_time(nt);
                 note.object.set_time(nt);
+                self.commit(NoteHit {
+                    line: line_id,
+                    id,
+                    kind: note.kind.clone(),
+                    time: note.time,
+                    judgement: Judgement::Perfect,
+                    diff: None,
+                });
                 (note.object.now(res), note.kind.clone())
             };
             let line = &chart.lines[line_id];
             res.with_model(line.now_transform(res, &chart.lines) * note_transform, |res| {
                 res.emit_at_origin(line.notes[id as usize].rotation(line), JUDGE_LINE_PERFECT_COLOR)
             });
-            if let Some(sfx) = match note_kind {
-                NoteKind::Click => Some(&mut res.sfx_click),
-                NoteKind::Drag => Some(&mut res.sfx_drag),
-                NoteKind::Flick => Some(&mut res.sfx_flick),
+            if let Some(sound) = match note_kind {
+                NoteKind::Click => Some(HitSound::Click),
+                NoteKind::Drag => Some(HitSound::Drag),
+                NoteKind::Flick => Some(HitSound::Flick),
                 _ => None,
             } {
-                play_sfx(sfx, &res.config);
+                play_hit_sound(res, sound, t);
             }
         }
     }
 
     #[inline]
     pub fn result(&self) -> PlayResult {
-        self.inner.result()
+        PlayResult {
+            judge_profile: self.profile,
+            modifiers: self.modifiers,
+            failed: self.failed(),
+            ..self.inner.result(self.scoring.as_ref())
+        }
     }
 
     #[inline]
@@ -820,7 +1143,13 @@ impl Judge {
     }
 }
 
-struct Handler(Vec<Touch>, i32, u32);
+/// The lane (out of `lanes`) of the point at `x` on a line with transform `tr`.
+fn lane_of(tr: &Matrix, x: f32, lanes: usize) -> usize {
+    let x = tr.transform_point(&Point::new(x, 0.)).x;
+    (((x + 1.) / 2. * lanes as f32).max(0.) as usize).min(lanes - 1)
+}
+
+struct Handler(Vec<Touch>);
 impl Handler {
     fn finalize(&mut self) {
         if is_mouse_button_down(MouseButton::Left) {
@@ -869,17 +1198,6 @@ impl EventHandler for Handler {
             position: vec2(x, y),
         });
     }
-
-    fn key_down_event(&mut self, _ctx: &mut miniquad::Context, _keycode: KeyCode, _keymods: miniquad::KeyMods, repeat: bool) {
-        if !repeat {
-            self.1 += 1;
-            self.2 += 1;
-        }
-    }
-
-    fn key_up_event(&mut self, _ctx: &mut miniquad::Context, _keycode: KeyCode, _keymods: miniquad::KeyMods) {
-        self.1 -= 1;
-    }
 }
 
 #[derive(Default)]
@@ -891,4 +1209,45 @@ pub struct PlayResult {
     pub counts: [u32; 4],
     pub early: u32,
     pub late: u32,
+    pub judge_profile: JudgeProfile,
+    /// Modifiers active during the play.
+    pub modifiers: Modifiers,
+    /// Name of the scoring rule.
+    pub scoring: &'static str,
+    pub score_text: String,
+    pub max_score: u32,
+    pub failed: bool,
+    /// Every judged note, in the order they were judged.
+    pub hits: Vec<NoteHit>,
+}
+
+impl PlayResult {
+    /// Timing errors of notes hit with exact timing.
+    pub fn diffs(&self) -> impl Iterator<Item = f32> + '_ {
+        self.hits.iter().filter_map(|it| it.diff)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TimingStats {
+    pub count: usize,
+    pub mean: f32,
+    pub std_dev: f32,
+}
+
+impl TimingStats {
+    pub fn new(diffs: impl IntoIterator<Item = f32>) -> Self {
+        let (count, sum, sum_sq) = diffs
+            .into_iter()
+            .fold((0, 0., 0.), |(count, sum, sum_sq), diff| (count + 1, sum + diff as f64, sum_sq + (diff * diff) as f64));
+        if count == 0 {
+            return Self::default();
+        }
+        let mean = sum / count as f64;
+        Self {
+            count,
+            mean: mean as f32,
+            std_dev: (sum_sq / count as f64 - mean * mean).max(0.).sqrt() as f32,
+        }
+    }
 }
@@ -0,0 +1,178 @@
+//! Diagnostics of live judging, enabled by [`Config::debug`](crate::config::Config::debug).
+
+use super::{Judge, JudgeStatus, Judgement, X_DIFF_MAX};
+use crate::{
+    core::{Chart, Matrix, Point, Resource, Vector, JUDGE_LINE_GOOD_COLOR, JUDGE_LINE_PERFECT_COLOR},
+    ui::Ui,
+};
+use macroquad::prelude::*;
+use std::fmt::Write;
+
+/// Length (in seconds) of the speed vectors drawn, which the flick threshold circle is scaled by as well.
+const SPEED_SCALE: f32 = 0.1;
+
+/// What the judge did with a touch.
+#[derive(Clone, Copy, Debug)]
+pub enum Decision {
+    /// Neither a click nor a flick.
+    Idle,
+    /// No note in reach.
+    NoNote,
+    /// The closest note is a drag, which is judged by position only.
+    Drag,
+    /// A click on a flick note.
+    NotFlicked,
+    Judged(Judgement),
+    HoldStarted,
+    /// A flick, which judges the note once it reaches the line.
+    Flicked,
+    /// A click too far from the note in time.
+    Bad,
+    /// A click on a note already judged bad.
+    Ignored,
+}
+
+impl Decision {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::NoNote => "no-note",
+            Self::Drag => "drag",
+            Self::NotFlicked => "not-flicked",
+            Self::Judged(Judgement::Perfect) => "perfect",
+            Self::Judged(Judgement::Good) => "good",
+            Self::Judged(Judgement::Bad) => "bad",
+            Self::Judged(Judgement::Miss) => "miss",
+            Self::HoldStarted => "hold-started",
+            Self::Flicked => "flicked",
+            Self::Bad => "bad",
+            Self::Ignored => "ignored",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    /// Chart time of the frame.
+    pub time: f32,
+    pub touch: u64,
+    /// In judge coordinates, see [`Judge::get_touches`].
+    pub position: Vec2,
+    pub phase: TouchPhase,
+    /// Velocity of the touch in normalized screen units per second, as seen by its [`VelocityTracker`](super::VelocityTracker).
+    pub velocity: Vector,
+    /// Line and index of the note the touch was matched to.
+    pub note: Option<(usize, u32)>,
+    pub decision: Decision,
+}
+
+#[derive(Default)]
+pub struct JudgeDebug {
+    /// Every touch of every frame since the last reset.
+    pub trace: Vec<TraceEntry>,
+    /// Where the trace of the last frame starts.
+    frame_start: usize,
+    /// Positions of the matched notes of the last frame on the chart, aligned with its trace.
+    targets: Vec<Option<Point>>,
+}
+
+impl JudgeDebug {
+    pub(super) fn begin_frame(&mut self) {
+        self.frame_start = self.trace.len();
+        self.targets.clear();
+    }
+
+    pub(super) fn push(&mut self, entry: TraceEntry, target: Option<Point>) {
+        self.trace.push(entry);
+        self.targets.push(target);
+    }
+
+    /// The trace as CSV, to be attached to bug reports.
+    pub fn to_csv(&self) -> String {
+        let mut res = "time,touch,x,y,phase,vx,vy,line,note,decision\n".to_owned();
+        for entry in &self.trace {
+            let (line, note) = entry
+                .note
+                .map_or((String::new(), String::new()), |(line, note)| (line.to_string(), note.to_string()));
+            writeln!(
+                res,
+                "{:.4},{},{:.4},{:.4},{},{:.3},{:.3},{line},{note},{}",
+                entry.time,
+                entry.touch as i64,
+                entry.position.x,
+                entry.position.y,
+                match entry.phase {
+                    TouchPhase::Started => "started",
+                    TouchPhase::Stationary => "stationary",
+                    TouchPhase::Moved => "moved",
+                    TouchPhase::Ended => "ended",
+                    TouchPhase::Cancelled => "cancelled",
+                },
+                entry.velocity.x,
+                entry.velocity.y,
+                entry.decision.name()
+            )
+            .unwrap();
+        }
+        res
+    }
+}
+
+impl Judge {
+    /// Draws the touches of the last frame with their speeds and matched notes, and the judgement windows of upcoming notes.
+    pub fn render_debug(&self, ui: &mut Ui, res: &mut Resource, chart: &Chart) {
+        let spd = res.config.speed;
+        let windows = self.windows;
+        let flip = Matrix::identity().append_nonuniform_scaling(&Vector::new(1., -1.));
+        res.apply_model_of(&flip, |res| {
+            for (line, (idx, st)) in chart.lines.iter().zip(self.notes.iter()) {
+                if !res.config.debug_line(line.group.as_deref()) {
+                    continue;
+                }
+                let line_tr = line.now_transform(res, &chart.lines);
+                for id in &idx[*st..] {
+                    let note = &line.notes[*id as usize];
+                    if note.time - res.time > windows.bad * spd {
+                        break;
+                    }
+                    let dt = ((note.time - res.time) / spd).abs();
+                    if !matches!(note.judge, JudgeStatus::NotJudged) || dt > windows.bad {
+                        continue;
+                    }
+                    let color = if dt <= windows.perfect {
+                        JUDGE_LINE_PERFECT_COLOR
+                    } else if dt <= windows.good {
+                        JUDGE_LINE_GOOD_COLOR
+                    } else {
+                        RED
+                    };
+                    let x = note.object.translation.0.now();
+                    res.with_model(line_tr, |res| {
+                        res.apply_model(|_| draw_rectangle_lines(x - X_DIFF_MAX, -0.02, X_DIFF_MAX * 2., 0.04, 0.006, color));
+                    });
+                }
+            }
+        });
+
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        let threshold = windows.flick_threshold(res);
+        for (entry, target) in debug.trace[debug.frame_start..].iter().zip(&debug.targets) {
+            let p = entry.position;
+            let v = entry.velocity;
+            draw_circle_lines(p.x, p.y, 0.03, 0.004, WHITE);
+            draw_circle_lines(p.x, p.y, threshold * SPEED_SCALE, 0.002, GRAY);
+            let color = if v.norm() >= threshold { GREEN } else { WHITE };
+            draw_line(p.x, p.y, p.x + v.x * SPEED_SCALE, p.y + v.y * SPEED_SCALE, 0.004, color);
+            if let Some(target) = target {
+                draw_line(p.x, p.y, target.x, -target.y, 0.003, YELLOW);
+            }
+            ui.text(format!("#{} {:.2} {}", entry.touch as i64, v.norm(), entry.decision.name()))
+                .pos(p.x + 0.04, p.y)
+                .anchor(0., 0.5)
+                .size(0.4)
+                .draw();
+        }
+    }
+}
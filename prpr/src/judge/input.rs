@@ -0,0 +1,185 @@
+use super::{button_to_id, Judge, TOUCHES};
+use crate::config::{Config, KeyBindings, RESERVED_KEYS};
+use macroquad::prelude::*;
+use miniquad::MouseButton;
+
+mod midi;
+pub use midi::MidiFileSource;
+#[cfg(feature = "midi")]
+pub use midi::MidiSource;
+
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(feature = "gamepad")]
+pub use gamepad::GamepadSource;
+
+/// Lanes are stored as bits of a `u32` in replays.
+pub const MAX_LANES: usize = 32;
+
+/// What a key, gamepad button or MIDI note does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    /// Judges the earliest note.
+    Tap,
+    /// Judges the earliest note in the lane.
+    Lane(u8),
+    /// Judges the earliest flick note.
+    Flick,
+}
+
+/// Input gathered from every source in a frame.
+#[derive(Default)]
+pub struct RawInput {
+    /// See [`ReplayFrame::touches`](crate::replay::ReplayFrame::touches).
+    pub touches: Vec<Touch>,
+    /// See [`ReplayFrame::events`](crate::replay::ReplayFrame::events).
+    pub events: Vec<Touch>,
+    /// Presses (`true`) and releases of buttons, in order.
+    pub buttons: Vec<(Button, bool)>,
+}
+
+/// Where [`Judge`] gets input from, see [`Judge::add_source`].
+pub trait InputSource {
+    /// Adds the input since the last poll. `time` is the current chart time.
+    fn poll(&mut self, time: f32, input: &mut RawInput);
+
+    /// Number of lanes the source has buttons for.
+    fn lanes(&self) -> usize {
+        0
+    }
+
+    /// Whether the source has a flick button.
+    fn has_flick(&self) -> bool {
+        false
+    }
+}
+
+/// Which keys, gamepad buttons or MIDI notes trigger which [`Button`]s.
+#[derive(Clone, Debug)]
+pub struct ButtonMap<T> {
+    pub tap: Vec<T>,
+    pub lanes: Vec<T>,
+    pub flick: Option<T>,
+}
+
+impl<T: PartialEq> ButtonMap<T> {
+    /// Lanes beyond [`MAX_LANES`] are dropped.
+    pub fn new(tap: Vec<T>, mut lanes: Vec<T>, flick: Option<T>) -> Self {
+        lanes.truncate(MAX_LANES);
+        Self { tap, lanes, flick }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&T, Button)> {
+        self.tap
+            .iter()
+            .map(|it| (it, Button::Tap))
+            .chain(self.lanes.iter().enumerate().map(|(i, it)| (it, Button::Lane(i as u8))))
+            .chain(self.flick.iter().map(|it| (it, Button::Flick)))
+    }
+
+    pub fn get(&self, code: &T) -> Option<Button> {
+        self.iter().find(|it| it.0 == code).map(|it| it.1)
+    }
+}
+
+/// Touches and the left mouse button.
+pub struct TouchSource;
+
+impl InputSource for TouchSource {
+    fn poll(&mut self, _time: f32, input: &mut RawInput) {
+        // TODO optimize
+        let mut touches = touches();
+        let btn = MouseButton::Left;
+        let id = button_to_id(btn);
+        if is_mouse_button_pressed(btn) {
+            let p = mouse_position();
+            touches.push(Touch {
+                id,
+                phase: TouchPhase::Started,
+                position: vec2(p.0, p.1),
+            });
+        } else if is_mouse_button_down(btn) {
+            let p = mouse_position();
+            touches.push(Touch {
+                id,
+                phase: TouchPhase::Moved,
+                position: vec2(p.0, p.1),
+            });
+        } else if is_mouse_button_released(btn) {
+            let p = mouse_position();
+            touches.push(Touch {
+                id,
+                phase: TouchPhase::Ended,
+                position: vec2(p.0, p.1),
+            });
+        }
+        let tr = Judge::touch_transform();
+        input.touches.extend(touches.into_iter().map(|mut it| {
+            tr(&mut it);
+            it
+        }));
+        let (w, h) = (screen_width(), screen_height());
+        input.events.extend(TOUCHES.with(|it| it.borrow().clone()).into_iter().map(|mut it| {
+            it.position = vec2(it.position.x / w * 2. - 1., it.position.y / h * 2. - 1.);
+            it
+        }));
+    }
+}
+
+/// Keys as bound in [`KeyBindings`]. Unknown and reserved keys are dropped.
+pub struct KeyboardSource(ButtonMap<KeyCode>);
+
+impl KeyboardSource {
+    pub fn new(bindings: &KeyBindings) -> Self {
+        let code = |name: &String| KeyBindings::key_code(name).filter(|it| !RESERVED_KEYS.contains(it));
+        Self(ButtonMap::new(
+            bindings.tap.iter().filter_map(code).collect(),
+            bindings.lanes.iter().filter_map(code).collect(),
+            bindings.flick.as_ref().and_then(code),
+        ))
+    }
+}
+
+impl InputSource for KeyboardSource {
+    fn poll(&mut self, _time: f32, input: &mut RawInput) {
+        for (code, button) in self.0.iter() {
+            if is_key_pressed(*code) {
+                input.buttons.push((button, true));
+            }
+            if is_key_released(*code) {
+                input.buttons.push((button, false));
+            }
+        }
+    }
+
+    fn lanes(&self) -> usize {
+        self.0.lanes.len()
+    }
+
+    fn has_flick(&self) -> bool {
+        self.0.flick.is_some()
+    }
+}
+
+/// The input sources enabled in `config`. Sources that fail to open are skipped with a warning.
+pub fn sources(config: &Config) -> Vec<Box<dyn InputSource>> {
+    let mut res: Vec<Box<dyn InputSource>> = Vec::new();
+    if !config.key_bindings.keyboard_only {
+        res.push(Box::new(TouchSource));
+    }
+    res.push(Box::new(KeyboardSource::new(&config.key_bindings)));
+    #[cfg(feature = "gamepad")]
+    if let Some(bindings) = &config.gamepad {
+        match GamepadSource::new(bindings) {
+            Ok(source) => res.push(Box::new(source)),
+            Err(err) => warn!("Failed to open gamepads: {:?}", err),
+        }
+    }
+    if let Some(bindings) = &config.midi {
+        match midi::open(bindings) {
+            Ok(source) => res.push(source),
+            Err(err) => warn!("Failed to open MIDI input: {:?}", err),
+        }
+    }
+    res
+}
@@ -0,0 +1,61 @@
+use super::{ButtonMap, InputSource, RawInput};
+use crate::config::GamepadBindings;
+use anyhow::{anyhow, Result};
+use gilrs::{Button as PadButton, EventType, Gilrs};
+
+#[rustfmt::skip]
+const BUTTON_NAMES: [(PadButton, &str); 17] = [
+    (PadButton::South, "South"), (PadButton::East, "East"), (PadButton::North, "North"), (PadButton::West, "West"),
+    (PadButton::LeftTrigger, "LeftTrigger"), (PadButton::LeftTrigger2, "LeftTrigger2"),
+    (PadButton::RightTrigger, "RightTrigger"), (PadButton::RightTrigger2, "RightTrigger2"),
+    (PadButton::Select, "Select"), (PadButton::Start, "Start"), (PadButton::Mode, "Mode"),
+    (PadButton::LeftThumb, "LeftThumb"), (PadButton::RightThumb, "RightThumb"),
+    (PadButton::DPadUp, "DPadUp"), (PadButton::DPadDown, "DPadDown"), (PadButton::DPadLeft, "DPadLeft"), (PadButton::DPadRight, "DPadRight"),
+];
+
+fn button(name: &str) -> Option<PadButton> {
+    let name = name.trim();
+    BUTTON_NAMES.iter().find(|it| it.1.eq_ignore_ascii_case(name)).map(|it| it.0)
+}
+
+/// Buttons of every connected gamepad. Unknown button names are dropped.
+pub struct GamepadSource {
+    gilrs: Gilrs,
+    map: ButtonMap<PadButton>,
+}
+
+impl GamepadSource {
+    pub fn new(bindings: &GamepadBindings) -> Result<Self> {
+        Ok(Self {
+            gilrs: Gilrs::new().map_err(|err| anyhow!("{err}"))?,
+            map: ButtonMap::new(
+                bindings.tap.iter().filter_map(|it| button(it)).collect(),
+                bindings.lanes.iter().filter_map(|it| button(it)).collect(),
+                bindings.flick.as_deref().and_then(button),
+            ),
+        })
+    }
+}
+
+impl InputSource for GamepadSource {
+    fn poll(&mut self, _time: f32, input: &mut RawInput) {
+        while let Some(event) = self.gilrs.next_event() {
+            let (code, down) = match event.event {
+                EventType::ButtonPressed(code, _) => (code, true),
+                EventType::ButtonReleased(code, _) => (code, false),
+                _ => continue,
+            };
+            if let Some(button) = self.map.get(&code) {
+                input.buttons.push((button, down));
+            }
+        }
+    }
+
+    fn lanes(&self) -> usize {
+        self.map.lanes.len()
+    }
+
+    fn has_flick(&self) -> bool {
+        self.map.flick.is_some()
+    }
+}
@@ -0,0 +1,230 @@
+use super::{ButtonMap, InputSource, RawInput};
+use crate::config::MidiBindings;
+use anyhow::{Context, Result};
+
+fn button_map(bindings: &MidiBindings) -> ButtonMap<u8> {
+    ButtonMap::new(bindings.tap.clone(), bindings.lanes.clone(), bindings.flick)
+}
+
+fn push_note(map: &ButtonMap<u8>, input: &mut RawInput, note: u8, on: bool) {
+    if let Some(button) = map.get(&note) {
+        input.buttons.push((button, on));
+    }
+}
+
+/// Note events read from a text file, one `<time> <note> <velocity>` per line with the time in chart seconds and velocity 0
+/// for note-offs. Lines starting with `#` are comments. Useful for testing bindings without a device.
+pub struct MidiFileSource {
+    map: ButtonMap<u8>,
+    events: Vec<(f32, u8, bool)>,
+    /// Index of the first event not yet polled.
+    cursor: usize,
+    last_time: f32,
+}
+
+impl MidiFileSource {
+    pub fn new(bindings: &MidiBindings, text: &str) -> Result<Self> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parse = || -> Option<(f32, u8, bool)> {
+                let mut it = line.split_whitespace();
+                let time = it.next()?.parse().ok()?;
+                let note = it.next()?.parse().ok()?;
+                let velocity: u8 = it.next()?.parse().ok()?;
+                it.next().is_none().then_some((time, note, velocity != 0))
+            };
+            events.push(parse().with_context(|| format!("Invalid MIDI event at line {}", index + 1))?);
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self {
+            map: button_map(bindings),
+            events,
+            cursor: 0,
+            last_time: f32::NEG_INFINITY,
+        })
+    }
+}
+
+impl InputSource for MidiFileSource {
+    fn poll(&mut self, time: f32, input: &mut RawInput) {
+        if time < self.last_time {
+            // seeked backwards
+            self.cursor = self.events.partition_point(|it| it.0 < time);
+        }
+        self.last_time = time;
+        while let Some((_, note, on)) = self.events.get(self.cursor).filter(|it| it.0 <= time) {
+            push_note(&self.map, input, *note, *on);
+            self.cursor += 1;
+        }
+    }
+
+    fn lanes(&self) -> usize {
+        self.map.lanes.len()
+    }
+
+    fn has_flick(&self) -> bool {
+        self.map.flick.is_some()
+    }
+}
+
+/// Note-ons and note-offs of a MIDI input port.
+#[cfg(feature = "midi")]
+pub struct MidiSource {
+    map: ButtonMap<u8>,
+    receiver: std::sync::mpsc::Receiver<(u8, bool)>,
+    _connection: midir::MidiInputConnection<()>,
+}
+
+#[cfg(feature = "midi")]
+impl MidiSource {
+    pub fn new(bindings: &MidiBindings) -> Result<Self> {
+        use anyhow::anyhow;
+        use midir::MidiInput;
+
+        let input = MidiInput::new("prpr").map_err(|err| anyhow!("{err}"))?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let callback = move |_: u64, message: &[u8], _: &mut ()| {
+            let &[status, note, velocity, ..] = message else {
+                return;
+            };
+            let on = match status & 0xf0 {
+                0x90 => velocity != 0,
+                0x80 => false,
+                _ => return,
+            };
+            let _ = sender.send((note, on));
+        };
+        let connection = if bindings.virtual_port {
+            #[cfg(unix)]
+            {
+                use midir::os::unix::VirtualInput;
+                input.create_virtual("prpr", callback, ()).map_err(|err| anyhow!("{err}"))?
+            }
+            #[cfg(not(unix))]
+            anyhow::bail!("Virtual MIDI ports are not supported on this platform")
+        } else {
+            let port = input
+                .ports()
+                .into_iter()
+                .find(|port| {
+                    bindings
+                        .port
+                        .as_ref()
+                        .map_or(true, |name| input.port_name(port).map_or(false, |it| it.contains(name.as_str())))
+                })
+                .context("No MIDI input port found")?;
+            input.connect(&port, "prpr", callback, ()).map_err(|err| anyhow!("{err}"))?
+        };
+        Ok(Self {
+            map: button_map(bindings),
+            receiver,
+            _connection: connection,
+        })
+    }
+}
+
+#[cfg(feature = "midi")]
+impl InputSource for MidiSource {
+    fn poll(&mut self, _time: f32, input: &mut RawInput) {
+        for (note, on) in self.receiver.try_iter() {
+            push_note(&self.map, input, note, on);
+        }
+    }
+
+    fn lanes(&self) -> usize {
+        self.map.lanes.len()
+    }
+
+    fn has_flick(&self) -> bool {
+        self.map.flick.is_some()
+    }
+}
+
+/// Opens the recording if there is one, or the device otherwise.
+pub fn open(bindings: &MidiBindings) -> Result<Box<dyn InputSource>> {
+    if let Some(path) = &bindings.recording {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read MIDI recording {path}"))?;
+        return Ok(Box::new(MidiFileSource::new(bindings, &text)?));
+    }
+    #[cfg(feature = "midi")]
+    {
+        Ok(Box::new(MidiSource::new(bindings)?))
+    }
+    #[cfg(not(feature = "midi"))]
+    anyhow::bail!("MIDI support is not enabled in this build")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Modifiers,
+        core::ChartExtra,
+        judge::{input::Button, Judge, JudgeProfile, PhigrosScoring},
+        parse::parse_pec,
+    };
+
+    const RECORDING: &str = "# time note velocity
+1.5 64 0
+0.5 60 100
+1 64 90
+
+1.2 60 0
+2 72 127
+3 50 100";
+
+    fn bindings(lanes: Vec<u8>) -> MidiBindings {
+        MidiBindings {
+            tap: vec![60],
+            lanes,
+            flick: Some(72),
+            ..Default::default()
+        }
+    }
+
+    fn poll(source: &mut MidiFileSource, time: f32) -> Vec<(Button, bool)> {
+        let mut input = RawInput::default();
+        source.poll(time, &mut input);
+        input.buttons
+    }
+
+    #[test]
+    fn recording() {
+        let mut source = MidiFileSource::new(&bindings(vec![62, 64]), RECORDING).unwrap();
+        assert_eq!((source.lanes(), source.has_flick()), (2, true));
+        assert!(poll(&mut source, 0.).is_empty());
+        assert_eq!(poll(&mut source, 1.), [(Button::Tap, true), (Button::Lane(1), true)]);
+        assert!(poll(&mut source, 1.).is_empty());
+        assert_eq!(poll(&mut source, 2.5), [(Button::Tap, false), (Button::Lane(1), false), (Button::Flick, true)]);
+        // note 50 is not bound
+        assert!(poll(&mut source, 10.).is_empty());
+        // seeking backwards plays the events after the new time again
+        assert!(poll(&mut source, 1.3).is_empty());
+        assert_eq!(poll(&mut source, 2.), [(Button::Lane(1), false), (Button::Flick, true)]);
+    }
+
+    #[test]
+    fn invalid_recording() {
+        for (text, line) in [("0.5 60", 1), ("# comment\n1 60 100 0", 2), ("1 60 100\n\nx 60 100", 3), ("1 300 100", 1)] {
+            let err = format!("{:?}", MidiFileSource::new(&bindings(Vec::new()), text).err().unwrap());
+            assert!(err.contains(&format!("Invalid MIDI event at line {line}")), "{err}");
+        }
+    }
+
+    #[test]
+    fn lane_counts() {
+        let chart = parse_pec("0\nbp 0 120\ncv 0 0 10\nn1 0 1 0 1 0", ChartExtra::default()).unwrap();
+        let mut judge = Judge::new(&chart, JudgeProfile::default(), Modifiers::default(), Box::new(PhigrosScoring));
+        let source = |lanes: Vec<u8>| Box::new(MidiFileSource::new(&bindings(lanes), "").unwrap());
+        judge.add_source(source(Vec::new())).unwrap();
+        judge.add_source(source(vec![61, 62])).unwrap();
+        judge.add_source(source(vec![63, 64])).unwrap();
+        assert!(judge.add_source(source(vec![65, 66, 67])).is_err());
+        assert_eq!(judge.lanes, 2);
+        assert!(!judge.key_flicks);
+    }
+}
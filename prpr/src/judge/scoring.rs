@@ -0,0 +1,276 @@
+use super::{JudgeWindows, Judgement, NoteHit};
+use serde::{Deserialize, Serialize};
+
+/// What a [`ScoringRule`] gets to see of the play so far.
+pub struct ScoreState<'a> {
+    pub num_of_notes: u32,
+    pub counts: [u32; 4],
+    pub combo: u32,
+    pub max_combo: u32,
+    pub hits: &'a [NoteHit],
+}
+
+pub trait ScoringRule {
+    fn name(&self) -> &'static str;
+
+    fn accuracy(&self, state: &ScoreState) -> f64;
+
+    fn score(&self, state: &ScoreState) -> u32;
+
+    /// The score of a play with all perfects.
+    fn max_score(&self, _state: &ScoreState) -> u32 {
+        1000000
+    }
+
+    fn format(&self, score: u32) -> String {
+        format!("{score:07}")
+    }
+
+    /// Whether the play has failed and should end right away.
+    fn failed(&self, _state: &ScoreState) -> bool {
+        false
+    }
+}
+
+fn phigros_accuracy(state: &ScoreState) -> f64 {
+    if state.num_of_notes == 0 {
+        return 1.;
+    }
+    (state.counts[0] as f64 + state.counts[1] as f64 * 0.65) / state.num_of_notes as f64
+}
+
+/// 90% accuracy (with goods weighted 0.65) plus 10% max combo.
+pub struct PhigrosScoring;
+
+impl ScoringRule for PhigrosScoring {
+    fn name(&self) -> &'static str {
+        "Phigros"
+    }
+
+    fn accuracy(&self, state: &ScoreState) -> f64 {
+        phigros_accuracy(state)
+    }
+
+    fn score(&self, state: &ScoreState) -> u32 {
+        const TOTAL: u32 = 1000000;
+        if state.counts[0] == state.num_of_notes {
+            TOTAL
+        } else {
+            let score = (0.9 * phigros_accuracy(state) + state.max_combo as f64 / state.num_of_notes as f64 * 0.1) * TOTAL as f64;
+            score.round() as u32
+        }
+    }
+}
+
+/// 3 points for a critical perfect (within half the perfect window), 2 for other perfects and 1 for goods.
+///
+/// Perfects without exact timing (drags, flicks and autoplay) count as critical.
+pub struct ExScoring {
+    critical: f32,
+}
+
+impl ExScoring {
+    pub fn new(windows: &JudgeWindows) -> Self {
+        Self {
+            critical: windows.perfect / 2.,
+        }
+    }
+}
+
+impl ScoringRule for ExScoring {
+    fn name(&self) -> &'static str {
+        "EX"
+    }
+
+    fn accuracy(&self, state: &ScoreState) -> f64 {
+        let max = self.max_score(state);
+        if max == 0 {
+            return 1.;
+        }
+        self.score(state) as f64 / max as f64
+    }
+
+    fn score(&self, state: &ScoreState) -> u32 {
+        state
+            .hits
+            .iter()
+            .map(|it| match it.judgement {
+                Judgement::Perfect if it.diff.map_or(true, |diff| diff.abs() <= self.critical) => 3,
+                Judgement::Perfect => 2,
+                Judgement::Good => 1,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn max_score(&self, state: &ScoreState) -> u32 {
+        state.num_of_notes * 3
+    }
+
+    fn format(&self, score: u32) -> String {
+        score.to_string()
+    }
+}
+
+/// The accuracy alone, in units of 0.0001%.
+pub struct AccuracyScoring;
+
+impl ScoringRule for AccuracyScoring {
+    fn name(&self) -> &'static str {
+        "Accuracy"
+    }
+
+    fn accuracy(&self, state: &ScoreState) -> f64 {
+        phigros_accuracy(state)
+    }
+
+    fn score(&self, state: &ScoreState) -> u32 {
+        (phigros_accuracy(state) * 1000000.).round() as u32
+    }
+
+    fn format(&self, score: u32) -> String {
+        format!("{:.2}%", score as f64 / 10000.)
+    }
+}
+
+/// Same as [`PhigrosScoring`], but the play fails on the first judgement other than perfect.
+pub struct ApOnlyScoring;
+
+impl ScoringRule for ApOnlyScoring {
+    fn name(&self) -> &'static str {
+        "AP Only"
+    }
+
+    fn accuracy(&self, state: &ScoreState) -> f64 {
+        PhigrosScoring.accuracy(state)
+    }
+
+    fn score(&self, state: &ScoreState) -> u32 {
+        PhigrosScoring.score(state)
+    }
+
+    fn failed(&self, state: &ScoreState) -> bool {
+        state.counts[1..].iter().any(|it| *it != 0)
+    }
+}
+
+/// The built-in scoring rules, as chosen in [`Config`](crate::config::Config).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Scoring {
+    #[default]
+    Phigros,
+    Ex,
+    Accuracy,
+    ApOnly,
+}
+
+impl Scoring {
+    pub fn rule(self, windows: &JudgeWindows) -> Box<dyn ScoringRule> {
+        match self {
+            Self::Phigros => Box::new(PhigrosScoring),
+            Self::Ex => Box::new(ExScoring::new(windows)),
+            Self::Accuracy => Box::new(AccuracyScoring),
+            Self::ApOnly => Box::new(ApOnlyScoring),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::NoteKind;
+
+    const RULES: [Scoring; 4] = [Scoring::Phigros, Scoring::Ex, Scoring::Accuracy, Scoring::ApOnly];
+
+    fn hit(judgement: Judgement, diff: Option<f32>) -> NoteHit {
+        NoteHit {
+            line: 0,
+            id: 0,
+            kind: NoteKind::Click,
+            time: 0.,
+            judgement,
+            diff,
+        }
+    }
+
+    fn state(hits: &[NoteHit], num_of_notes: u32) -> ScoreState {
+        let mut counts = [0; 4];
+        let (mut combo, mut max_combo) = (0, 0);
+        for hit in hits {
+            counts[hit.judgement as usize] += 1;
+            combo = if matches!(hit.judgement, Judgement::Perfect | Judgement::Good) {
+                combo + 1
+            } else {
+                0
+            };
+            max_combo = max_combo.max(combo);
+        }
+        ScoreState {
+            num_of_notes,
+            counts,
+            combo,
+            max_combo,
+            hits,
+        }
+    }
+
+    #[test]
+    fn all_perfect() {
+        let windows = JudgeWindows::STANDARD;
+        let hits = [
+            hit(Judgement::Perfect, Some(0.)),
+            hit(Judgement::Perfect, Some(windows.perfect / 4.)),
+            hit(Judgement::Perfect, Some(-windows.perfect / 4.)),
+            hit(Judgement::Perfect, None),
+        ];
+        let state = state(&hits, 4);
+        for scoring in RULES {
+            let rule = scoring.rule(&windows);
+            assert_eq!(rule.score(&state), rule.max_score(&state), "{}", rule.name());
+            assert_eq!(rule.accuracy(&state), 1., "{}", rule.name());
+            assert!(!rule.failed(&state), "{}", rule.name());
+        }
+    }
+
+    #[test]
+    fn ex_critical() {
+        let windows = JudgeWindows::STANDARD;
+        let rule = ExScoring::new(&windows);
+        let p = windows.perfect;
+        let hits = [
+            hit(Judgement::Perfect, Some(p * 0.25)),
+            hit(Judgement::Perfect, None),
+            hit(Judgement::Perfect, Some(p * 0.75)),
+            hit(Judgement::Perfect, Some(-p * 0.75)),
+            hit(Judgement::Good, Some(p * 1.5)),
+            hit(Judgement::Miss, None),
+        ];
+        let mut state = state(&hits, 6);
+        assert_eq!(rule.score(&state), 3 + 3 + 2 + 2 + 1);
+        assert_eq!(rule.max_score(&state), 18);
+        assert_eq!(rule.accuracy(&state), 11. / 18.);
+        // the score only depends on the hits
+        state.counts = [0; 4];
+        assert_eq!(rule.score(&state), 11);
+    }
+
+    #[test]
+    fn ap_only() {
+        let mut hits = vec![hit(Judgement::Perfect, Some(0.)), hit(Judgement::Perfect, None)];
+        assert!(!ApOnlyScoring.failed(&state(&hits, 3)));
+        hits.push(hit(Judgement::Good, Some(0.1)));
+        assert!(ApOnlyScoring.failed(&state(&hits, 3)));
+        assert!(ApOnlyScoring.failed(&state(&[hit(Judgement::Miss, None)], 3)));
+    }
+
+    #[test]
+    fn no_notes() {
+        let state = state(&[], 0);
+        for scoring in RULES {
+            let rule = scoring.rule(&JudgeWindows::STANDARD);
+            assert_eq!(rule.accuracy(&state), 1., "{}", rule.name());
+            assert_eq!(rule.score(&state), rule.max_score(&state), "{}", rule.name());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod calibration;
 pub mod config;
 pub mod core;
 pub mod ext;
@@ -5,8 +6,10 @@ pub mod fs;
 pub mod info;
 pub mod judge;
 pub mod l10n;
+pub mod lint;
 pub mod parse;
 pub mod particle;
+pub mod replay;
 pub mod scene;
 pub mod task;
 pub mod time;
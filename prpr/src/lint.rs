@@ -0,0 +1,646 @@
+use crate::{
+    core::{Chart, ChartExtra, EasingRegistry, JudgeLine, NoteKind, Vector, EPS},
+    ext::NotNanExt,
+    fs::{load_info, FileSystem},
+    info::{ChartFormat, ChartInfo},
+    parse::{detect_format, parse_extra_easings, parse_pec, parse_phigros, parse_rpe_headless, RPE_TWEEN_MAP},
+    scene::GameScene,
+};
+use anyhow::Result;
+use nalgebra::Rotation2;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+const OVERLAP_TIME: f32 = 1e-3;
+const OVERLAP_DIST: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<f32>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            line: None,
+            note: None,
+            time: None,
+        }
+    }
+
+    #[inline]
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    #[inline]
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    #[inline]
+    pub fn info(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Info, code, message)
+    }
+
+    #[inline]
+    pub fn line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    #[inline]
+    pub fn note(mut self, note: usize) -> Self {
+        self.note = Some(note);
+        self
+    }
+
+    #[inline]
+    pub fn time(mut self, time: f32) -> Self {
+        self.time = Some(time);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]", self.severity, self.code)?;
+        if let Some(line) = self.line {
+            write!(f, " line #{line}")?;
+        }
+        if let Some(note) = self.note {
+            write!(f, " note #{note}")?;
+        }
+        if let Some(time) = self.time {
+            write!(f, " @{time:.3}s")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LintReport {
+    pub chart: Option<String>,
+    pub format: Option<ChartFormat>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl LintReport {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics.iter().filter(|it| it.severity == severity).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|it| it.severity == Severity::Error)
+    }
+}
+
+/// Loads the chart from `fs` the same way the game does, but without any GL context, and checks it.
+pub async fn lint(fs: &mut dyn FileSystem) -> Result<LintReport> {
+    let mut report = LintReport {
+        chart: None,
+        format: None,
+        diagnostics: Vec::new(),
+    };
+    lint_inner(fs, &mut report).await?;
+    report.diagnostics.sort_by_key(|it| std::cmp::Reverse(it.severity));
+    Ok(report)
+}
+
+async fn lint_inner(fs: &mut dyn FileSystem, report: &mut LintReport) -> Result<()> {
+    let info = match load_info(fs).await {
+        Ok(info) => info,
+        Err(err) => {
+            report
+                .diagnostics
+                .push(Diagnostic::error("info", format!("failed to load chart info: {err:?}")));
+            return Ok(());
+        }
+    };
+    report.chart = Some(info.chart.clone());
+    let diags = &mut report.diagnostics;
+    if !fs.exists(&info.music).await? {
+        diags.push(Diagnostic::error("missing-music", format!("music file {} not found", info.music)));
+    }
+    if !fs.exists(&info.illustration).await? {
+        diags.push(Diagnostic::warning("missing-illustration", format!("illustration {} not found", info.illustration)));
+    }
+    let mut easings = EasingRegistry::default();
+    let mut extra_value = None;
+    if let Ok(extra) = fs.load_file("extra.json").await {
+        match serde_json::from_slice::<Value>(&extra) {
+            Ok(value) => {
+                match parse_extra_easings(&String::from_utf8_lossy(&extra)) {
+                    Ok(registry) => easings = registry,
+                    Err(err) => diags.push(Diagnostic::error("extra", format!("invalid easings in extra.json: {err:?}"))),
+                }
+                extra_value = Some(value);
+            }
+            Err(err) => diags.push(Diagnostic::error("extra", format!("extra.json is not valid JSON: {err}"))),
+        }
+    }
+    let text = match GameScene::load_chart_bytes(fs, &info).await.map(String::from_utf8) {
+        Ok(Ok(text)) => text,
+        Ok(Err(_)) => {
+            diags.push(Diagnostic::error("chart-encoding", "chart is not valid UTF-8"));
+            return Ok(());
+        }
+        Err(err) => {
+            diags.push(Diagnostic::error("missing-chart", format!("{err:?}")));
+            return Ok(());
+        }
+    };
+    let format = info.format.clone().unwrap_or_else(|| detect_format(&text));
+    report.format = Some(format.clone());
+    let diags = &mut report.diagnostics;
+    // the parsers assume some invariants (and may panic otherwise), so those are checked on the raw chart first
+    let fatal = match format {
+        ChartFormat::Rpe | ChartFormat::Pgr => match serde_json::from_str::<Value>(&text) {
+            Ok(value) => {
+                if matches!(format, ChartFormat::Rpe) {
+                    lint_rpe(&value, diags)
+                } else {
+                    lint_pgr(&value, diags)
+                }
+            }
+            Err(err) => {
+                diags.push(Diagnostic::error("json", format!("chart is not valid JSON: {err}")));
+                true
+            }
+        },
+        ChartFormat::Pec => lint_pec(&text, diags),
+    };
+    if fatal {
+        return Ok(());
+    }
+    let chart = match format {
+        ChartFormat::Rpe => {
+            let extra = ChartExtra {
+                easings,
+                ..Default::default()
+            };
+            parse_rpe_headless(&text, fs, extra).await
+        }
+        ChartFormat::Pgr => parse_phigros(&text, ChartExtra::default()),
+        ChartFormat::Pec => parse_pec(&text, ChartExtra::default()),
+    };
+    match chart {
+        Ok(mut chart) => {
+            if let Some(extra) = &extra_value {
+                lint_effect_targets(extra, chart.lines.len(), diags);
+            }
+            diags.extend(lint_chart(&mut chart, &info));
+        }
+        Err(err) => diags.push(Diagnostic::error("parse", format!("{err:?}"))),
+    }
+    Ok(())
+}
+
+fn line_transform(lines: &mut [JudgeLine], id: usize, time: f32, aspect_ratio: f32) -> (Vector, f32) {
+    fn own(line: &mut JudgeLine, time: f32, aspect_ratio: f32) -> (Vector, f32) {
+        line.object.set_time(time);
+        let mut tr = line.object.translation.now();
+        tr.y /= aspect_ratio;
+        (tr, line.object.rotation.now())
+    }
+    let (mut tr, rot) = own(&mut lines[id], time, aspect_ratio);
+    if let Some(parent) = lines[id].parent {
+        let (ptr, prot) = own(&mut lines[parent], time, aspect_ratio);
+        tr = Rotation2::new(prot.to_radians()) * tr + ptr;
+    }
+    (tr, rot)
+}
+
+/// Checks a parsed chart. Note indices are the ones after [crate::core::JudgeLineCache] sorted them.
+pub fn lint_chart(chart: &mut Chart, info: &ChartInfo) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    let count = chart.lines.len();
+    for (id, line) in chart.lines.iter().enumerate() {
+        let Some(parent) = line.parent else {
+            continue;
+        };
+        if parent >= count {
+            diags.push(Diagnostic::error("parent", format!("parent line #{parent} does not exist")).line(id));
+            continue;
+        }
+        let mut steps = 0;
+        let mut cur = Some(parent);
+        while let Some(now) = cur {
+            if now == id || steps > count {
+                diags.push(Diagnostic::error("parent", "cyclic parent relationship").line(id));
+                break;
+            }
+            cur = chart.lines.get(now).and_then(|it| it.parent);
+            steps += 1;
+        }
+    }
+    if diags.iter().any(|it| it.code == "parent") {
+        return diags;
+    }
+    if chart.lines.iter().all(|line| line.notes.iter().all(|it| it.fake)) {
+        diags.push(Diagnostic::warning("no-notes", "chart has no real notes"));
+    }
+    let top = 1. / info.aspect_ratio;
+    for id in 0..count {
+        let notes: Vec<_> = chart.lines[id]
+            .notes
+            .iter_mut()
+            .enumerate()
+            .map(|(index, note)| {
+                note.object.set_time(note.time);
+                (index, note.time, note.object.translation.0.now(), note.fake, note.above, note.kind.clone(), note.object.now_alpha())
+            })
+            .collect();
+        for (index, time, x, fake, _, kind, alpha) in &notes {
+            let diag = |d: Diagnostic| d.line(id).note(*index).time(*time);
+            if *time < 0. {
+                diags.push(diag(Diagnostic::warning("negative-time", "note is placed before the music starts")));
+            }
+            if let NoteKind::Hold { end_time, .. } = kind {
+                if *end_time <= *time {
+                    diags.push(diag(Diagnostic::error("hold-end", format!("hold ends at {end_time:.3}s, not after it starts"))));
+                }
+            }
+            if *fake {
+                continue;
+            }
+            if *alpha <= EPS {
+                diags.push(diag(Diagnostic::info("invisible-note", "real note is fully transparent")));
+            }
+            let (tr, rot) = line_transform(&mut chart.lines, id, *time, info.aspect_ratio);
+            let pos = tr + Rotation2::new(rot.to_radians()) * Vector::new(*x, 0.);
+            if pos.x.abs() > 1. + EPS || pos.y.abs() > top + EPS {
+                diags.push(diag(Diagnostic::warning(
+                    "off-screen",
+                    format!("note is outside the visible area at ({:.3}, {:.3})", pos.x, pos.y * info.aspect_ratio),
+                )));
+            }
+        }
+        let mut real: Vec<_> = notes.iter().filter(|it| !it.3).collect();
+        real.sort_by_key(|it| it.1.not_nan());
+        for (i, a) in real.iter().enumerate() {
+            for b in real[(i + 1)..].iter() {
+                if b.1 - a.1 > OVERLAP_TIME {
+                    break;
+                }
+                if a.4 == b.4 && (a.2 - b.2).abs() < OVERLAP_DIST {
+                    diags.push(
+                        Diagnostic::warning("overlap", format!("overlaps with note #{}", b.0))
+                            .line(id)
+                            .note(a.0)
+                            .time(a.1),
+                    );
+                }
+            }
+        }
+    }
+    diags
+}
+
+fn lint_effect_targets(extra: &Value, lines: usize, diags: &mut Vec<Diagnostic>) {
+    for (id, effect) in extra["effects"].as_array().into_iter().flatten().enumerate() {
+        if let Some(target) = effect["target"].as_u64() {
+            if target >= lines as u64 {
+                diags
+                    .push(Diagnostic::error("effect-target", format!("effect #{id} targets judge line #{target}, but there are only {lines} lines")));
+            }
+        }
+    }
+    for id in extra["lines"].as_object().into_iter().flat_map(|it| it.keys()) {
+        if id.parse::<usize>().map_or(true, |it| it >= lines) {
+            diags.push(Diagnostic::error("line-target", format!("expressions are given for judge line #{id}, but there are only {lines} lines")));
+        }
+    }
+}
+
+fn triple(value: &Value) -> Option<(f64, f64, f64)> {
+    let arr = value.as_array()?;
+    if arr.len() != 3 {
+        return None;
+    }
+    Some((arr[0].as_f64()?, arr[1].as_f64()?, arr[2].as_f64()?))
+}
+
+fn triple_beats(value: &Value) -> Option<f64> {
+    triple(value).filter(|it| it.2 != 0.).map(|(a, b, c)| a + b / c)
+}
+
+fn lint_rpe_events(id: usize, desc: &str, events: &Value, diags: &mut Vec<Diagnostic>) -> bool {
+    let Some(events) = events.as_array() else {
+        return false;
+    };
+    let mut fatal = false;
+    let mut last_end = f64::NEG_INFINITY;
+    for (index, e) in events.iter().enumerate() {
+        let (Some(start), Some(end)) = (triple_beats(&e["startTime"]), triple_beats(&e["endTime"])) else {
+            diags.push(Diagnostic::error("invalid-time", format!("{desc} event #{index} has an invalid beat triple")).line(id));
+            fatal = true;
+            continue;
+        };
+        if end < start {
+            diags.push(Diagnostic::warning("event-time", format!("{desc} event #{index} ends before it starts")).line(id));
+        }
+        if start < last_end - EPS as f64 {
+            diags.push(Diagnostic::warning("event-order", format!("{desc} event #{index} overlaps or precedes the previous one")).line(id));
+        }
+        last_end = end;
+        if e["bezier"].as_u64().unwrap_or(0) != 0 {
+            continue;
+        }
+        if let Some(easing) = e["easingType"].as_i64() {
+            if easing >= RPE_TWEEN_MAP.len() as i64 {
+                diags.push(
+                    Diagnostic::warning("unknown-easing", format!("{desc} event #{index} uses unknown easing type {easing}, falling back to linear"))
+                        .line(id),
+                );
+            }
+        }
+    }
+    fatal
+}
+
+fn lint_rpe(value: &Value, diags: &mut Vec<Diagnostic>) -> bool {
+    let mut fatal = false;
+    let Some(lines) = value["judgeLineList"].as_array() else {
+        diags.push(Diagnostic::error("json", "missing judgeLineList"));
+        return true;
+    };
+    for (id, line) in lines.iter().enumerate() {
+        if line["bpmfactor"].as_f64().map_or(false, |it| it <= 0.) {
+            diags.push(Diagnostic::error("bpm", "bpmfactor is not positive").line(id));
+            fatal = true;
+        }
+        for layer in line["eventLayers"].as_array().into_iter().flatten() {
+            for (key, desc) in [
+                ("alphaEvents", "alpha"),
+                ("moveXEvents", "move X"),
+                ("moveYEvents", "move Y"),
+                ("rotateEvents", "rotate"),
+                ("speedEvents", "speed"),
+            ] {
+                fatal |= lint_rpe_events(id, desc, &layer[key], diags);
+            }
+        }
+        for (key, desc) in [
+            ("colorEvents", "color"),
+            ("textEvents", "text"),
+            ("scaleXEvents", "scale X"),
+            ("scaleYEvents", "scale Y"),
+            ("inclineEvents", "incline"),
+            ("paintEvents", "paint"),
+        ] {
+            fatal |= lint_rpe_events(id, desc, &line["extended"][key], diags);
+        }
+        for key in ["posControl", "sizeControl", "alphaControl", "yControl"] {
+            for (index, e) in line[key].as_array().into_iter().flatten().enumerate() {
+                let easing = e["easing"].as_i64().unwrap_or(1);
+                if easing >= RPE_TWEEN_MAP.len() as i64 {
+                    diags.push(Diagnostic::warning("unknown-easing", format!("{key} #{index} uses unknown easing type {easing}")).line(id));
+                }
+            }
+        }
+        for (index, note) in line["notes"].as_array().into_iter().flatten().enumerate() {
+            if triple_beats(&note["startTime"]).is_none() || triple_beats(&note["endTime"]).is_none() {
+                diags.push(Diagnostic::error("invalid-time", "note has an invalid beat triple").line(id).note(index));
+                fatal = true;
+            }
+        }
+    }
+    for (index, bpm) in value["BPMList"].as_array().into_iter().flatten().enumerate() {
+        if bpm["bpm"].as_f64().map_or(true, |it| it <= 0.) {
+            diags.push(Diagnostic::error("bpm", format!("BPM item #{index} is not positive")));
+        }
+        if triple_beats(&bpm["startTime"]).is_none() {
+            diags.push(Diagnostic::error("invalid-time", format!("BPM item #{index} has an invalid beat triple")));
+            fatal = true;
+        }
+    }
+    fatal
+}
+
+fn lint_pgr(value: &Value, diags: &mut Vec<Diagnostic>) -> bool {
+    let mut fatal = false;
+    let Some(lines) = value["judgeLineList"].as_array() else {
+        diags.push(Diagnostic::error("json", "missing judgeLineList"));
+        return true;
+    };
+    for (id, line) in lines.iter().enumerate() {
+        let bpm = line["bpm"].as_f64().unwrap_or_default();
+        if bpm <= 0. {
+            diags.push(Diagnostic::error("bpm", "BPM is not positive").line(id));
+            fatal = true;
+            continue;
+        }
+        let r = 60. / bpm / 32.;
+        for (key, desc) in [
+            ("judgeLineDisappearEvents", "alpha"),
+            ("judgeLineRotateEvents", "rotate"),
+            ("judgeLineMoveEvents", "move"),
+            ("speedEvents", "speed"),
+        ] {
+            let events: Vec<_> = line[key]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|e| Some((e["startTime"].as_f64()?, e["endTime"].as_f64()?)))
+                .collect();
+            let valid: Vec<_> = events.iter().filter(|(st, en)| st <= en).collect();
+            if valid.len() != events.len() {
+                diags.push(
+                    Diagnostic::warning(
+                        "event-time",
+                        format!("{} {desc} event(s) end before they start and will be ignored", events.len() - valid.len()),
+                    )
+                    .line(id),
+                );
+            }
+            let Some(last) = valid.last() else {
+                diags.push(Diagnostic::error("no-events", format!("line has no valid {desc} events")).line(id));
+                fatal = true;
+                continue;
+            };
+            for pair in valid.windows(2) {
+                if pair[0].1 != pair[1].0 {
+                    diags.push(
+                        Diagnostic::error("event-gap", format!("{desc} events are not contiguous ({} -> {})", pair[0].1, pair[1].0))
+                            .line(id)
+                            .time((pair[0].1 * r) as f32),
+                    );
+                }
+            }
+            if last.1 <= 900000000. {
+                diags.push(Diagnostic::error("event-end", format!("last {desc} event ends too early ({})", last.1)).line(id));
+            }
+            if key == "speedEvents" && valid[0].0 != 0. {
+                diags.push(Diagnostic::error("event-start", "speed events should start at 0").line(id));
+                fatal = true;
+            }
+        }
+    }
+    fatal
+}
+
+fn lint_pec(text: &str, diags: &mut Vec<Diagnostic>) -> bool {
+    for (index, line) in text.lines().enumerate() {
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        let easing = match tokens.first() {
+            Some(&"cm") => tokens.get(6),
+            Some(&"cr") => tokens.get(5),
+            _ => None,
+        };
+        if let Some(easing) = easing.and_then(|it| it.parse::<i64>().ok()) {
+            if easing >= RPE_TWEEN_MAP.len() as i64 {
+                diags.push(Diagnostic::warning("unknown-easing", format!("line {} of the chart uses unknown easing type {easing}", index + 1)));
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn codes(diags: &[Diagnostic]) -> Vec<&'static str> {
+        diags.iter().map(|it| it.code).collect()
+    }
+
+    #[test]
+    fn rpe_rules() {
+        let chart = json!({
+            "BPMList": [{ "bpm": 0, "startTime": [0, 0, 1] }],
+            "judgeLineList": [{
+                "bpmfactor": 0,
+                "eventLayers": [{
+                    "moveXEvents": [
+                        { "startTime": [0, 0, 1], "endTime": [2, 0, 1], "easingType": 1 },
+                        { "startTime": [1, 0, 1], "endTime": [3, 0, 1], "easingType": 200 },
+                    ],
+                }],
+                "notes": [{ "startTime": [0, 0, 0], "endTime": [0, 0, 1] }],
+            }],
+        });
+        let mut diags = Vec::new();
+        assert!(lint_rpe(&chart, &mut diags));
+        let codes = codes(&diags);
+        for code in ["bpm", "event-order", "unknown-easing", "invalid-time"] {
+            assert!(codes.contains(&code), "missing {code} in {codes:?}");
+        }
+        assert_eq!(diags.iter().filter(|it| it.code == "bpm").count(), 2);
+    }
+
+    #[test]
+    fn rpe_valid() {
+        let chart = json!({
+            "BPMList": [{ "bpm": 120, "startTime": [0, 0, 1] }],
+            "judgeLineList": [{
+                "eventLayers": [{
+                    "moveXEvents": [{ "startTime": [0, 0, 1], "endTime": [1, 0, 1], "easingType": 1 }],
+                }],
+                "notes": [{ "startTime": [1, 1, 2], "endTime": [1, 1, 2] }],
+            }],
+        });
+        let mut diags = Vec::new();
+        assert!(!lint_rpe(&chart, &mut diags));
+        assert!(diags.is_empty(), "{diags:?}");
+    }
+
+    #[test]
+    fn pgr_rules() {
+        let event = |start: f64, end: f64| json!({ "startTime": start, "endTime": end });
+        let chart = json!({
+            "judgeLineList": [{
+                "bpm": 120,
+                "judgeLineDisappearEvents": [event(0., 10.), event(20., 1e9)],
+                "judgeLineRotateEvents": [event(0., 1e9)],
+                "judgeLineMoveEvents": [event(0., 100.)],
+                "speedEvents": [event(0., 1e9)],
+            }],
+        });
+        let mut diags = Vec::new();
+        assert!(!lint_pgr(&chart, &mut diags));
+        assert_eq!(codes(&diags), ["event-gap", "event-end"]);
+
+        let chart = json!({ "judgeLineList": [{ "bpm": 0 }] });
+        let mut diags = Vec::new();
+        assert!(lint_pgr(&chart, &mut diags));
+        assert_eq!(codes(&diags), ["bpm"]);
+    }
+
+    #[test]
+    fn pec_rules() {
+        let mut diags = Vec::new();
+        assert!(!lint_pec("0\nbp 0 120\ncm 0 0 1 1024 700 1\ncm 0 1 2 1024 700 99", &mut diags));
+        assert_eq!(codes(&diags), ["unknown-easing"]);
+        assert!(diags[0].message.contains("line 4"));
+    }
+
+    #[test]
+    fn chart_rules() {
+        let source = "0\nbp 0 120\ncv 0 0 10\ncp 0 0 1024 700\ncd 0 0 0\nca 0 0 255\nn1 0 1 0 1 0\nn1 0 1 0 1 0\nn2 0 4 3 512 1 0\nn1 0 2 0 1 1";
+        let mut chart = parse_pec(source, ChartExtra::default()).unwrap();
+        let diags = lint_chart(&mut chart, &ChartInfo::default());
+        let codes = codes(&diags);
+        assert_eq!(codes.iter().filter(|it| **it == "overlap").count(), 1, "{codes:?}");
+        assert!(codes.contains(&"hold-end"), "{codes:?}");
+        assert!(!codes.contains(&"off-screen"), "{codes:?}");
+    }
+
+    #[test]
+    fn effect_targets() {
+        let extra = json!({
+            "effects": [
+                { "start": [0, 0, 1], "end": [1, 0, 1], "shader": "chromatic", "target": 1 },
+                { "start": [0, 0, 1], "end": [1, 0, 1], "shader": "chromatic", "target": 2 },
+                { "start": [0, 0, 1], "end": [1, 0, 1], "shader": "chromatic" },
+            ],
+        });
+        let mut diags = Vec::new();
+        lint_effect_targets(&extra, 2, &mut diags);
+        assert_eq!(codes(&diags), ["effect-target"]);
+        assert!(diags[0].message.starts_with("effect #1 "));
+
+        let extra = json!({ "lines": { "0": { "alpha": "sin(beat)" }, "2": { "x": "0" } } });
+        let mut diags = Vec::new();
+        lint_effect_targets(&extra, 2, &mut diags);
+        assert_eq!(codes(&diags), ["line-target"]);
+        assert!(diags[0].message.contains("#2"));
+
+        let mut diags = Vec::new();
+        lint_effect_targets(&json!({}), 0, &mut diags);
+        assert!(diags.is_empty());
+    }
+}
@@ -1,17 +1,45 @@
+use crate::{
+    core::{
+        AnimFloat, BezierTween, Chart, ClampedCustomTween, ClampedTween, EasingRegistry, JudgeLineKind, NoteKind, StaticTween, TweenFunction,
+        Tweenable, EPS,
+    },
+    ext::NotNanExt,
+    info::ChartFormat,
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, rc::Rc};
+
 mod extra;
 pub use extra::parse_extra;
+pub(crate) use extra::parse_extra_easings;
 
 mod pec;
-pub use pec::parse_pec;
+pub use pec::{parse_pec, serialize_pec};
 
 mod pgr;
-pub use pgr::parse_phigros;
+pub use pgr::{parse_phigros, serialize_phigros};
 
 mod rpe;
-pub use rpe::{parse_rpe, RPE_HEIGHT, RPE_WIDTH};
+pub(crate) use rpe::parse_rpe_headless;
+pub use rpe::{parse_rpe, serialize_rpe, RPE_HEIGHT, RPE_WIDTH};
+
+mod transform;
+pub use transform::ChartTransform;
+
+pub fn detect_format(text: &str) -> ChartFormat {
+    if text.starts_with('{') {
+        if text.contains("\"META\"") {
+            ChartFormat::Rpe
+        } else {
+            ChartFormat::Pgr
+        }
+    } else {
+        ChartFormat::Pec
+    }
+}
 
 fn process_lines(v: &mut [crate::core::JudgeLine]) {
-    use crate::ext::NotNanExt;
     let mut times = Vec::new();
     // TODO optimize using k-merge sort
     let sorts = v
@@ -83,3 +111,209 @@ pub const RPE_TWEEN_MAP: [crate::core::TweenId; 30] = {
         e(Bounce, InOut), e(Elastic, InOut),
     ]
 };
+
+/// Easing of RPE events and extra keyframes: an RPE easing type, or the name of a custom easing declared in `extra.json`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum EasingType {
+    Id(i32),
+    Name(String),
+}
+
+impl EasingType {
+    /// The tween of the part of the easing in `range` (`easingLeft..easingRight`).
+    pub(crate) fn tween(&self, range: Range<f32>, easings: &EasingRegistry) -> Result<Rc<dyn TweenFunction>> {
+        let full = range.start.abs() < EPS && (range.end - 1.0).abs() < EPS;
+        Ok(match self {
+            Self::Id(id) => {
+                let tween = RPE_TWEEN_MAP.get((*id).max(1) as usize).copied().unwrap_or(RPE_TWEEN_MAP[0]);
+                if full {
+                    StaticTween::get_rc(tween)
+                } else {
+                    Rc::new(ClampedTween::new(tween, range))
+                }
+            }
+            Self::Name(name) => {
+                let tween = easings.get(name).ok_or_else(|| anyhow!("Unknown easing {name}"))?;
+                if full {
+                    tween
+                } else {
+                    Rc::new(ClampedCustomTween::new(tween, range))
+                }
+            }
+        })
+    }
+}
+
+const LINEARIZE_STEPS: usize = 16;
+
+fn rpe_easing_type(tween: crate::core::TweenId) -> Option<i32> {
+    RPE_TWEEN_MAP.iter().skip(1).position(|it| *it == tween).map(|it| it as i32 + 1)
+}
+
+fn anim_value(anim: &AnimFloat, time: f32, left: bool) -> Option<f32> {
+    let kfs = &anim.keyframes;
+    if kfs.is_empty() {
+        return None;
+    }
+    let cursor = kfs
+        .iter()
+        .rposition(|it| if left { it.time < time } else { it.time <= time })
+        .unwrap_or(0);
+    let value = if cursor == kfs.len() - 1 {
+        kfs[cursor].value
+    } else {
+        let (kf1, kf2) = (&kfs[cursor], &kfs[cursor + 1]);
+        f32::tween(&kf1.value, &kf2.value, kf1.tween.y((time - kf1.time) / (kf2.time - kf1.time)))
+    };
+    Some(match &anim.next {
+        Some(next) => value + anim_value(next, time, left).unwrap_or_default(),
+        None => value,
+    })
+}
+
+/// Approximates `anim` on `[0, end]` with `(time, value)` points connected linearly. A jump is two points at the same time.
+fn linearize(anim: &AnimFloat, default: f32, end: f32) -> Vec<(f32, f32)> {
+    let mut times = vec![0., end];
+    let mut cur = Some(anim);
+    while let Some(anim) = cur {
+        times.extend(anim.keyframes.iter().map(|it| it.time).filter(|it| *it > 0. && *it < end));
+        cur = anim.next.as_deref();
+    }
+    times.sort_by_key(|it| it.not_nan());
+    times.dedup();
+    let value = |time: f32, left: bool| anim_value(anim, time, left).unwrap_or(default);
+    let mut points = Vec::new();
+    for pair in times.windows(2) {
+        let (st, en) = (pair[0], pair[1]);
+        let (start, end) = (value(st, false), value(en, true));
+        points.push((st, start));
+        let linear = [0.25, 0.5, 0.75]
+            .into_iter()
+            .all(|t| (value(f32::tween(&st, &en, t), false) - f32::tween(&start, &end, t)).abs() < 1e-4);
+        if !linear {
+            for i in 1..LINEARIZE_STEPS {
+                let time = f32::tween(&st, &en, i as f32 / LINEARIZE_STEPS as f32);
+                points.push((time, value(time, false)));
+            }
+        }
+        points.push((en, end));
+    }
+    points.dedup();
+    points
+}
+
+fn polyline_value(points: &[(f32, f32)], time: f32, left: bool) -> f32 {
+    let i = points.partition_point(|it| if left { it.0 < time } else { it.0 <= time });
+    if i == 0 {
+        return points[0].1;
+    }
+    if i == points.len() {
+        return points[i - 1].1;
+    }
+    let (a, b) = (points[i - 1], points[i]);
+    if b.0 - a.0 < f32::EPSILON {
+        b.1
+    } else {
+        f32::tween(&a.1, &b.1, (time - a.0) / (b.0 - a.0))
+    }
+}
+
+/// Merges two results of [linearize] into `(time, a, b)` points.
+fn merge_polylines(a: &[(f32, f32)], b: &[(f32, f32)]) -> Vec<(f32, f32, f32)> {
+    let mut times: Vec<_> = a.iter().chain(b.iter()).map(|it| it.0).collect();
+    times.sort_by_key(|it| it.not_nan());
+    times.dedup();
+    let mut res = Vec::new();
+    for time in times {
+        let left = (time, polyline_value(a, time, true), polyline_value(b, time, true));
+        let right = (time, polyline_value(a, time, false), polyline_value(b, time, false));
+        res.push(left);
+        if left != right {
+            res.push(right);
+        }
+    }
+    res
+}
+
+fn chart_end(chart: &Chart) -> f32 {
+    fn anim_end(anim: &AnimFloat) -> f32 {
+        let end = anim.keyframes.last().map_or(0., |it| it.time);
+        anim.next.as_deref().map_or(end, |next| end.max(anim_end(next)))
+    }
+    let mut end = 0f32;
+    for line in &chart.lines {
+        for note in &line.notes {
+            end = end.max(match note.kind {
+                NoteKind::Hold { end_time, .. } => end_time,
+                _ => note.time,
+            });
+        }
+        let obj = &line.object;
+        for anim in [&obj.alpha, &obj.rotation, &obj.translation.0, &obj.translation.1] {
+            end = end.max(anim_end(anim));
+        }
+    }
+    end + 1.
+}
+
+fn has_bezier(anim: &AnimFloat) -> bool {
+    let mut cur = Some(anim);
+    while let Some(anim) = cur {
+        if anim.keyframes.iter().any(|it| it.tween.as_any().is::<BezierTween>()) {
+            return true;
+        }
+        cur = anim.next.as_deref();
+    }
+    false
+}
+
+/// Warnings for features that official charts and PEC charts both lack.
+fn lossy_warnings(chart: &Chart, format: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    for (id, line) in chart.lines.iter().enumerate() {
+        let mut warn = |feature: &str| res.push(format!("Line #{id}: {feature} cannot be represented in {format} and is dropped"));
+        match line.kind {
+            JudgeLineKind::Normal => {}
+            JudgeLineKind::Texture(..) => warn("texture"),
+            JudgeLineKind::Text(_) => warn("text"),
+            JudgeLineKind::Paint(..) => warn("paint events"),
+        }
+        if line.attach_ui.is_some() {
+            warn("attachUI");
+        }
+        if line.parent.is_some() {
+            warn("parent line");
+        }
+        if line.z_index != 0 {
+            warn("z order");
+        }
+        if !line.color.is_default() {
+            warn("color events");
+        }
+        if !line.object.scale.0.is_default() || !line.object.scale.1.is_default() {
+            warn("scale events");
+        }
+        if !line.incline.is_default() {
+            warn("incline events");
+        }
+        let ctrl = line.ctrl_obj.borrow();
+        if !ctrl.alpha.is_default() || !ctrl.size.is_default() || !ctrl.pos.is_default() || !ctrl.y.is_default() {
+            warn("control events");
+        }
+        if line.notes.iter().any(|it| !it.object.alpha.is_default()) {
+            warn("note alpha");
+        }
+        if line.notes.iter().any(|it| it.object.translation.1.now().abs() > 1e-4) {
+            warn("note Y offset");
+        }
+        let obj = &line.object;
+        if [&obj.alpha, &obj.rotation, &obj.translation.0, &obj.translation.1]
+            .into_iter()
+            .any(has_bezier)
+        {
+            res.push(format!("Line #{id}: bezier easings are approximated with linear events in {format}"));
+        }
+    }
+    res
+}
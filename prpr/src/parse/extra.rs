@@ -1,14 +1,17 @@
 use crate::{
-    core::{Anim, BpmList, ChartExtra, ClampedTween, Effect, Keyframe, StaticTween, Triple, Tweenable, Uniform, Video, EPS},
+    core::{
+        Anim, AnimFloat, BezierTween, BpmList, ChartExtra, EasingRegistry, Effect, Expr, ExprUniform, Keyframe, LineExprs, PiecewiseTween,
+        PreviousFrameUniform, StepTween, TextureUniform, Triple, TweenFunction, Tweenable, Uniform, Video,
+    },
     ext::ScaleType,
     fs::FileSystem,
 };
-use anyhow::{anyhow, Context, Result};
-use macroquad::prelude::{Color, Vec2};
+use anyhow::{anyhow, bail, Context, Result};
+use macroquad::prelude::{Color, Vec2, Vec3};
 use serde::Deserialize;
 use std::{collections::HashMap, path::Path, rc::Rc};
 
-use super::RPE_TWEEN_MAP;
+use super::EasingType;
 
 // serde is weird...
 fn f32_zero() -> f32 {
@@ -26,7 +29,7 @@ struct ExtKeyframe<T> {
     easing_left: f32,
     #[serde(default = "f32_one")]
     easing_right: f32,
-    easing_type: i32,
+    easing_type: EasingType,
     start: T,
     end: T,
     start_time: Triple,
@@ -43,11 +46,32 @@ enum ExtAnim<V> {
 }
 
 impl<V> ExtAnim<V> {
-    fn into<T: Tweenable>(self, r: &mut BpmList, default: Option<T>) -> Anim<T>
+    fn map<U>(self, f: impl Fn(V) -> U) -> ExtAnim<U> {
+        match self {
+            ExtAnim::Default => ExtAnim::Default,
+            ExtAnim::Fixed(value) => ExtAnim::Fixed(f(value)),
+            ExtAnim::Keyframes(events) => ExtAnim::Keyframes(
+                events
+                    .into_iter()
+                    .map(|e| ExtKeyframe {
+                        easing_left: e.easing_left,
+                        easing_right: e.easing_right,
+                        easing_type: e.easing_type,
+                        start: f(e.start),
+                        end: f(e.end),
+                        start_time: e.start_time,
+                        end_time: e.end_time,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn into<T: Tweenable>(self, r: &mut BpmList, default: Option<T>, easings: &EasingRegistry) -> Result<Anim<T>>
     where
         V: Into<T>,
     {
-        match self {
+        Ok(match self {
             ExtAnim::Default => Anim::default(),
             ExtAnim::Fixed(value) => Anim::fixed(value.into()),
             ExtAnim::Keyframes(events) => {
@@ -61,23 +85,70 @@ impl<V> ExtAnim<V> {
                     kfs.push(Keyframe {
                         time: r.time(&e.start_time),
                         value: e.start.into(),
-                        tween: {
-                            let tween = RPE_TWEEN_MAP.get(e.easing_type.max(1) as usize).copied().unwrap_or(RPE_TWEEN_MAP[0]);
-                            if e.easing_left.abs() < EPS && (e.easing_right - 1.0).abs() < EPS {
-                                StaticTween::get_rc(tween)
-                            } else {
-                                Rc::new(ClampedTween::new(tween, e.easing_left..e.easing_right))
-                            }
-                        },
+                        tween: e.easing_type.tween(e.easing_left..e.easing_right, easings)?,
                     });
                     kfs.push(Keyframe::new(r.time(&e.end_time), e.end.into(), 0));
                 }
                 Anim::new(kfs)
             }
-        }
+        })
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtSteps {
+    count: u32,
+    #[serde(default)]
+    start: bool,
+}
+
+/// A custom easing, going from 0 to 1 over `0..=1`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ExtEasing {
+    /// Control points `[x1, y1, x2, y2]` of a cubic bezier, like CSS `cubic-bezier()`.
+    Bezier([f32; 4]),
+    /// Values sampled evenly over `0..=1`, linearly interpolated.
+    Samples(Vec<f32>),
+    /// `[x, y]` points, linearly interpolated.
+    Points(Vec<(f32, f32)>),
+    Steps(ExtSteps),
+}
+
+fn parse_easing(easing: ExtEasing) -> Result<Rc<dyn TweenFunction>> {
+    Ok(match easing {
+        ExtEasing::Bezier([x1, y1, x2, y2]) => {
+            // x has to be monotone for the curve to be a function
+            if !(0.0..=1.0).contains(&x1) || !(0.0..=1.0).contains(&x2) {
+                bail!("X of bezier control points must be within [0, 1]");
+            }
+            Rc::new(BezierTween::new((x1, y1), (x2, y2)))
+        }
+        ExtEasing::Samples(samples) => {
+            if samples.len() < 2 {
+                bail!("At least two samples are required");
+            }
+            Rc::new(PiecewiseTween::from_samples(&samples))
+        }
+        ExtEasing::Points(points) => {
+            if points.is_empty() {
+                bail!("At least one point is required");
+            }
+            Rc::new(PiecewiseTween::new(points))
+        }
+        ExtEasing::Steps(steps) => {
+            if steps.count == 0 {
+                bail!("Step count must be positive");
+            }
+            Rc::new(StepTween {
+                count: steps.count,
+                start: steps.start,
+            })
+        }
+    })
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ExtBpmItem {
@@ -104,11 +175,26 @@ impl From<BpmForm> for BpmList {
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum Variable {
+    /// Rounded for `int` and `bool` uniforms.
     Float(ExtAnim<f32>),
+    Bool(ExtAnim<bool>),
     Vec2(ExtAnim<(f32, f32)>),
+    Vec3(ExtAnim<(f32, f32, f32)>),
     Color(ExtAnim<[u8; 4]>),
+    /// See [`Expr`]. Colors take values from 0 to 1 here.
+    Expr(String),
+    Exprs(Vec<String>),
+    Texture(ExtTexture),
+}
+
+#[derive(Deserialize)]
+struct ExtTexture {
+    /// Path of the image in the chart, or [`PREVIOUS_FRAME`].
+    texture: String,
 }
 
+const PREVIOUS_FRAME: &str = "@previousFrame";
+
 #[derive(Deserialize)]
 struct ExtEffect {
     start: Triple,
@@ -118,6 +204,9 @@ struct ExtEffect {
     vars: HashMap<String, Variable>,
     #[serde(default)]
     global: bool,
+    /// Index of the judge line the effect applies to.
+    #[serde(default)]
+    target: Option<usize>,
 }
 
 #[derive(Deserialize)]
@@ -133,6 +222,18 @@ struct ExtVideo {
     dim: ExtAnim<f32>,
 }
 
+/// Expressions for properties of a judge line, see [`Expr`].
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtLine {
+    alpha: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+    rotation: Option<String>,
+    scale_x: Option<String>,
+    scale_y: Option<String>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Extra {
@@ -141,43 +242,108 @@ struct Extra {
     effects: Vec<ExtEffect>,
     #[serde(default)]
     videos: Vec<ExtVideo>,
+    #[serde(default)]
+    easings: HashMap<String, ExtEasing>,
+    #[serde(default)]
+    lines: HashMap<usize, ExtLine>,
 }
 
-async fn parse_effect(r: &mut BpmList, rpe: ExtEffect, fs: &mut dyn FileSystem) -> Result<Effect> {
+async fn parse_effect(r: &mut BpmList, rpe: ExtEffect, fs: &mut dyn FileSystem, easings: &EasingRegistry) -> Result<Effect> {
     let range = r.time(&rpe.start)..r.time(&rpe.end);
-    let vars = rpe
-        .vars
-        .into_iter()
-        .map(|(name, var)| -> Result<Box<dyn Uniform>> {
-            Ok(match var {
-                Variable::Float(events) => Box::new((name, events.into::<f32>(r, None))),
-                Variable::Vec2(events) => Box::new((name, events.into::<Vec2>(r, None))),
-                Variable::Color(events) => Box::new((name, events.into::<Color>(r, None))),
-            })
-        })
-        .collect::<Result<_>>()?;
     let string;
-    Effect::new(
-        range,
-        if let Some(path) = rpe.shader.strip_prefix('/') {
-            string = String::from_utf8(fs.load_file(path).await?).with_context(|| format!("Cannot load shader from {path}"))?;
-            &string
-        } else {
-            Effect::get_preset(&rpe.shader).ok_or_else(|| anyhow!("Cannot find preset shader {}", rpe.shader))?
-        },
-        vars,
-        rpe.global,
-    )
+    let shader = if let Some(path) = rpe.shader.strip_prefix('/') {
+        string = String::from_utf8(fs.load_file(path).await?).with_context(|| format!("Cannot load shader from {path}"))?;
+        &string
+    } else {
+        Effect::get_preset(&rpe.shader).ok_or_else(|| anyhow!("Cannot find preset shader {}", rpe.shader))?
+    };
+    let mut vars: Vec<Box<dyn Uniform>> = Vec::new();
+    for (name, var) in rpe.vars {
+        let is_int = matches!(Effect::uniform_type(shader, &name), Some("int" | "bool"));
+        vars.push(match var {
+            Variable::Float(events) if is_int => Box::new((name, events.map(|it| it.round() as i32).into::<i32>(r, None, easings)?)),
+            Variable::Float(events) => Box::new((name, events.into::<f32>(r, None, easings)?)),
+            Variable::Bool(events) => {
+                if !is_int {
+                    bail!("{name} is not an int or bool uniform");
+                }
+                Box::new((name, events.map(i32::from).into::<i32>(r, None, easings)?))
+            }
+            Variable::Vec2(events) => Box::new((name, events.into::<Vec2>(r, None, easings)?)),
+            Variable::Vec3(events) => Box::new((name, events.into::<Vec3>(r, None, easings)?)),
+            Variable::Color(events) => Box::new((name, events.into::<Color>(r, None, easings)?)),
+            Variable::Expr(expr) => Box::new(ExprUniform::new(name, vec![Expr::parse(&expr)?], r.clone(), range.clone())?),
+            Variable::Exprs(exprs) => {
+                let exprs = exprs.iter().map(|it| Expr::parse(it)).collect::<Result<_>>()?;
+                Box::new(ExprUniform::new(name, exprs, r.clone(), range.clone())?)
+            }
+            Variable::Texture(texture) if texture.texture == PREVIOUS_FRAME => Box::new(PreviousFrameUniform(name)),
+            Variable::Texture(texture) => {
+                let path = texture.texture;
+                let image = image::load_from_memory(&fs.load_file(&path).await.with_context(|| format!("Failed to load texture {path}"))?)
+                    .with_context(|| format!("Failed to decode texture {path}"))?;
+                Box::new(TextureUniform(name, image.into()))
+            }
+        });
+    }
+    if rpe.global && rpe.target.is_some() {
+        bail!("Global effects can't target a line");
+    }
+    let mut effect = Effect::new(range, shader, vars, rpe.global)?;
+    effect.target = rpe.target;
+    Ok(effect)
+}
+
+fn parse_line(r: &BpmList, line: ExtLine) -> Result<LineExprs> {
+    let anim = |name: &str, expr: Option<String>| {
+        expr.map(|it| {
+            Expr::parse(&it)
+                .with_context(|| format!("In {name}"))
+                .map(|it| AnimFloat::expr(it, r.clone()))
+        })
+        .transpose()
+    };
+    Ok(LineExprs {
+        alpha: anim("alpha", line.alpha)?,
+        x: anim("x", line.x)?,
+        y: anim("y", line.y)?,
+        rotation: anim("rotation", line.rotation)?,
+        scale_x: anim("scaleX", line.scale_x)?,
+        scale_y: anim("scaleY", line.scale_y)?,
+    })
+}
+
+fn easing_registry(easings: HashMap<String, ExtEasing>) -> Result<EasingRegistry> {
+    let mut res = EasingRegistry::default();
+    for (name, easing) in easings {
+        let tween = parse_easing(easing).with_context(|| format!("In easing {name}"))?;
+        res.insert(name, tween);
+    }
+    Ok(res)
+}
+
+/// Parses only the custom easings of `extra.json`, which needs no window unlike effects.
+pub(crate) fn parse_extra_easings(source: &str) -> Result<EasingRegistry> {
+    #[derive(Deserialize)]
+    struct Easings {
+        #[serde(default)]
+        easings: HashMap<String, ExtEasing>,
+    }
+    easing_registry(serde_json::from_str::<Easings>(source).context("Failed to parse JSON")?.easings)
 }
 
 pub async fn parse_extra(source: &str, fs: &mut dyn FileSystem, ffmpeg: Option<&Path>) -> Result<ChartExtra> {
     let ext: Extra = serde_json::from_str(source).context("Failed to parse JSON")?;
     let mut r: BpmList = ext.bpm.into();
+    let easings = easing_registry(ext.easings)?;
     let mut effects = Vec::new();
     let mut global_effects = Vec::new();
     for (id, effect) in ext.effects.into_iter().enumerate() {
-        (if effect.global { &mut global_effects } else { &mut effects })
-            .push(parse_effect(&mut r, effect, fs).await.with_context(|| format!("In effect #{id}"))?);
+        (if effect.global { &mut global_effects } else { &mut effects }).push(
+            parse_effect(&mut r, effect, fs, &easings)
+                .await
+                .with_context(|| format!("In effect #{id}"))?,
+        );
     }
     let mut videos = Vec::new();
     if let Some(ffmpeg) = ffmpeg {
@@ -190,16 +356,22 @@ pub async fn parse_extra(source: &str, fs: &mut dyn FileSystem, ffmpeg: Option<&
                         .with_context(|| format!("Failed to read video from {}", video.path))?,
                     r.time(&video.time),
                     video.scale,
-                    video.alpha.into(&mut r, Some(1.)),
-                    video.dim.into(&mut r, Some(0.)),
+                    video.alpha.into(&mut r, Some(1.), &easings)?,
+                    video.dim.into(&mut r, Some(0.), &easings)?,
                 )
                 .with_context(|| format!("Failed to load video from {}", video.path))?,
             );
         }
     }
+    let mut lines = HashMap::new();
+    for (id, line) in ext.lines {
+        lines.insert(id, parse_line(&r, line).with_context(|| format!("In judge line #{id}"))?);
+    }
     Ok(ChartExtra {
         effects,
         global_effects,
         videos,
+        easings,
+        lines,
     })
 }
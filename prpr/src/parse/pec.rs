@@ -11,6 +11,9 @@ use anyhow::{anyhow, bail, Context, Result};
 use macroquad::prelude::warn;
 use std::cell::RefCell;
 
+mod serialize;
+pub use serialize::serialize_pec;
+
 trait Take {
     fn take_f32(&mut self) -> Result<f32>;
     fn take_usize(&mut self) -> Result<usize>;
@@ -171,6 +174,9 @@ fn parse_judge_line(mut pec: PECJudgeLine, id: usize, max_time: f32) -> Result<J
         z_index: 0,
         show_below: false,
         attach_ui: None,
+        name: String::new(),
+        group: None,
+        links: Vec::new(),
 
         cache,
     })
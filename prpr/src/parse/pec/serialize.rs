@@ -0,0 +1,298 @@
+use crate::{
+    core::{AnimFloat, BpmList, Chart, JudgeLine, NoteKind, StaticTween},
+    parse::{chart_end, linearize, lossy_warnings, merge_polylines, rpe_easing_type},
+};
+
+const OFFSET_DELTA: f32 = 0.15;
+const SPEED_RATIO: f32 = 5.85;
+const LINEAR: i32 = 1;
+
+struct Segment {
+    start_time: f32,
+    end_time: f32,
+    start: f32,
+    end: f32,
+    easing: i32,
+}
+
+impl Segment {
+    fn single(time: f32, value: f32) -> Self {
+        Self {
+            start_time: time,
+            end_time: time,
+            start: value,
+            end: value,
+            easing: LINEAR,
+        }
+    }
+}
+
+fn exact_segments(anim: &AnimFloat, allow_easing: bool) -> Option<Vec<Segment>> {
+    if anim.keyframes.is_empty() || anim.next.is_some() {
+        return None;
+    }
+    let kfs = &anim.keyframes;
+    let mut res = Vec::new();
+    for (i, kf) in kfs.iter().enumerate() {
+        let tween = kf.tween.as_any().downcast_ref::<StaticTween>()?.0;
+        match kfs.get(i + 1) {
+            Some(next) if tween != 0 => {
+                let easing = rpe_easing_type(tween)?;
+                if !allow_easing && easing != LINEAR {
+                    return None;
+                }
+                res.push(Segment {
+                    start_time: kf.time,
+                    end_time: next.time,
+                    start: kf.value,
+                    end: next.value,
+                    easing,
+                });
+            }
+            _ => res.push(Segment::single(kf.time, kf.value)),
+        }
+    }
+    Some(res)
+}
+
+fn polyline_segments(points: &[(f32, f32)]) -> Vec<Segment> {
+    let mut res = vec![Segment::single(points[0].0, points[0].1)];
+    for p in points.windows(2) {
+        let (st, en) = (p[0], p[1]);
+        res.push(if en.0 - st.0 < f32::EPSILON {
+            Segment::single(en.0, en.1)
+        } else {
+            Segment {
+                start_time: st.0,
+                end_time: en.0,
+                start: st.1,
+                end: en.1,
+                easing: LINEAR,
+            }
+        });
+    }
+    res
+}
+
+fn segments(anim: &AnimFloat, default: f32, end: f32, allow_easing: bool) -> Vec<Segment> {
+    exact_segments(anim, allow_easing).unwrap_or_else(|| polyline_segments(&linearize(anim, default, end)))
+}
+
+fn emit(
+    r: &mut BpmList,
+    out: &mut Vec<String>,
+    segments: &[Segment],
+    single: impl Fn(f32, f32) -> String,
+    ranged: impl Fn(f32, f32, f32, i32) -> String,
+) {
+    let mut last = None;
+    for seg in segments {
+        let start_time = r.beat(seg.start_time);
+        if seg.start_time == seg.end_time {
+            if last != Some(seg.end) {
+                out.push(single(start_time, seg.end));
+            }
+        } else {
+            if last != Some(seg.start) {
+                out.push(single(start_time, seg.start));
+            }
+            out.push(ranged(start_time, r.beat(seg.end_time), seg.end, seg.easing));
+        }
+        last = Some(seg.end);
+    }
+}
+
+fn serialize_judge_line(r: &mut BpmList, out: &mut Vec<String>, line: &JudgeLine, id: usize, end: f32) {
+    let obj = &line.object;
+    for note in &line.notes {
+        let time = r.beat(note.time);
+        let x = note.object.translation.0.now() * 1024.;
+        let above = if note.above { 1 } else { 2 };
+        let fake = note.fake as u8;
+        let head = match note.kind {
+            NoteKind::Click => format!("n1 {id} {time} {x} {above} {fake}"),
+            NoteKind::Hold { end_time, .. } => format!("n2 {id} {time} {} {x} {above} {fake}", r.beat(end_time)),
+            NoteKind::Flick => format!("n3 {id} {time} {x} {above} {fake}"),
+            NoteKind::Drag => format!("n4 {id} {time} {x} {above} {fake}"),
+        };
+        out.push(format!("{head} # {} & {}", note.speed, note.object.scale.0.now_opt().unwrap_or(1.)));
+    }
+
+    let height = linearize(&line.height, 0., end);
+    let mut last_speed = None;
+    for p in height.windows(2) {
+        let (st, en) = (p[0], p[1]);
+        if en.0 - st.0 < f32::EPSILON {
+            continue;
+        }
+        let speed = (en.1 - st.1) / (en.0 - st.0) * SPEED_RATIO;
+        if last_speed != Some(speed) {
+            out.push(format!("cv {id} {} {speed}", r.beat(st.0)));
+            last_speed = Some(speed);
+        }
+    }
+
+    emit(
+        r,
+        out,
+        &segments(&obj.alpha, 1., end, false),
+        |time, value| format!("ca {id} {time} {}", value * 255.),
+        |time, end_time, value, _| format!("cf {id} {time} {end_time} {}", value * 255.),
+    );
+    emit(
+        r,
+        out,
+        &segments(&obj.rotation, 0., end, true),
+        |time, value| format!("cd {id} {time} {}", -value),
+        |time, end_time, value, easing| format!("cr {id} {time} {end_time} {} {easing}", -value),
+    );
+
+    let x = |v: f32| (v + 1.) / 2. * 2048.;
+    let y = |v: f32| (v + 1.) / 2. * 1400.;
+    let (xs, ys) = match (exact_segments(&obj.translation.0, true), exact_segments(&obj.translation.1, true)) {
+        (Some(xs), Some(ys))
+            if xs.len() == ys.len()
+                && xs
+                    .iter()
+                    .zip(ys.iter())
+                    .all(|(a, b)| a.start_time == b.start_time && a.end_time == b.end_time && a.easing == b.easing) =>
+        {
+            (xs, ys)
+        }
+        _ => {
+            let points = merge_polylines(&linearize(&obj.translation.0, 0., end), &linearize(&obj.translation.1, 0., end));
+            let xs: Vec<_> = points.iter().map(|it| (it.0, it.1)).collect();
+            let ys: Vec<_> = points.iter().map(|it| (it.0, it.2)).collect();
+            (polyline_segments(&xs), polyline_segments(&ys))
+        }
+    };
+    let mut last = None;
+    for (sx, sy) in xs.iter().zip(ys.iter()) {
+        let start_time = r.beat(sx.start_time);
+        let (from, to) = ((sx.start, sy.start), (sx.end, sy.end));
+        if sx.start_time == sx.end_time {
+            if last != Some(to) {
+                out.push(format!("cp {id} {start_time} {} {}", x(to.0), y(to.1)));
+            }
+        } else {
+            if last != Some(from) {
+                out.push(format!("cp {id} {start_time} {} {}", x(from.0), y(from.1)));
+            }
+            out.push(format!("cm {id} {start_time} {} {} {} {}", r.beat(sx.end_time), x(to.0), y(to.1), sx.easing));
+        }
+        last = Some(to);
+    }
+}
+
+/// Exports `chart` as a PEC chart, returning the text along with warnings about features that are lost.
+pub fn serialize_pec(chart: &Chart) -> (String, Vec<String>) {
+    let warnings = lossy_warnings(chart, "PEC");
+    let mut r = chart.bpm_list.borrow_mut();
+    let end = chart_end(chart);
+    let mut out = vec![(((chart.offset + OFFSET_DELTA) * 1000.).round() as i32).to_string()];
+    out.extend(r.ranges().map(|(beats, bpm)| format!("bp {beats} {bpm}")));
+    for (id, line) in chart.lines.iter().enumerate() {
+        serialize_judge_line(&mut r, &mut out, line, id, end);
+    }
+    out.push(String::new());
+    (out.join("\n"), warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{ChartExtra, JudgeLineKind},
+        parse::parse_pec,
+    };
+
+    const CHART: &str = "0
+bp 0 120
+bp 4 240
+cv 0 0 10
+cv 0 4 5
+cp 0 0 1024 700
+cd 0 0 0
+ca 0 0 255
+cm 0 1 2 1536 700 4
+cr 0 2 6 90 2
+cf 0 5 6 0
+n1 0 1 512 1 0
+n2 0 2 3 0 1 0 # 1.5 & 1.2
+n3 0 5 -512 2 0
+n4 0 6 256 1 1
+cp 1 0 1024 300
+n1 1 3 0 2 0";
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3 * (1. + a.abs())
+    }
+
+    fn value(anim: &AnimFloat, time: f32) -> f32 {
+        let mut anim = anim.clone();
+        anim.set_time(time);
+        anim.now()
+    }
+
+    fn assert_line_eq(a: &JudgeLine, b: &JudgeLine, id: usize, end: f32) {
+        let anims = [
+            ("alpha", &a.object.alpha, &b.object.alpha),
+            ("rotation", &a.object.rotation, &b.object.rotation),
+            ("move X", &a.object.translation.0, &b.object.translation.0),
+            ("move Y", &a.object.translation.1, &b.object.translation.1),
+            ("height", &a.height, &b.height),
+        ];
+        for (desc, x, y) in anims {
+            for time in (0..=(end * 10.) as u32).map(|it| it as f32 / 10.) {
+                let (x, y) = (value(x, time), value(y, time));
+                assert!(close(x, y), "{desc} of line #{id} at {time}: {x} != {y}");
+            }
+        }
+        assert_eq!(a.notes.len(), b.notes.len(), "note count of line #{id}");
+        for (index, (x, y)) in a.notes.iter().zip(&b.notes).enumerate() {
+            let desc = format!("note #{index} of line #{id}");
+            assert!(close(x.time, y.time), "time of {desc}: {} != {}", x.time, y.time);
+            assert!(close(x.height, y.height), "height of {desc}: {} != {}", x.height, y.height);
+            assert!(close(x.speed, y.speed), "speed of {desc}");
+            assert!(close(x.object.translation.0.now(), y.object.translation.0.now()), "X of {desc}");
+            assert_eq!(x.object.scale.0.now_opt(), y.object.scale.0.now_opt(), "size of {desc}");
+            assert_eq!((x.above, x.fake), (y.above, y.fake), "side of {desc}");
+            match (&x.kind, &y.kind) {
+                (NoteKind::Hold { end_time: t1, .. }, NoteKind::Hold { end_time: t2, .. }) => assert!(close(*t1, *t2), "hold end of {desc}"),
+                (x, y) => assert_eq!(std::mem::discriminant(x), std::mem::discriminant(y), "kind of {desc}"),
+            }
+        }
+    }
+
+    #[test]
+    fn pec_round_trip() {
+        let chart = parse_pec(CHART, ChartExtra::default()).unwrap();
+        let (text, _) = serialize_pec(&chart);
+        let res = parse_pec(&text, ChartExtra::default()).unwrap();
+        assert!(close(chart.offset, res.offset), "offset: {} != {}", chart.offset, res.offset);
+        let bpm: Vec<_> = res.bpm_list.borrow().ranges().collect();
+        assert_eq!(bpm, [(0., 120.), (4., 240.)]);
+        assert_eq!(chart.lines.len(), res.lines.len());
+        let end = chart_end(&chart);
+        for (id, (x, y)) in chart.lines.iter().zip(&res.lines).enumerate() {
+            assert_line_eq(x, y, id, end);
+        }
+    }
+
+    #[test]
+    fn pec_lossy_warnings() {
+        let mut chart = parse_pec(CHART, ChartExtra::default()).unwrap();
+        chart.lines[1].kind = JudgeLineKind::Text(Default::default());
+        chart.lines[1].z_index = 2;
+        let (_, warnings) = serialize_pec(&chart);
+        assert!(warnings
+            .iter()
+            .any(|it| it == "Line #1: text cannot be represented in PEC and is dropped"));
+        assert!(warnings
+            .iter()
+            .any(|it| it == "Line #1: z order cannot be represented in PEC and is dropped"));
+        assert!(!warnings
+            .iter()
+            .any(|it| it.starts_with("Line #0: text") || it.starts_with("Line #0: z order")));
+    }
+}
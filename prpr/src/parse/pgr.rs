@@ -9,10 +9,13 @@ use crate::{
 };
 use anyhow::{bail, Context, Result};
 use macroquad::prelude::warn;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 
-#[derive(Deserialize)]
+mod serialize;
+pub use serialize::serialize_phigros;
+
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PgrEvent {
     pub start_time: f32,
@@ -23,7 +26,7 @@ struct PgrEvent {
     pub end2: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PgrSpeedEvent {
     pub start_time: f32,
@@ -32,7 +35,7 @@ struct PgrSpeedEvent {
     pub floor_position: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PgrNote {
     #[serde(rename = "type")]
@@ -44,10 +47,16 @@ pub struct PgrNote {
     floor_position: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PgrJudgeLine {
     bpm: f32,
+    #[serde(default)]
+    num_of_notes: usize,
+    #[serde(default)]
+    num_of_notes_above: usize,
+    #[serde(default)]
+    num_of_notes_below: usize,
     #[serde(rename = "judgeLineDisappearEvents")]
     alpha_events: Vec<PgrEvent>,
     #[serde(rename = "judgeLineRotateEvents")]
@@ -60,9 +69,11 @@ struct PgrJudgeLine {
     notes_below: Vec<PgrNote>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PgrChart {
+    #[serde(default)]
+    format_version: u32,
     offset: f32,
     judge_line_list: Vec<PgrJudgeLine>,
 }
@@ -210,6 +221,9 @@ fn parse_judge_line(pgr: PgrJudgeLine, max_time: f32) -> Result<JudgeLine> {
         z_index: 0,
         show_below: true,
         attach_ui: None,
+        name: String::new(),
+        group: None,
+        links: Vec::new(),
 
         cache,
     })
@@ -232,6 +246,10 @@ pub fn parse_phigros(source: &str, extra: ChartExtra) -> Result<Chart> {
         .max()
         .unwrap_or_default()
         + 1.;
+    // Official charts have a BPM for each line, which only scales its times. The chart still needs a real BPM list, since the
+    // dummy default panics on any lookup, and beats are used by the RPE exporter, expression variables and the beat display
+    // when tweaking offsets. The first line's BPM is used.
+    let bpm = pgr.judge_line_list.first().map_or(120., |it| it.bpm);
     let mut lines = pgr
         .judge_line_list
         .into_iter()
@@ -239,5 +257,5 @@ pub fn parse_phigros(source: &str, extra: ChartExtra) -> Result<Chart> {
         .map(|(id, pgr)| parse_judge_line(pgr, max_time).with_context(|| format!("In judge line #{id}")))
         .collect::<Result<Vec<_>>>()?;
     process_lines(&mut lines);
-    Ok(Chart::new(pgr.offset, lines, BpmList::default(), ChartSettings::default(), extra))
+    Ok(Chart::new(pgr.offset, lines, BpmList::new(vec![(0., bpm)]), ChartSettings::default(), extra))
 }
@@ -0,0 +1,238 @@
+use super::{PgrChart, PgrEvent, PgrJudgeLine, PgrNote, PgrSpeedEvent};
+use crate::{
+    core::{Chart, JudgeLine, NoteKind, HEIGHT_RATIO},
+    parse::{chart_end, linearize, lossy_warnings, merge_polylines},
+};
+
+const FORMAT_VERSION: u32 = 3;
+const END_TIME: f32 = 999999999.;
+const POSITION_RATIO: f32 = 2. * 9. / 160.;
+
+fn float_events(points: &[(f32, f32)], r: f32, f: impl Fn(f32) -> f32) -> Vec<PgrEvent> {
+    let event = |st: f32, en: f32, start: f32, end: f32| PgrEvent {
+        start_time: st,
+        end_time: en,
+        start: f(start),
+        end: f(end),
+        start2: 0.,
+        end2: 0.,
+    };
+    let mut res: Vec<_> = points.windows(2).map(|p| event(p[0].0 / r, p[1].0 / r, p[0].1, p[1].1)).collect();
+    let (time, value) = *points.last().unwrap();
+    res.push(event(time / r, END_TIME, value, value));
+    res
+}
+
+fn move_events(points: &[(f32, f32, f32)], r: f32) -> Vec<PgrEvent> {
+    let event = |st: f32, en: f32, start: (f32, f32), end: (f32, f32)| PgrEvent {
+        start_time: st,
+        end_time: en,
+        start: (start.0 + 1.) / 2.,
+        end: (end.0 + 1.) / 2.,
+        start2: (start.1 + 1.) / 2.,
+        end2: (end.1 + 1.) / 2.,
+    };
+    let mut res: Vec<_> = points
+        .windows(2)
+        .map(|p| event(p[0].0 / r, p[1].0 / r, (p[0].1, p[0].2), (p[1].1, p[1].2)))
+        .collect();
+    let (time, x, y) = *points.last().unwrap();
+    res.push(event(time / r, END_TIME, (x, y), (x, y)));
+    res
+}
+
+fn serialize_judge_line(line: &JudgeLine, id: usize, bpm: f32, end: f32, warnings: &mut Vec<String>) -> PgrJudgeLine {
+    let r = 60. / bpm / 32.;
+    let obj = &line.object;
+    let mut speed_events = Vec::new();
+    let height = linearize(&line.height, 0., end);
+    for p in height.windows(2) {
+        let (st, en) = (p[0], p[1]);
+        if en.0 - st.0 < f32::EPSILON {
+            continue;
+        }
+        speed_events.push(PgrSpeedEvent {
+            start_time: st.0 / r,
+            end_time: en.0 / r,
+            value: (en.1 - st.1) / (en.0 - st.0) * HEIGHT_RATIO,
+            floor_position: st.1 * HEIGHT_RATIO,
+        });
+    }
+    let (time, value) = *height.last().unwrap();
+    speed_events.push(PgrSpeedEvent {
+        start_time: time / r,
+        end_time: END_TIME,
+        value: 0.,
+        floor_position: value * HEIGHT_RATIO,
+    });
+    let line_speed = |time: f32| speed_events.iter().rfind(|it| it.start_time * r <= time).map_or(0., |it| it.value);
+
+    let mut notes_above = Vec::new();
+    let mut notes_below = Vec::new();
+    if line.notes.iter().any(|it| it.fake) {
+        warnings.push(format!("Line #{id}: fake notes cannot be represented in official charts and are dropped"));
+    }
+    if line.notes.iter().any(|it| !it.object.scale.0.is_default()) {
+        warnings.push(format!("Line #{id}: note size cannot be represented in official charts and is dropped"));
+    }
+    for note in line.notes.iter().filter(|it| !it.fake) {
+        let pgr = PgrNote {
+            kind: match note.kind {
+                NoteKind::Click => 1,
+                NoteKind::Drag => 2,
+                NoteKind::Hold { .. } => 3,
+                NoteKind::Flick => 4,
+            },
+            time: note.time / r,
+            position_x: note.object.translation.0.now() / POSITION_RATIO,
+            hold_time: match note.kind {
+                NoteKind::Hold { end_time, .. } => (end_time - note.time) / r,
+                _ => 0.,
+            },
+            speed: if matches!(note.kind, NoteKind::Hold { .. }) {
+                note.speed * line_speed(note.time)
+            } else {
+                note.speed
+            },
+            floor_position: note.height * HEIGHT_RATIO,
+        };
+        if note.above {
+            notes_above.push(pgr);
+        } else {
+            notes_below.push(pgr);
+        }
+    }
+
+    PgrJudgeLine {
+        bpm,
+        num_of_notes: notes_above.len() + notes_below.len(),
+        num_of_notes_above: notes_above.len(),
+        num_of_notes_below: notes_below.len(),
+        alpha_events: float_events(&linearize(&obj.alpha, 1., end), r, |it| it),
+        rotate_events: float_events(&linearize(&obj.rotation, 0., end), r, |it| it),
+        move_events: move_events(&merge_polylines(&linearize(&obj.translation.0, 0., end), &linearize(&obj.translation.1, 0., end)), r),
+        speed_events,
+
+        notes_above,
+        notes_below,
+    }
+}
+
+/// Exports `chart` as an official chart (formatVersion 3), returning the JSON along with warnings about features that are lost.
+pub fn serialize_phigros(chart: &Chart) -> (String, Vec<String>) {
+    let mut warnings = lossy_warnings(chart, "official charts");
+    let bpm = chart.bpm_list.borrow().ranges().next().map_or(120., |it| it.1);
+    let end = chart_end(chart);
+    let pgr = PgrChart {
+        format_version: FORMAT_VERSION,
+        offset: chart.offset,
+        judge_line_list: chart
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(id, line)| serialize_judge_line(line, id, bpm, end, &mut warnings))
+            .collect(),
+    };
+    (serde_json::to_string(&pgr).unwrap(), warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{AnimFloat, ChartExtra},
+        parse::parse_phigros,
+    };
+
+    const CHART: &str = r#"{
+        "formatVersion": 3,
+        "offset": 0.1,
+        "judgeLineList": [{
+            "bpm": 120,
+            "judgeLineDisappearEvents": [
+                { "startTime": 0, "endTime": 16, "start": 0, "end": 1, "start2": 0, "end2": 0 },
+                { "startTime": 16, "endTime": 1e9, "start": 1, "end": 1, "start2": 0, "end2": 0 }
+            ],
+            "judgeLineRotateEvents": [
+                { "startTime": 0, "endTime": 32, "start": 0, "end": 45, "start2": 0, "end2": 0 },
+                { "startTime": 32, "endTime": 1e9, "start": 45, "end": 45, "start2": 0, "end2": 0 }
+            ],
+            "judgeLineMoveEvents": [
+                { "startTime": 0, "endTime": 64, "start": 0.5, "end": 0.7, "start2": 0.5, "end2": 0.4 },
+                { "startTime": 64, "endTime": 1e9, "start": 0.7, "end": 0.7, "start2": 0.4, "end2": 0.4 }
+            ],
+            "speedEvents": [
+                { "startTime": 0, "endTime": 64, "value": 1, "floorPosition": 0 },
+                { "startTime": 64, "endTime": 1e9, "value": 2, "floorPosition": 1 }
+            ],
+            "notesAbove": [
+                { "type": 1, "time": 32, "positionX": 1, "holdTime": 0, "speed": 1, "floorPosition": 0.5 },
+                { "type": 3, "time": 96, "positionX": 0, "holdTime": 32, "speed": 2, "floorPosition": 2 }
+            ],
+            "notesBelow": [{ "type": 4, "time": 80, "positionX": -2, "holdTime": 0, "speed": 1.5, "floorPosition": 1.5 }]
+        }]
+    }"#;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3 * (1. + a.abs())
+    }
+
+    fn value(anim: &AnimFloat, time: f32) -> f32 {
+        let mut anim = anim.clone();
+        anim.set_time(time);
+        anim.now()
+    }
+
+    #[test]
+    fn pgr_round_trip() {
+        let chart = parse_phigros(CHART, ChartExtra::default()).unwrap();
+        let (json, warnings) = serialize_phigros(&chart);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        let res = parse_phigros(&json, ChartExtra::default()).unwrap();
+        assert!(close(chart.offset, res.offset));
+        let (a, b) = (&chart.lines[0], &res.lines[0]);
+        let anims = [
+            ("alpha", &a.object.alpha, &b.object.alpha),
+            ("rotation", &a.object.rotation, &b.object.rotation),
+            ("move X", &a.object.translation.0, &b.object.translation.0),
+            ("move Y", &a.object.translation.1, &b.object.translation.1),
+            ("height", &a.height, &b.height),
+        ];
+        for (desc, x, y) in anims {
+            for time in (0..=(chart_end(&chart) * 10.) as u32).map(|it| it as f32 / 10.) {
+                let (x, y) = (value(x, time), value(y, time));
+                assert!(close(x, y), "{desc} at {time}: {x} != {y}");
+            }
+        }
+        assert_eq!(a.notes.len(), b.notes.len());
+        for (index, (x, y)) in a.notes.iter().zip(&b.notes).enumerate() {
+            assert!(close(x.time, y.time), "time of note #{index}: {} != {}", x.time, y.time);
+            assert!(close(x.height, y.height), "height of note #{index}: {} != {}", x.height, y.height);
+            assert!(close(x.speed, y.speed), "speed of note #{index}: {} != {}", x.speed, y.speed);
+            assert!(close(x.object.translation.0.now(), y.object.translation.0.now()), "X of note #{index}");
+            assert_eq!(x.above, y.above, "side of note #{index}");
+            match (&x.kind, &y.kind) {
+                (NoteKind::Hold { end_time: t1, .. }, NoteKind::Hold { end_time: t2, .. }) => assert!(close(*t1, *t2), "hold end of note #{index}"),
+                (x, y) => assert_eq!(std::mem::discriminant(x), std::mem::discriminant(y), "kind of note #{index}"),
+            }
+        }
+    }
+
+    #[test]
+    fn pgr_drops_fake_notes() {
+        let mut chart = parse_phigros(CHART, ChartExtra::default()).unwrap();
+        chart.lines[0].notes[0].fake = true;
+        chart.lines[0].notes[1].object.scale.0 = AnimFloat::fixed(1.5);
+        let (json, warnings) = serialize_phigros(&chart);
+        assert_eq!(
+            warnings,
+            [
+                "Line #0: fake notes cannot be represented in official charts and are dropped",
+                "Line #0: note size cannot be represented in official charts and is dropped",
+            ]
+        );
+        let res = parse_phigros(&json, ChartExtra::default()).unwrap();
+        assert_eq!(res.lines[0].notes.len(), 2);
+        assert!(res.lines[0].notes.iter().all(|it| it.object.scale.0.is_default()));
+    }
+}
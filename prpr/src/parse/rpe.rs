@@ -1,8 +1,9 @@
-use super::{process_lines, RPE_TWEEN_MAP};
+use super::{process_lines, EasingType, RPE_TWEEN_MAP};
 use crate::{
     core::{
-        Anim, AnimFloat, AnimVector, BezierTween, BpmList, Chart, ChartSettings, ClampedTween, CtrlObject, JudgeLine, JudgeLineCache, JudgeLineKind,
-        Keyframe, Note, NoteKind, Object, StaticTween, Triple, TweenFunction, Tweenable, UIElement, EPS, HEIGHT_RATIO, JUDGE_LINE_PERFECT_COLOR, ChartExtra,
+        Anim, AnimFloat, AnimVector, BezierTween, BpmList, Chart, ChartExtra, ChartSettings, ClampedTween, CtrlObject, EasingRegistry, EventLink,
+        JudgeLine, JudgeLineCache, JudgeLineKind, Keyframe, Note, NoteKind, Object, Triple, TweenFunction, Tweenable, UIElement, EPS, HEIGHT_RATIO,
+        JUDGE_LINE_PERFECT_COLOR,
     },
     ext::NotNanExt,
     fs::FileSystem,
@@ -10,14 +11,21 @@ use crate::{
 };
 use anyhow::{bail, Context, Result};
 use macroquad::prelude::Color;
-use serde::Deserialize;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    rc::Rc,
+};
+
+mod serialize;
+pub use serialize::serialize_rpe;
 
 pub const RPE_WIDTH: f32 = 1350.;
 pub const RPE_HEIGHT: f32 = 900.;
 const SPEED_RATIO: f32 = 10. / 45. / HEIGHT_RATIO;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEBpmItem {
     bpm: f32,
@@ -33,10 +41,11 @@ fn f32_one() -> f32 {
     1.
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEEvent<T = f32> {
-    // TODO linkgroup
+    #[serde(default)]
+    linkgroup: i32,
     #[serde(default = "f32_zero")]
     easing_left: f32,
     #[serde(default = "f32_one")]
@@ -45,14 +54,14 @@ struct RPEEvent<T = f32> {
     bezier: u8,
     #[serde(default)]
     bezier_points: [f32; 4],
-    easing_type: i32,
+    easing_type: EasingType,
     start: T,
     end: T,
     start_time: Triple,
     end_time: Triple,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPECtrlEvent {
     easing: u8,
@@ -61,27 +70,33 @@ struct RPECtrlEvent {
     value: HashMap<String, f32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPESpeedEvent {
-    // TODO linkgroup
+    #[serde(default)]
+    linkgroup: i32,
     start_time: Triple,
     end_time: Triple,
     start: f32,
     end: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEEventLayer {
+    #[serde(skip_serializing_if = "Option::is_none")]
     alpha_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     move_x_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     move_y_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     rotate_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     speed_events: Option<Vec<RPESpeedEvent>>,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 struct RGBColor(u8, u8, u8);
 impl From<RGBColor> for Color {
     fn from(RGBColor(r, g, b): RGBColor) -> Self {
@@ -89,18 +104,24 @@ impl From<RGBColor> for Color {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEExtendedEvents {
+    #[serde(skip_serializing_if = "Option::is_none")]
     color_events: Option<Vec<RPEEvent<RGBColor>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     text_events: Option<Vec<RPEEvent<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     scale_x_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     scale_y_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     incline_events: Option<Vec<RPEEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     paint_events: Option<Vec<RPEEvent>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPENote {
     // TODO above == 0? what does that even mean?
@@ -118,13 +139,15 @@ struct RPENote {
     visible_time: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEJudgeLine {
-    // TODO group
-    // TODO alphaControl, bpmfactor
     #[serde(rename = "Name")]
     name: String,
+    #[serde(rename = "Group", default)]
+    group: usize,
+    #[serde(rename = "bpmfactor", default = "f32_one")]
+    bpm_factor: f32,
     #[serde(rename = "Texture")]
     texture: String,
     #[serde(rename = "father")]
@@ -135,7 +158,7 @@ struct RPEJudgeLine {
     is_cover: u8,
     #[serde(default)]
     z_order: i32,
-    #[serde(rename = "attachUI")]
+    #[serde(rename = "attachUI", skip_serializing_if = "Option::is_none")]
     attach_ui: Option<UIElement>,
 
     #[serde(default)]
@@ -148,36 +171,177 @@ struct RPEJudgeLine {
     y_control: Vec<RPECtrlEvent>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEMetadata {
+    #[serde(rename = "RPEVersion", default)]
+    rpe_version: i32,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    charter: String,
+    #[serde(default)]
+    composer: String,
+    #[serde(default)]
+    song: String,
+    #[serde(default)]
+    background: String,
     offset: i32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RPEChart {
     #[serde(rename = "META")]
     meta: RPEMetadata,
     #[serde(rename = "BPMList")]
     bpm_list: Vec<RPEBpmItem>,
+    #[serde(default)]
+    judge_line_group: Vec<String>,
     judge_line_list: Vec<RPEJudgeLine>,
 }
 
+/// An event which can be in a link group.
+trait Linked: Clone {
+    fn link_group(&self) -> i32;
+    fn set_link_group(&mut self, group: i32);
+    fn start_time(&self) -> &Triple;
+    /// Takes the values of another event in the group, keeping its own times.
+    fn link_to(&mut self, other: &Self);
+}
+
+impl<T: Clone> Linked for RPEEvent<T> {
+    fn link_group(&self) -> i32 {
+        self.linkgroup
+    }
+
+    fn set_link_group(&mut self, group: i32) {
+        self.linkgroup = group;
+    }
+
+    fn start_time(&self) -> &Triple {
+        &self.start_time
+    }
+
+    fn link_to(&mut self, other: &Self) {
+        self.easing_left = other.easing_left;
+        self.easing_right = other.easing_right;
+        self.bezier = other.bezier;
+        self.bezier_points = other.bezier_points;
+        self.easing_type = other.easing_type.clone();
+        self.start = other.start.clone();
+        self.end = other.end.clone();
+    }
+}
+
+impl Linked for RPESpeedEvent {
+    fn link_group(&self) -> i32 {
+        self.linkgroup
+    }
+
+    fn set_link_group(&mut self, group: i32) {
+        self.linkgroup = group;
+    }
+
+    fn start_time(&self) -> &Triple {
+        &self.start_time
+    }
+
+    fn link_to(&mut self, other: &Self) {
+        self.start = other.start;
+        self.end = other.end;
+    }
+}
+
+fn link_events<E: Linked>(events: &mut Option<Vec<E>>, groups: &mut HashMap<i32, E>) {
+    for event in events.iter_mut().flatten() {
+        if event.link_group() == 0 {
+            continue;
+        }
+        match groups.entry(event.link_group()) {
+            Entry::Occupied(first) => event.link_to(first.get()),
+            Entry::Vacant(entry) => {
+                entry.insert(event.clone());
+            }
+        }
+    }
+}
+
+/// Events in a link group are edited together in RPE, so they all take the values (and easing) of the first one in the chart.
+/// Each kind of event has its own groups.
+fn link_chart(rpe: &mut RPEChart) {
+    let mut floats: [HashMap<i32, RPEEvent>; 8] = Default::default();
+    let mut speed = HashMap::new();
+    let mut color = HashMap::new();
+    let mut text = HashMap::new();
+    let [alpha, move_x, move_y, rotate, scale_x, scale_y, incline, paint] = &mut floats;
+    for line in &mut rpe.judge_line_list {
+        for layer in line.event_layers.iter_mut().flatten() {
+            link_events(&mut layer.alpha_events, alpha);
+            link_events(&mut layer.move_x_events, move_x);
+            link_events(&mut layer.move_y_events, move_y);
+            link_events(&mut layer.rotate_events, rotate);
+            link_events(&mut layer.speed_events, &mut speed);
+        }
+        if let Some(e) = &mut line.extended {
+            link_events(&mut e.color_events, &mut color);
+            link_events(&mut e.text_events, &mut text);
+            link_events(&mut e.scale_x_events, scale_x);
+            link_events(&mut e.scale_y_events, scale_y);
+            link_events(&mut e.incline_events, incline);
+            link_events(&mut e.paint_events, paint);
+        }
+    }
+}
+
+fn event_links(r: &mut BpmList, rpe: &RPEJudgeLine) -> Vec<EventLink> {
+    fn add<E: Linked>(res: &mut Vec<EventLink>, r: &mut BpmList, events: &Option<Vec<E>>, name: &'static str) {
+        for e in events.iter().flatten().filter(|it| it.link_group() != 0) {
+            res.push(EventLink {
+                events: name,
+                time: r.time(e.start_time()),
+                group: e.link_group(),
+            });
+        }
+    }
+    let mut res = Vec::new();
+    for layer in rpe.event_layers.iter().flatten() {
+        add(&mut res, r, &layer.alpha_events, "alphaEvents");
+        add(&mut res, r, &layer.move_x_events, "moveXEvents");
+        add(&mut res, r, &layer.move_y_events, "moveYEvents");
+        add(&mut res, r, &layer.rotate_events, "rotateEvents");
+        add(&mut res, r, &layer.speed_events, "speedEvents");
+    }
+    if let Some(e) = &rpe.extended {
+        add(&mut res, r, &e.color_events, "colorEvents");
+        add(&mut res, r, &e.text_events, "textEvents");
+        add(&mut res, r, &e.scale_x_events, "scaleXEvents");
+        add(&mut res, r, &e.scale_y_events, "scaleYEvents");
+        add(&mut res, r, &e.incline_events, "inclineEvents");
+        add(&mut res, r, &e.paint_events, "paintEvents");
+    }
+    res
+}
+
 type BezierMap = HashMap<(u16, i16, i16), Rc<dyn TweenFunction>>;
 
+/// Tweens shared by events: bezier curves by their points, and custom easings by name.
+struct Tweens<'a> {
+    bezier: BezierMap,
+    easings: &'a EasingRegistry,
+}
+
 fn bezier_key<T>(event: &RPEEvent<T>) -> (u16, i16, i16) {
     let p = &event.bezier_points;
     let int = |p: f32| (p * 100.).round() as i16;
     ((int(p[0]) * 100 + int(p[1])) as u16, int(p[2]), int(p[3]))
 }
 
-fn parse_events<T: Tweenable, V: Clone + Into<T>>(
-    r: &mut BpmList,
-    rpe: &[RPEEvent<V>],
-    default: Option<T>,
-    bezier_map: &BezierMap,
-) -> Result<Anim<T>> {
+fn parse_events<T: Tweenable, V: Clone + Into<T>>(r: &mut BpmList, rpe: &[RPEEvent<V>], default: Option<T>, tweens: &Tweens) -> Result<Anim<T>> {
     let mut kfs = Vec::new();
     if let Some(default) = default {
         if rpe[0].start_time.beats() != 0.0 {
@@ -188,15 +352,10 @@ fn parse_events<T: Tweenable, V: Clone + Into<T>>(
         kfs.push(Keyframe {
             time: r.time(&e.start_time),
             value: e.start.clone().into(),
-            tween: {
-                let tween = RPE_TWEEN_MAP.get(e.easing_type.max(1) as usize).copied().unwrap_or(RPE_TWEEN_MAP[0]);
-                if e.bezier != 0 {
-                    Rc::clone(&bezier_map[&bezier_key(e)])
-                } else if e.easing_left.abs() < EPS && (e.easing_right - 1.0).abs() < EPS {
-                    StaticTween::get_rc(tween)
-                } else {
-                    Rc::new(ClampedTween::new(tween, e.easing_left..e.easing_right))
-                }
+            tween: if e.bezier != 0 {
+                Rc::clone(&tweens.bezier[&bezier_key(e)])
+            } else {
+                e.easing_type.tween(e.easing_left..e.easing_right, tweens.easings)?
             },
         });
         kfs.push(Keyframe::new(r.time(&e.end_time), e.end.clone().into(), 0));
@@ -342,7 +501,15 @@ fn parse_ctrl_events(rpe: &[RPECtrlEvent], key: &str) -> AnimFloat {
     )
 }
 
-async fn parse_judge_line(r: &mut BpmList, rpe: RPEJudgeLine, max_time: f32, fs: &mut dyn FileSystem, bezier_map: &BezierMap) -> Result<JudgeLine> {
+async fn parse_judge_line(
+    r: &mut BpmList,
+    rpe: RPEJudgeLine,
+    max_time: f32,
+    fs: &mut dyn FileSystem,
+    tweens: &Tweens,
+    load_texture: bool,
+) -> Result<JudgeLine> {
+    let links = event_links(r, &rpe);
     let event_layers: Vec<_> = rpe.event_layers.into_iter().flatten().collect();
     fn events_with_factor(
         r: &mut BpmList,
@@ -350,11 +517,11 @@ async fn parse_judge_line(r: &mut BpmList, rpe: RPEJudgeLine, max_time: f32, fs:
         get: impl Fn(&RPEEventLayer) -> &Option<Vec<RPEEvent>>,
         factor: f32,
         desc: &str,
-        bezier_map: &BezierMap,
+        tweens: &Tweens,
     ) -> Result<AnimFloat> {
         let anis: Vec<_> = event_layers
             .iter()
-            .filter_map(|it| get(it).as_ref().map(|es| parse_events(r, es, None, bezier_map)))
+            .filter_map(|it| get(it).as_ref().map(|es| parse_events(r, es, None, tweens)))
             .collect::<Result<_>>()
             .with_context(|| format!("Failed to parse {desc} events"))?;
         let mut res = AnimFloat::chain(anis);
@@ -366,19 +533,15 @@ async fn parse_judge_line(r: &mut BpmList, rpe: RPEJudgeLine, max_time: f32, fs:
     let cache = JudgeLineCache::new(&mut notes);
     Ok(JudgeLine {
         object: Object {
-            alpha: events_with_factor(r, &event_layers, |it| &it.alpha_events, 1. / 255., "alpha", bezier_map)?,
-            rotation: events_with_factor(r, &event_layers, |it| &it.rotate_events, -1., "rotate", bezier_map)?,
+            alpha: events_with_factor(r, &event_layers, |it| &it.alpha_events, 1. / 255., "alpha", tweens)?,
+            rotation: events_with_factor(r, &event_layers, |it| &it.rotate_events, -1., "rotate", tweens)?,
             translation: AnimVector(
-                events_with_factor(r, &event_layers, |it| &it.move_x_events, 2. / RPE_WIDTH, "move X", bezier_map)?,
-                events_with_factor(r, &event_layers, |it| &it.move_y_events, 2. / RPE_HEIGHT, "move Y", bezier_map)?,
+                events_with_factor(r, &event_layers, |it| &it.move_x_events, 2. / RPE_WIDTH, "move X", tweens)?,
+                events_with_factor(r, &event_layers, |it| &it.move_y_events, 2. / RPE_HEIGHT, "move Y", tweens)?,
             ),
             scale: {
-                fn parse(r: &mut BpmList, opt: &Option<Vec<RPEEvent>>, factor: f32, bezier_map: &BezierMap) -> Result<AnimFloat> {
-                    let mut res = opt
-                        .as_ref()
-                        .map(|it| parse_events(r, it, None, bezier_map))
-                        .transpose()?
-                        .unwrap_or_default();
+                fn parse(r: &mut BpmList, opt: &Option<Vec<RPEEvent>>, factor: f32, tweens: &Tweens) -> Result<AnimFloat> {
+                    let mut res = opt.as_ref().map(|it| parse_events(r, it, None, tweens)).transpose()?.unwrap_or_default();
                     res.map_value(|v| v * factor);
                     Ok(res)
                 }
@@ -406,9 +569,9 @@ async fn parse_judge_line(r: &mut BpmList, rpe: RPEJudgeLine, max_time: f32, fs:
                                     } else {
                                         1.
                                     },
-                                bezier_map,
+                                tweens,
                             )?,
-                            parse(r, &e.scale_y_events, factor, bezier_map)?,
+                            parse(r, &e.scale_y_events, factor, tweens)?,
                         ))
                     })
                     .transpose()?
@@ -423,31 +586,34 @@ async fn parse_judge_line(r: &mut BpmList, rpe: RPEJudgeLine, max_time: f32, fs:
         }),
         height,
         incline: if let Some(events) = rpe.extended.as_ref().and_then(|e| e.incline_events.as_ref()) {
-            parse_events(r, events, Some(0.), bezier_map).context("Failed to parse incline events")?
+            parse_events(r, events, Some(0.), tweens).context("Failed to parse incline events")?
         } else {
             AnimFloat::default()
         },
         notes,
         kind: if rpe.texture == "line.png" {
             if let Some(events) = rpe.extended.as_ref().and_then(|e| e.paint_events.as_ref()) {
-                JudgeLineKind::Paint(parse_events(r, events, Some(-1.), bezier_map).context("Failed to parse paint events")?, RefCell::default())
+                JudgeLineKind::Paint(parse_events(r, events, Some(-1.), tweens).context("Failed to parse paint events")?, RefCell::default())
             } else if let Some(events) = rpe.extended.as_ref().and_then(|e| e.text_events.as_ref()) {
-                JudgeLineKind::Text(parse_events(r, events, Some(String::new()), bezier_map).context("Failed to parse text events")?)
+                JudgeLineKind::Text(parse_events(r, events, Some(String::new()), tweens).context("Failed to parse text events")?)
             } else {
                 JudgeLineKind::Normal
             }
         } else {
-            JudgeLineKind::Texture(
-                image::load_from_memory(
-                    &fs.load_file(&rpe.texture)
-                        .await
-                        .with_context(|| format!("加载插图 {} 失败", rpe.texture))?,
-                )?
-                .into(),
-            )
+            let image = image::load_from_memory(
+                &fs.load_file(&rpe.texture)
+                    .await
+                    .with_context(|| format!("加载插图 {} 失败", rpe.texture))?,
+            )?;
+            if load_texture {
+                JudgeLineKind::Texture(image.into(), rpe.texture.clone())
+            } else {
+                // no GL context to upload the texture to
+                JudgeLineKind::Normal
+            }
         },
         color: if let Some(events) = rpe.extended.as_ref().and_then(|e| e.color_events.as_ref()) {
-            parse_events(r, events, Some(JUDGE_LINE_PERFECT_COLOR), bezier_map).context("Failed to parse color events")?
+            parse_events(r, events, Some(JUDGE_LINE_PERFECT_COLOR), tweens).context("Failed to parse color events")?
         } else {
             Anim::default()
         },
@@ -462,6 +628,9 @@ async fn parse_judge_line(r: &mut BpmList, rpe: RPEJudgeLine, max_time: f32, fs:
         z_index: rpe.z_order,
         show_below: rpe.is_cover != 1,
         attach_ui: rpe.attach_ui,
+        name: rpe.name,
+        group: None,
+        links,
 
         cache,
     })
@@ -496,8 +665,24 @@ fn get_bezier_map(rpe: &RPEChart) -> BezierMap {
 }
 
 pub async fn parse_rpe(source: &str, fs: &mut dyn FileSystem, extra: ChartExtra) -> Result<Chart> {
-    let rpe: RPEChart = serde_json::from_str(source).context("Failed to parse JSON")?;
-    let bezier_map = get_bezier_map(&rpe);
+    parse_rpe_inner(source, fs, extra, true).await
+}
+
+// texture lines are parsed as normal lines, so that charts can be checked without a window
+pub(crate) async fn parse_rpe_headless(source: &str, fs: &mut dyn FileSystem, extra: ChartExtra) -> Result<Chart> {
+    parse_rpe_inner(source, fs, extra, false).await
+}
+
+async fn parse_rpe_inner(source: &str, fs: &mut dyn FileSystem, extra: ChartExtra, load_texture: bool) -> Result<Chart> {
+    let mut rpe: RPEChart = serde_json::from_str(source).context("Failed to parse JSON")?;
+    link_chart(&mut rpe);
+    let tweens = Tweens {
+        bezier: get_bezier_map(&rpe),
+        easings: &extra.easings,
+    };
+    if let Some((id, line)) = rpe.judge_line_list.iter().enumerate().find(|(_, it)| it.bpm_factor <= 0.) {
+        bail!("In judge line #{id} ({}): bpmfactor should be positive, got {}", line.name, line.bpm_factor);
+    }
     let mut r = BpmList::new(rpe.bpm_list.into_iter().map(|it| (it.start_time.beats(), it.bpm)).collect());
     fn vec<T>(v: &Option<Vec<T>>) -> impl Iterator<Item = &T> {
         v.iter().flat_map(|it| it.iter())
@@ -507,6 +692,7 @@ pub async fn parse_rpe(source: &str, fs: &mut dyn FileSystem, extra: ChartExtra)
         .judge_line_list
         .iter()
         .map(|line| {
+            let mut r = r.with_factor(line.bpm_factor);
             line.notes.as_ref().map(|notes| {
                 notes
                     .iter()
@@ -535,14 +721,113 @@ pub async fn parse_rpe(source: &str, fs: &mut dyn FileSystem, extra: ChartExtra)
         .max().unwrap_or_default() + 1.;
     // don't want to add a whole crate for a mere join_all...
     let mut lines = Vec::new();
+    let groups = rpe.judge_line_group;
     for (id, rpe) in rpe.judge_line_list.into_iter().enumerate() {
         let name = rpe.name.clone();
-        lines.push(
-            parse_judge_line(&mut r, rpe, max_time, fs, &bezier_map)
-                .await
-                .with_context(move || format!("In judge line #{id} ({})", name))?,
-        );
+        let group = groups.get(rpe.group).cloned();
+        let mut line_r = r.with_factor(rpe.bpm_factor);
+        let mut line = parse_judge_line(&mut line_r, rpe, max_time, fs, &tweens, load_texture)
+            .await
+            .with_context(move || format!("In judge line #{id} ({})", name))?;
+        line.group = group;
+        lines.push(line);
     }
     process_lines(&mut lines);
     Ok(Chart::new(rpe.meta.offset as f32 / 1000.0, lines, r, ChartSettings::default(), extra))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::fs_from_file;
+
+    // Lines A and B share link groups, and B still has stale values from before the last edit in A. C has the same stale values
+    // without a group.
+    const LINKED_CHART: &str = r#"{
+        "META": { "RPEVersion": 140, "offset": 0 },
+        "BPMList": [{ "bpm": 120, "startTime": [0, 0, 1] }],
+        "judgeLineList": [
+            {
+                "Name": "A", "Texture": "line.png", "father": -1, "isCover": 1,
+                "eventLayers": [{
+                    "moveXEvents": [{ "linkgroup": 1, "startTime": [0, 0, 1], "endTime": [2, 0, 1], "start": -300, "end": 300, "easingType": 3 }],
+                    "speedEvents": [{ "linkgroup": 2, "startTime": [0, 0, 1], "endTime": [4, 0, 1], "start": 10, "end": 5 }]
+                }]
+            },
+            {
+                "Name": "B", "Texture": "line.png", "father": -1, "isCover": 1,
+                "eventLayers": [{
+                    "moveXEvents": [{ "linkgroup": 1, "startTime": [0, 0, 1], "endTime": [2, 0, 1], "start": 0, "end": 100, "easingType": 1 }],
+                    "speedEvents": [{ "linkgroup": 2, "startTime": [0, 0, 1], "endTime": [4, 0, 1], "start": 1, "end": 1 }]
+                }]
+            },
+            {
+                "Name": "C", "Texture": "line.png", "father": -1, "isCover": 1,
+                "eventLayers": [{
+                    "moveXEvents": [{ "startTime": [0, 0, 1], "endTime": [2, 0, 1], "start": 0, "end": 100, "easingType": 1 }],
+                    "speedEvents": [{ "startTime": [0, 0, 1], "endTime": [4, 0, 1], "start": 1, "end": 1 }]
+                }]
+            }
+        ]
+    }"#;
+
+    fn value(anim: &AnimFloat, time: f32) -> f32 {
+        let mut anim = anim.clone();
+        anim.set_time(time);
+        anim.now()
+    }
+
+    fn parse(source: &str) -> Result<Chart> {
+        let mut fs = fs_from_file(&std::env::temp_dir()).unwrap();
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(parse_rpe_headless(source, fs.as_mut(), ChartExtra::default()))
+    }
+
+    #[test]
+    fn linked_events() {
+        let chart = parse(LINKED_CHART).unwrap();
+        let [a, b, c] = &chart.lines[..] else { unreachable!() };
+        for time in (0..=30).map(|it| it as f32 / 10.) {
+            assert_eq!(value(&b.object.translation.0, time), value(&a.object.translation.0, time), "move X at {time}");
+            assert_eq!(value(&b.height, time), value(&a.height, time), "height at {time}");
+        }
+        assert!((value(&b.object.translation.0, 1.) - 300. * 2. / RPE_WIDTH).abs() < 1e-5);
+        assert!((value(&c.object.translation.0, 1.) - 100. * 2. / RPE_WIDTH).abs() < 1e-5);
+        assert!(value(&c.height, 1.) < value(&b.height, 1.));
+
+        let json: serde_json::Value = serde_json::from_str(&serialize_rpe(&chart, &Default::default())).unwrap();
+        let groups: Vec<_> = json["judgeLineList"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|line| {
+                let layer = &line["eventLayers"][0];
+                (layer["moveXEvents"][0]["linkgroup"].as_i64().unwrap(), layer["speedEvents"][0]["linkgroup"].as_i64().unwrap())
+            })
+            .collect();
+        assert_eq!(groups, [(1, 2), (1, 2), (0, 0)]);
+        let links: Vec<_> = parse(&json.to_string()).unwrap().lines[1]
+            .links
+            .iter()
+            .map(|it| (it.events, it.group))
+            .collect();
+        assert_eq!(links, [("moveXEvents", 1), ("speedEvents", 2)]);
+    }
+
+    #[test]
+    fn bpm_factor() {
+        let chart = LINKED_CHART.replacen(r#""Name": "B","#, r#""Name": "B", "bpmfactor": 2,"#, 1);
+        let chart = parse(&chart).unwrap();
+        // the linked move keeps the times of B, so it ends after 2 beats of 60 BPM instead of 120
+        assert!((value(&chart.lines[1].object.translation.0, 1.) - value(&chart.lines[0].object.translation.0, 0.5)).abs() < 1e-5);
+        assert!((value(&chart.lines[1].object.translation.0, 2.) - value(&chart.lines[0].object.translation.0, 1.)).abs() < 1e-5);
+
+        for factor in ["0", "-1"] {
+            let chart = LINKED_CHART.replacen(r#""Name": "B","#, &format!(r#""Name": "B", "bpmfactor": {factor},"#), 1);
+            let err = format!("{:?}", parse(&chart).err().unwrap());
+            assert!(err.contains("In judge line #1 (B): bpmfactor should be positive"), "{err}");
+        }
+    }
+}
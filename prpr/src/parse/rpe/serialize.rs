@@ -0,0 +1,615 @@
+use super::{
+    Linked, RGBColor, RPEBpmItem, RPEChart, RPECtrlEvent, RPEEvent, RPEEventLayer, RPEExtendedEvents, RPEJudgeLine, RPEMetadata, RPENote,
+    RPESpeedEvent, RPE_HEIGHT, RPE_WIDTH, SPEED_RATIO,
+};
+use crate::{
+    core::{
+        easing_from, Anim, AnimFloat, BezierTween, BpmList, Chart, ClampedTween, EventLink, JudgeLine, JudgeLineKind, Note, NoteKind, StaticTween,
+        Triple, TweenFunction, TweenId, TweenMajor, TweenMinor, Tweenable, EPS,
+    },
+    ext::NotNanExt,
+    info::ChartInfo,
+    parse::{rpe_easing_type, EasingType},
+};
+use macroquad::prelude::Color;
+use std::collections::HashMap;
+
+const RPE_VERSION: i32 = 100;
+const VISIBLE_FOREVER: f32 = 999999.;
+const CTRL_END: f32 = 9999999.;
+const DEFAULT_GROUP: &str = "Default";
+const LINK_TIME_EPS: f32 = 1e-3;
+
+struct Easing {
+    easing_type: i32,
+    easing_left: f32,
+    easing_right: f32,
+    bezier: u8,
+    bezier_points: [f32; 4],
+}
+
+fn easing_type(tween: TweenId) -> i32 {
+    rpe_easing_type(tween).unwrap_or(1)
+}
+
+fn static_tween(tween: &dyn TweenFunction) -> Option<TweenId> {
+    tween.as_any().downcast_ref::<StaticTween>().map(|it| it.0)
+}
+
+fn easing_of(tween: &dyn TweenFunction) -> Easing {
+    let linear = Easing {
+        easing_type: 1,
+        easing_left: 0.,
+        easing_right: 1.,
+        bezier: 0,
+        bezier_points: [0.; 4],
+    };
+    let any = tween.as_any();
+    if let Some(tween) = any.downcast_ref::<StaticTween>() {
+        Easing {
+            easing_type: easing_type(tween.0),
+            ..linear
+        }
+    } else if let Some(tween) = any.downcast_ref::<ClampedTween>() {
+        Easing {
+            easing_type: easing_type(tween.0),
+            easing_left: tween.1.start,
+            easing_right: tween.1.end,
+            ..linear
+        }
+    } else if let Some(tween) = any.downcast_ref::<BezierTween>() {
+        Easing {
+            bezier: 1,
+            bezier_points: [tween.p1.0, tween.p1.1, tween.p2.0, tween.p2.1],
+            ..linear
+        }
+    } else {
+        linear
+    }
+}
+
+fn event<V: Clone>(r: &mut BpmList, start_time: f32, end_time: f32, start: V, end: V, easing: Easing) -> RPEEvent<V> {
+    RPEEvent {
+        linkgroup: 0,
+        easing_left: easing.easing_left,
+        easing_right: easing.easing_right,
+        bezier: easing.bezier,
+        bezier_points: easing.bezier_points,
+        easing_type: EasingType::Id(easing.easing_type),
+        start,
+        end,
+        start_time: Triple::from_beats(r.beat(start_time)),
+        end_time: Triple::from_beats(r.beat(end_time)),
+    }
+}
+
+fn events<T: Tweenable, V: Clone>(r: &mut BpmList, anim: &Anim<T>, f: impl Fn(&T) -> V) -> Vec<RPEEvent<V>> {
+    let kfs = &anim.keyframes;
+    let mut res = Vec::new();
+    // whether the current keyframe is the end of the last event
+    let mut covered = false;
+    for (i, kf) in kfs.iter().enumerate() {
+        let next = kfs.get(i + 1);
+        match (next, static_tween(kf.tween.as_ref())) {
+            (Some(next), Some(1)) => {
+                // jumps to the next value immediately
+                let value = f(&next.value);
+                res.push(event(r, kf.time, kf.time, value.clone(), value, easing_of(kf.tween.as_ref())));
+                covered = true;
+            }
+            (Some(next), tween) if tween != Some(0) => {
+                res.push(event(r, kf.time, next.time, f(&kf.value), f(&next.value), easing_of(kf.tween.as_ref())));
+                covered = true;
+            }
+            _ => {
+                if !covered {
+                    let value = f(&kf.value);
+                    res.push(event(r, kf.time, kf.time, value.clone(), value, easing_of(kf.tween.as_ref())));
+                }
+                covered = false;
+            }
+        }
+    }
+    res
+}
+
+fn layers<T: Tweenable>(anim: &Anim<T>) -> Vec<&Anim<T>> {
+    let mut res = Vec::new();
+    let mut cur = Some(anim);
+    while let Some(anim) = cur {
+        if !anim.keyframes.is_empty() {
+            res.push(anim);
+        }
+        cur = anim.next.as_deref();
+    }
+    res
+}
+
+fn optional_events<T: Tweenable, V: Clone>(r: &mut BpmList, anim: &Anim<T>, f: impl Fn(&T) -> V) -> Option<Vec<RPEEvent<V>>> {
+    if anim.keyframes.is_empty() {
+        None
+    } else {
+        Some(events(r, anim, f))
+    }
+}
+
+fn speed_events(r: &mut BpmList, height: &AnimFloat) -> Option<Vec<RPESpeedEvent>> {
+    const QUAD_IN: TweenId = easing_from(TweenMajor::Quad, TweenMinor::In);
+    const QUAD_OUT: TweenId = easing_from(TweenMajor::Quad, TweenMinor::Out);
+    if height.keyframes.is_empty() {
+        return None;
+    }
+    let mut res = Vec::new();
+    for pair in height.keyframes.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let dt = b.time - a.time;
+        if dt < EPS {
+            continue;
+        }
+        // height is the integral of speed, see `parse_speed_events`
+        let avg = (b.value - a.value) / dt;
+        let (start, end) = match a.tween.as_any().downcast_ref::<ClampedTween>() {
+            Some(tween) if tween.0 == QUAD_OUT => {
+                let q = tween.1.end;
+                let start = avg * 2. / (2. - q);
+                (start, start * (1. - q))
+            }
+            Some(tween) if tween.0 == QUAD_IN => {
+                let p = tween.1.start;
+                let end = avg * 2. / (1. + p);
+                (end * p, end)
+            }
+            _ => (avg, avg),
+        };
+        res.push(RPESpeedEvent {
+            linkgroup: 0,
+            start_time: Triple::from_beats(r.beat(a.time)),
+            end_time: Triple::from_beats(r.beat(b.time)),
+            start: start / SPEED_RATIO,
+            end: end / SPEED_RATIO,
+        });
+    }
+    Some(res)
+}
+
+fn ctrl_events(anim: &AnimFloat, key: &str) -> Vec<RPECtrlEvent> {
+    let ctrl = |easing: i32, x: f32, value: f32| RPECtrlEvent {
+        easing: easing as u8,
+        x,
+        value: HashMap::from([(key.to_owned(), value)]),
+    };
+    if anim.keyframes.is_empty() {
+        return vec![ctrl(1, 0., 1.), ctrl(1, CTRL_END, 1.)];
+    }
+    anim.keyframes
+        .iter()
+        .map(|kf| ctrl(easing_of(kf.tween.as_ref()).easing_type, kf.time, kf.value))
+        .collect()
+}
+
+fn serialize_note(r: &mut BpmList, note: &Note) -> RPENote {
+    let (alpha, visible_time) = match note.object.alpha.keyframes.as_ref() {
+        [] => (255, VISIBLE_FOREVER),
+        [kf] => ((kf.value * 255.).round() as u16, VISIBLE_FOREVER),
+        [_, kf, ..] => ((kf.value * 255.).round() as u16, note.time - kf.time),
+    };
+    let end_time = match note.kind {
+        NoteKind::Hold { end_time, .. } => end_time,
+        _ => note.time,
+    };
+    RPENote {
+        kind: match note.kind {
+            NoteKind::Click => 1,
+            NoteKind::Hold { .. } => 2,
+            NoteKind::Flick => 3,
+            NoteKind::Drag => 4,
+        },
+        above: if note.above { 1 } else { 2 },
+        start_time: Triple::from_beats(r.beat(note.time)),
+        end_time: Triple::from_beats(r.beat(end_time)),
+        position_x: note.object.translation.0.now() * (RPE_WIDTH / 2.),
+        y_offset: if note.speed.abs() < EPS {
+            0.
+        } else {
+            note.object.translation.1.now() * (RPE_HEIGHT / 2.) / note.speed
+        },
+        alpha,
+        size: note.object.scale.0.now_opt().unwrap_or(1.),
+        speed: note.speed,
+        is_fake: note.fake as u8,
+        visible_time,
+    }
+}
+
+/// Puts the events back into their link groups, found by their start times.
+fn restore_links(r: &mut BpmList, line: &mut RPEJudgeLine, links: &[EventLink]) {
+    fn restore<E: Linked>(r: &mut BpmList, events: &mut Option<Vec<E>>, name: &str, links: &[EventLink], used: &mut [bool]) {
+        for e in events.iter_mut().flatten() {
+            let time = r.time(e.start_time());
+            if let Some(id) = (0..links.len()).find(|&id| !used[id] && links[id].events == name && (links[id].time - time).abs() < LINK_TIME_EPS) {
+                used[id] = true;
+                e.set_link_group(links[id].group);
+            }
+        }
+    }
+    let used = &mut vec![false; links.len()];
+    for layer in line.event_layers.iter_mut().flatten() {
+        restore(r, &mut layer.alpha_events, "alphaEvents", links, used);
+        restore(r, &mut layer.move_x_events, "moveXEvents", links, used);
+        restore(r, &mut layer.move_y_events, "moveYEvents", links, used);
+        restore(r, &mut layer.rotate_events, "rotateEvents", links, used);
+        restore(r, &mut layer.speed_events, "speedEvents", links, used);
+    }
+    if let Some(e) = &mut line.extended {
+        restore(r, &mut e.color_events, "colorEvents", links, used);
+        restore(r, &mut e.text_events, "textEvents", links, used);
+        restore(r, &mut e.scale_x_events, "scaleXEvents", links, used);
+        restore(r, &mut e.scale_y_events, "scaleYEvents", links, used);
+        restore(r, &mut e.incline_events, "inclineEvents", links, used);
+        restore(r, &mut e.paint_events, "paintEvents", links, used);
+    }
+}
+
+fn serialize_judge_line(r: &mut BpmList, line: &JudgeLine, groups: &[String]) -> RPEJudgeLine {
+    let obj = &line.object;
+    let alpha = layers(&obj.alpha);
+    let rotation = layers(&obj.rotation);
+    let move_x = layers(&obj.translation.0);
+    let move_y = layers(&obj.translation.1);
+    let mut speed = speed_events(r, &line.height);
+    let count = [alpha.len(), rotation.len(), move_x.len(), move_y.len()]
+        .into_iter()
+        .max()
+        .unwrap()
+        .max(1);
+    let event_layers = (0..count)
+        .map(|i| {
+            let mut layer_events = |anim: Option<&&AnimFloat>, factor: f32| anim.map(|anim| events(r, anim, |v| v * factor));
+            Some(RPEEventLayer {
+                alpha_events: layer_events(alpha.get(i), 255.),
+                move_x_events: layer_events(move_x.get(i), RPE_WIDTH / 2.),
+                move_y_events: layer_events(move_y.get(i), RPE_HEIGHT / 2.),
+                rotate_events: layer_events(rotation.get(i), -1.),
+                speed_events: speed.take(),
+            })
+        })
+        .collect();
+
+    let texture = match &line.kind {
+        JudgeLineKind::Texture(_, path) => path.clone(),
+        _ => "line.png".to_owned(),
+    };
+    let scale_factor = match line.kind {
+        JudgeLineKind::Texture(..) => 2.57 / RPE_WIDTH,
+        JudgeLineKind::Text(_) => 1.,
+        _ if line.attach_ui.is_some() => 1.,
+        _ => 0.5,
+    };
+    let scale_y_factor = if matches!(line.kind, JudgeLineKind::Texture(..)) {
+        2.57 / RPE_WIDTH
+    } else {
+        1.
+    };
+    let extended = RPEExtendedEvents {
+        color_events: optional_events(r, &line.color, |c: &Color| {
+            RGBColor((c.r * 255.).round() as u8, (c.g * 255.).round() as u8, (c.b * 255.).round() as u8)
+        }),
+        text_events: match &line.kind {
+            JudgeLineKind::Text(anim) => Some(events(r, anim, String::clone)),
+            _ => None,
+        },
+        scale_x_events: optional_events(r, &obj.scale.0, |v| v / scale_factor),
+        scale_y_events: optional_events(r, &obj.scale.1, |v| v / scale_y_factor),
+        incline_events: optional_events(r, &line.incline, |v| *v),
+        paint_events: match &line.kind {
+            JudgeLineKind::Paint(anim, _) => Some(events(r, anim, |v| *v)),
+            _ => None,
+        },
+    };
+
+    let mut notes: Vec<_> = line.notes.iter().collect();
+    notes.sort_by_key(|it| it.time.not_nan());
+    let ctrl_obj = line.ctrl_obj.borrow();
+    let mut res = RPEJudgeLine {
+        name: if line.name.is_empty() {
+            "Untitled".to_owned()
+        } else {
+            line.name.clone()
+        },
+        group: line
+            .group
+            .as_ref()
+            .and_then(|group| groups.iter().position(|it| it == group))
+            .unwrap_or_default(),
+        bpm_factor: 1.,
+        texture,
+        parent: Some(line.parent.map_or(-1, |it| it as isize)),
+        event_layers,
+        extended: Some(extended),
+        notes: Some(notes.into_iter().map(|it| serialize_note(r, it)).collect()),
+        is_cover: !line.show_below as u8,
+        z_order: line.z_index,
+        attach_ui: line.attach_ui,
+
+        pos_control: ctrl_events(&ctrl_obj.pos, "pos"),
+        size_control: ctrl_events(&ctrl_obj.size, "size"),
+        alpha_control: ctrl_events(&ctrl_obj.alpha, "alpha"),
+        y_control: ctrl_events(&ctrl_obj.y, "y"),
+    };
+    restore_links(r, &mut res, &line.links);
+    res
+}
+
+pub fn serialize_rpe(chart: &Chart, info: &ChartInfo) -> String {
+    let mut r = chart.bpm_list.borrow_mut();
+    let mut groups = vec![DEFAULT_GROUP.to_owned()];
+    for group in chart.lines.iter().filter_map(|it| it.group.as_ref()) {
+        if !groups.contains(group) {
+            groups.push(group.clone());
+        }
+    }
+    let rpe = RPEChart {
+        meta: RPEMetadata {
+            rpe_version: RPE_VERSION,
+            name: info.name.clone(),
+            id: info.id.clone().unwrap_or_default(),
+            level: info.level.clone(),
+            charter: info.charter.clone(),
+            composer: info.composer.clone(),
+            song: info.music.clone(),
+            background: info.illustration.clone(),
+            offset: (chart.offset * 1000.).round() as i32,
+        },
+        bpm_list: r
+            .ranges()
+            .map(|(beats, bpm)| RPEBpmItem {
+                bpm,
+                start_time: Triple::from_beats(beats),
+            })
+            .collect(),
+        judge_line_list: chart.lines.iter().map(|line| serialize_judge_line(&mut r, line, &groups)).collect(),
+        judge_line_group: groups,
+    };
+    serde_json::to_string(&rpe).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::ChartExtra,
+        fs::fs_from_file,
+        parse::{parse_pec, parse_phigros, parse_rpe_headless},
+    };
+    use std::{fmt::Debug, future::Future};
+
+    const RPE_CHART: &str = r#"{
+        "META": { "RPEVersion": 140, "name": "Test", "id": "1", "level": "IN Lv.1", "song": "song.ogg", "background": "bg.png", "offset": 50 },
+        "BPMList": [{ "bpm": 120, "startTime": [0, 0, 1] }, { "bpm": 240, "startTime": [4, 0, 1] }],
+        "judgeLineGroup": ["Default", "Extra"],
+        "judgeLineList": [
+            {
+                "Name": "Main", "Texture": "line.png", "father": -1, "isCover": 1,
+                "eventLayers": [
+                    {
+                        "alphaEvents": [{ "startTime": [0, 0, 1], "endTime": [1, 0, 1], "start": 255, "end": 128, "easingType": 1 }],
+                        "moveXEvents": [{ "startTime": [0, 0, 1], "endTime": [2, 0, 1], "start": -300, "end": 300, "easingType": 3 }],
+                        "moveYEvents": [{ "startTime": [0, 0, 1], "endTime": [8, 0, 1], "start": 0, "end": -200, "easingType": 2 }],
+                        "rotateEvents": [{
+                            "startTime": [1, 0, 1], "endTime": [6, 0, 1], "start": 0, "end": 90,
+                            "easingType": 4, "easingLeft": 0.2, "easingRight": 0.8
+                        }],
+                        "speedEvents": [
+                            { "startTime": [0, 0, 1], "endTime": [4, 0, 1], "start": 10, "end": 10 },
+                            { "startTime": [4, 0, 1], "endTime": [8, 0, 1], "start": 10, "end": 5 }
+                        ]
+                    },
+                    {
+                        "moveXEvents": [{
+                            "startTime": [2, 0, 1], "endTime": [4, 1, 2], "start": 0, "end": 100,
+                            "easingType": 1, "bezier": 1, "bezierPoints": [0.1, 0.2, 0.3, 1.0]
+                        }]
+                    }
+                ],
+                "notes": [
+                    { "type": 1, "above": 1, "startTime": [1, 0, 1], "endTime": [1, 0, 1],
+                      "positionX": -200, "yOffset": 0, "alpha": 255, "size": 1, "speed": 1, "isFake": 0, "visibleTime": 999999 },
+                    { "type": 2, "above": 1, "startTime": [2, 0, 1], "endTime": [3, 1, 2],
+                      "positionX": 0, "yOffset": 30, "alpha": 255, "size": 1, "speed": 1.5, "isFake": 0, "visibleTime": 999999 },
+                    { "type": 3, "above": 2, "startTime": [5, 0, 1], "endTime": [5, 0, 1],
+                      "positionX": 150, "yOffset": 0, "alpha": 128, "size": 1.2, "speed": 1, "isFake": 0, "visibleTime": 999999 },
+                    { "type": 4, "above": 1, "startTime": [6, 1, 4], "endTime": [6, 1, 4],
+                      "positionX": 0, "yOffset": 0, "alpha": 255, "size": 1, "speed": 1, "isFake": 1, "visibleTime": 999999 }
+                ]
+            },
+            {
+                "Name": "Lyrics", "Group": 1, "bpmfactor": 2.0, "Texture": "line.png", "father": 0, "isCover": 0, "zOrder": 3,
+                "eventLayers": [{ "moveYEvents": [{ "startTime": [0, 0, 1], "endTime": [1, 0, 1], "start": 100, "end": 100, "easingType": 1 }] }],
+                "extended": {
+                    "textEvents": [{ "startTime": [0, 0, 1], "endTime": [2, 0, 1], "start": "Hello", "end": "World", "easingType": 1 }],
+                    "colorEvents": [{ "startTime": [0, 0, 1], "endTime": [1, 0, 1], "start": [255, 0, 0], "end": [0, 0, 255], "easingType": 1 }],
+                    "scaleXEvents": [{ "startTime": [0, 0, 1], "endTime": [1, 0, 1], "start": 1, "end": 2, "easingType": 1 }]
+                }
+            },
+            {
+                "Name": "Paint", "Texture": "line.png", "isCover": 1,
+                "eventLayers": [],
+                "extended": { "paintEvents": [{ "startTime": [1, 0, 1], "endTime": [2, 0, 1], "start": 0, "end": 5, "easingType": 1 }] }
+            }
+        ]
+    }"#;
+
+    const PEC_CHART: &str = "0
+bp 0 120
+cv 0 0 10
+cp 0 0 1024 700
+cd 0 0 0
+ca 0 0 255
+cm 0 1 2 1536 700 4
+cr 0 2 4 90 2
+n1 0 1 512 1 0
+n2 0 2 3 0 1 0
+n3 0 3 -512 2 0
+n4 0 4 256 1 1";
+
+    const PGR_CHART: &str = r#"{
+        "formatVersion": 3,
+        "offset": 0.1,
+        "judgeLineList": [{
+            "bpm": 120,
+            "judgeLineDisappearEvents": [{ "startTime": 0, "endTime": 1e9, "start": 1, "end": 1, "start2": 0, "end2": 0 }],
+            "judgeLineRotateEvents": [
+                { "startTime": 0, "endTime": 32, "start": 0, "end": 45, "start2": 0, "end2": 0 },
+                { "startTime": 32, "endTime": 1e9, "start": 45, "end": 45, "start2": 0, "end2": 0 }
+            ],
+            "judgeLineMoveEvents": [
+                { "startTime": 0, "endTime": 64, "start": 0.5, "end": 0.7, "start2": 0.5, "end2": 0.4 },
+                { "startTime": 64, "endTime": 1e9, "start": 0.7, "end": 0.7, "start2": 0.4, "end2": 0.4 }
+            ],
+            "speedEvents": [{ "startTime": 0, "endTime": 1e9, "value": 1, "floorPosition": 0 }],
+            "notesAbove": [
+                { "type": 1, "time": 64, "positionX": 1, "holdTime": 0, "speed": 1, "floorPosition": 1 },
+                { "type": 3, "time": 128, "positionX": 0, "holdTime": 32, "speed": 1, "floorPosition": 2 }
+            ],
+            "notesBelow": [{ "type": 4, "time": 96, "positionX": -2, "holdTime": 0, "speed": 1, "floorPosition": 1.5 }]
+        }]
+    }"#;
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    fn parse(source: &str) -> Chart {
+        let mut fs = fs_from_file(&std::env::temp_dir()).unwrap();
+        block_on(parse_rpe_headless(source, fs.as_mut(), ChartExtra::default())).unwrap()
+    }
+
+    fn round_trip(chart: &Chart) -> Chart {
+        parse(&serialize_rpe(chart, &ChartInfo::default()))
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3 * (1. + a.abs())
+    }
+
+    fn keyframe_times<T: Tweenable>(anim: &Anim<T>, res: &mut Vec<f32>) {
+        res.extend(anim.keyframes.iter().map(|it| it.time));
+        if let Some(next) = &anim.next {
+            keyframe_times(next, res);
+        }
+    }
+
+    /// Compares the values between and around the keyframes of both animations, since the keyframes themselves may be split
+    /// differently.
+    fn assert_anim_eq<T: Tweenable + Debug>(a: &Anim<T>, b: &Anim<T>, eq: impl Fn(&T, &T) -> bool, desc: &str) {
+        let mut times = Vec::new();
+        keyframe_times(a, &mut times);
+        keyframe_times(b, &mut times);
+        times.sort_by(f32::total_cmp);
+        times.dedup_by(|x, y| (*x - *y).abs() < 1e-3);
+        let mut points: Vec<_> = times.windows(2).map(|it| (it[0] + it[1]) / 2.).collect();
+        points.extend(times.first().map(|it| it - 1.));
+        points.extend(times.last().map(|it| it + 1.));
+        let (mut a, mut b) = (a.clone(), b.clone());
+        for t in points {
+            a.set_time(t);
+            b.set_time(t);
+            let (x, y) = (a.now_opt(), b.now_opt());
+            let same = match (&x, &y) {
+                (Some(x), Some(y)) => eq(x, y),
+                (None, None) => true,
+                _ => false,
+            };
+            assert!(same, "{desc} at {t}: {x:?} != {y:?}");
+        }
+    }
+
+    fn assert_float_anim_eq(a: &AnimFloat, b: &AnimFloat, desc: &str) {
+        assert_anim_eq(a, b, |x, y| close(*x, *y), desc);
+    }
+
+    fn assert_line_eq(a: &JudgeLine, b: &JudgeLine, id: usize) {
+        let desc = |what: &str| format!("{what} of line #{id}");
+        assert_float_anim_eq(&a.object.alpha, &b.object.alpha, &desc("alpha"));
+        assert_float_anim_eq(&a.object.rotation, &b.object.rotation, &desc("rotation"));
+        assert_float_anim_eq(&a.object.translation.0, &b.object.translation.0, &desc("move X"));
+        assert_float_anim_eq(&a.object.translation.1, &b.object.translation.1, &desc("move Y"));
+        assert_float_anim_eq(&a.object.scale.0, &b.object.scale.0, &desc("scale X"));
+        assert_float_anim_eq(&a.object.scale.1, &b.object.scale.1, &desc("scale Y"));
+        assert_float_anim_eq(&a.incline, &b.incline, &desc("incline"));
+        assert_anim_eq(&a.color, &b.color, |x, y| close(x.r, y.r) && close(x.g, y.g) && close(x.b, y.b) && close(x.a, y.a), &desc("color"));
+        match (&a.kind, &b.kind) {
+            (JudgeLineKind::Normal, JudgeLineKind::Normal) => {}
+            (JudgeLineKind::Text(x), JudgeLineKind::Text(y)) => assert_anim_eq(x, y, String::eq, &desc("text")),
+            (JudgeLineKind::Paint(x, _), JudgeLineKind::Paint(y, _)) => assert_float_anim_eq(x, y, &desc("paint")),
+            _ => panic!("{} differs", desc("kind")),
+        }
+        assert_eq!(a.parent, b.parent, "{}", desc("parent"));
+        assert_eq!(a.z_index, b.z_index, "{}", desc("z index"));
+        assert_eq!(a.show_below, b.show_below, "{}", desc("cover"));
+
+        assert_eq!(a.notes.len(), b.notes.len(), "{}", desc("note count"));
+        for (index, (x, y)) in a.notes.iter().zip(&b.notes).enumerate() {
+            let desc = format!("note #{index} of line #{id}");
+            assert!(close(x.time, y.time), "time of {desc}: {} != {}", x.time, y.time);
+            assert!(close(x.height, y.height), "height of {desc}: {} != {}", x.height, y.height);
+            assert!(close(x.speed, y.speed), "speed of {desc}");
+            assert_eq!((x.above, x.fake), (y.above, y.fake), "side of {desc}");
+            match (&x.kind, &y.kind) {
+                (
+                    NoteKind::Hold {
+                        end_time: t1,
+                        end_height: h1,
+                    },
+                    NoteKind::Hold {
+                        end_time: t2,
+                        end_height: h2,
+                    },
+                ) => {
+                    assert!(close(*t1, *t2) && close(*h1, *h2), "hold end of {desc}");
+                }
+                (x, y) => assert_eq!(std::mem::discriminant(x), std::mem::discriminant(y), "kind of {desc}"),
+            }
+            assert_float_anim_eq(&x.object.alpha, &y.object.alpha, &format!("alpha of {desc}"));
+            assert_float_anim_eq(&x.object.translation.0, &y.object.translation.0, &format!("X of {desc}"));
+            assert_float_anim_eq(&x.object.translation.1, &y.object.translation.1, &format!("Y of {desc}"));
+            assert_float_anim_eq(&x.object.scale.0, &y.object.scale.0, &format!("size of {desc}"));
+        }
+    }
+
+    fn assert_chart_eq(a: &Chart, b: &Chart) {
+        assert!(close(a.offset, b.offset), "offset: {} != {}", a.offset, b.offset);
+        assert_eq!(a.lines.len(), b.lines.len());
+        for (id, (x, y)) in a.lines.iter().zip(&b.lines).enumerate() {
+            assert_line_eq(x, y, id);
+        }
+    }
+
+    #[test]
+    fn rpe_round_trip() {
+        let chart = parse(RPE_CHART);
+        let res = round_trip(&chart);
+        assert_chart_eq(&chart, &res);
+        let bpm: Vec<_> = res.bpm_list.borrow().ranges().collect();
+        assert_eq!(bpm, chart.bpm_list.borrow().ranges().collect::<Vec<_>>());
+        assert_eq!(bpm, [(0., 120.), (4., 240.)]);
+        let names: Vec<_> = res.lines.iter().map(|it| it.name.as_str()).collect();
+        assert_eq!(names, ["Main", "Lyrics", "Paint"]);
+        let groups: Vec<_> = res.lines.iter().map(|it| it.group.as_deref()).collect();
+        assert_eq!(groups, [Some("Default"), Some("Extra"), Some("Default")]);
+        assert!(matches!(res.lines[1].kind, JudgeLineKind::Text(_)));
+        assert!(matches!(res.lines[2].kind, JudgeLineKind::Paint(..)));
+    }
+
+    #[test]
+    fn pec_to_rpe() {
+        let chart = parse_pec(PEC_CHART, ChartExtra::default()).unwrap();
+        assert_chart_eq(&chart, &round_trip(&chart));
+    }
+
+    #[test]
+    fn pgr_to_rpe() {
+        let chart = parse_phigros(PGR_CHART, ChartExtra::default()).unwrap();
+        assert_chart_eq(&chart, &round_trip(&chart));
+    }
+}
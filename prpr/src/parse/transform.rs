@@ -0,0 +1,173 @@
+use crate::{
+    config::Modifiers,
+    core::{AnimFloat, Chart, JudgeLineCache},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Notes are randomly placed within this distance to the center of their line.
+const RANDOM_X_RANGE: f32 = 0.8;
+
+/// Changes to the layout of a parsed chart, applied before playing it. Lines attached to UI elements are left alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChartTransform {
+    /// Flips the chart horizontally.
+    pub mirror: bool,
+    /// Moves notes above their lines to below and vice versa.
+    pub swap_sides: bool,
+    /// Places every note at a random X, chosen with this seed.
+    pub random_x: Option<u64>,
+}
+
+impl ChartTransform {
+    pub fn new(modifiers: &Modifiers) -> Self {
+        Self {
+            mirror: modifiers.mirror,
+            swap_sides: modifiers.swap_sides,
+            random_x: modifiers.random_seed,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, chart: &mut Chart) {
+        if self.is_identity() {
+            return;
+        }
+        let mut rng = self.random_x.map(StdRng::seed_from_u64);
+        for line in chart.lines.iter_mut().filter(|it| it.attach_ui.is_none()) {
+            if self.mirror {
+                line.object.mirror();
+            }
+            for note in &mut line.notes {
+                if let Some(rng) = &mut rng {
+                    note.object.translation.0 = AnimFloat::fixed(rng.gen_range(-RANDOM_X_RANGE..=RANDOM_X_RANGE));
+                }
+                if self.mirror {
+                    note.object.mirror();
+                }
+                if self.swap_sides {
+                    note.above = !note.above;
+                }
+            }
+            // the cache sorts notes by side, so it's built again
+            line.cache = JudgeLineCache::new(&mut line.notes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{core::ChartExtra, parse::parse_pec};
+
+    const CHART: &str = "0
+bp 0 120
+cp 0 0 1024 700
+cd 0 0 0
+ca 0 0 255
+cm 0 1 2 1536 500 2
+cr 0 1 2 90 2
+n1 0 1 512 1 0
+n1 0 2 -256 1 0
+n3 0 3 100 2 0
+n4 0 4 256 2 0
+n2 0 1 2 0 1 0
+cp 1 0 512 700
+n1 1 1 0 2 0";
+
+    fn chart() -> Chart {
+        parse_pec(CHART, ChartExtra::default()).unwrap()
+    }
+
+    fn value(anim: &AnimFloat, time: f32) -> f32 {
+        let mut anim = anim.clone();
+        anim.set_time(time);
+        anim.now()
+    }
+
+    fn note_xs(chart: &Chart) -> Vec<f32> {
+        chart
+            .lines
+            .iter()
+            .flat_map(|it| &it.notes)
+            .map(|it| it.object.translation.0.now())
+            .collect()
+    }
+
+    #[test]
+    fn mirror() {
+        let original = chart();
+        let mut chart = chart();
+        ChartTransform {
+            mirror: true,
+            ..Default::default()
+        }
+        .apply(&mut chart);
+        for (a, b) in original.lines.iter().zip(&chart.lines) {
+            for time in (0..=30).map(|it| it as f32 / 10.) {
+                assert_eq!(value(&b.object.translation.0, time), -value(&a.object.translation.0, time), "X at {time}");
+                assert_eq!(value(&b.object.translation.1, time), value(&a.object.translation.1, time), "Y at {time}");
+                assert_eq!(value(&b.object.rotation, time), -value(&a.object.rotation, time), "rotation at {time}");
+            }
+            for (x, y) in a.notes.iter().zip(&b.notes) {
+                assert_eq!((x.time, x.above), (y.time, y.above));
+                assert_eq!(y.object.translation.0.now(), -x.object.translation.0.now());
+            }
+        }
+    }
+
+    #[test]
+    fn swap_sides() {
+        let original = chart();
+        let mut chart = chart();
+        ChartTransform {
+            swap_sides: true,
+            ..Default::default()
+        }
+        .apply(&mut chart);
+        for (a, b) in original.lines.iter().zip(&chart.lines) {
+            assert_eq!(a.notes.len(), b.notes.len());
+            for x in &a.notes {
+                let y = b
+                    .notes
+                    .iter()
+                    .find(|it| it.time == x.time && it.object.translation.0.now() == x.object.translation.0.now())
+                    .unwrap();
+                assert_eq!(y.above, !x.above);
+            }
+            // the cache takes plain notes above the line to be in front of the ones below
+            let first_plain = b.notes.iter().position(|it| it.plain()).unwrap_or(b.notes.len());
+            assert!(b.notes[first_plain..].windows(2).all(|it| it[0].above || !it[1].above));
+        }
+        assert!(chart.lines[0].notes.iter().any(|it| it.plain() && it.above));
+        assert!(chart.lines[0].notes.iter().any(|it| it.plain() && !it.above));
+        chart.reset();
+    }
+
+    #[test]
+    fn random_x() {
+        let transform = ChartTransform {
+            random_x: Some(42),
+            ..Default::default()
+        };
+        let (mut a, mut b, mut c) = (chart(), chart(), chart());
+        transform.apply(&mut a);
+        transform.apply(&mut b);
+        ChartTransform {
+            random_x: Some(43),
+            ..transform
+        }
+        .apply(&mut c);
+        let xs = note_xs(&a);
+        assert_eq!(xs, note_xs(&b));
+        assert_ne!(xs, note_xs(&c));
+        assert_ne!(xs, note_xs(&chart()));
+        assert!(xs.iter().all(|it| it.abs() <= RANDOM_X_RANGE));
+        let original = chart();
+        for time in (0..=30).map(|it| it as f32 / 10.) {
+            assert_eq!(value(&a.lines[0].object.translation.0, time), value(&original.lines[0].object.translation.0, time));
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Replays of plays, recorded by [`Judge`](crate::judge::Judge).
+//!
+//! A replay file consists of a small uncompressed header followed by the deflated frame stream:
+//!
+//! ```text
+//! magic "PRRP" | version: u16 | speed: f32 | note_scale: f32 | aspect_ratio: f32 | dpi: u32
+//!     | profile | modifiers: u8 | [seed: u64] | lanes: u8 | key_flicks: u8 | scoring: u8 | deflate(frames)
+//! ```
+//!
+//! All numbers are little-endian, counts and touch ids are LEB128 varints. The judge profile is a tag byte, followed by its
+//! windows as five `f32`s if it's custom. Modifiers are stored as [`Modifiers::bits`], followed
+//! by the random seed if there is one.
+
+use crate::{
+    config::Modifiers,
+    core::Resource,
+    judge::{input::MAX_LANES, Judge, JudgeProfile, JudgeWindows, Scoring},
+};
+use anyhow::{bail, Context, Result};
+use macroquad::prelude::{vec2, Touch, TouchPhase};
+
+const MAGIC: &[u8; 4] = b"PRRP";
+/// The bit of [`Modifiers::random_seed`] in [`Modifiers::bits`].
+const RANDOM_SEED_BIT: u8 = 6;
+
+/// Input fed to the judge in a single update.
+#[derive(Clone)]
+pub struct ReplayFrame {
+    /// The chart time of this update.
+    pub time: f32,
+    /// Touches in judge coordinates, as returned by [`Judge::get_touches`](crate::judge::Judge::get_touches) plus the mouse.
+    pub touches: Vec<Touch>,
+    /// Raw touch events of this frame, in normalized screen coordinates. These feed the velocity trackers.
+    pub events: Vec<Touch>,
+    /// Change of the number of held tap keys.
+    pub key_delta: i32,
+    /// Number of tap keys pressed in this frame.
+    pub keys_down: u32,
+    /// Lanes whose keys are pressed in this frame, as bits.
+    pub lanes_down: u32,
+    /// Lanes whose keys are held, as bits.
+    pub lanes_held: u32,
+    /// Number of flick keys pressed in this frame.
+    pub flicks: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct Replay {
+    pub speed: f32,
+    pub note_scale: f32,
+    pub aspect_ratio: f32,
+    pub dpi: u32,
+    pub judge_profile: JudgeProfile,
+    pub modifiers: Modifiers,
+    /// See [`Judge::lanes`].
+    pub lanes: u8,
+    /// See [`Judge::key_flicks`].
+    pub key_flicks: bool,
+    pub scoring: Scoring,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub const VERSION: u16 = 1;
+
+    pub fn new(res: &Resource, judge: &Judge) -> Self {
+        Self {
+            speed: res.config.speed,
+            note_scale: res.config.note_scale,
+            aspect_ratio: res.aspect_ratio,
+            dpi: res.dpi,
+            judge_profile: judge.profile,
+            modifiers: res.config.modifiers,
+            lanes: judge.lanes as u8,
+            key_flicks: judge.key_flicks,
+            scoring: res.config.scoring,
+            frames: judge.record.clone(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_varint(&mut body, self.frames.len() as u64);
+        for frame in &self.frames {
+            body.extend_from_slice(&frame.time.to_le_bytes());
+            write_touches(&mut body, &frame.touches);
+            write_touches(&mut body, &frame.events);
+            write_varint(&mut body, zigzag(frame.key_delta as i64));
+            write_varint(&mut body, frame.keys_down as u64);
+            write_varint(&mut body, frame.lanes_down as u64);
+            write_varint(&mut body, frame.lanes_held as u64);
+            write_varint(&mut body, frame.flicks as u64);
+        }
+
+        let mut res = Vec::with_capacity(body.len() / 2 + 22);
+        res.extend_from_slice(MAGIC);
+        res.extend_from_slice(&Self::VERSION.to_le_bytes());
+        res.extend_from_slice(&self.speed.to_le_bytes());
+        res.extend_from_slice(&self.note_scale.to_le_bytes());
+        res.extend_from_slice(&self.aspect_ratio.to_le_bytes());
+        res.extend_from_slice(&self.dpi.to_le_bytes());
+        write_profile(&mut res, &self.judge_profile);
+        res.push(self.modifiers.bits());
+        if let Some(seed) = self.modifiers.random_seed {
+            res.extend_from_slice(&seed.to_le_bytes());
+        }
+        res.push(self.lanes);
+        res.push(self.key_flicks as u8);
+        res.push(match self.scoring {
+            Scoring::Phigros => 0,
+            Scoring::Ex => 1,
+            Scoring::Accuracy => 2,
+            Scoring::ApOnly => 3,
+        });
+        res.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(&body, 6));
+        res
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader(bytes);
+        if r.take(4)? != MAGIC {
+            bail!("Not a replay file");
+        }
+        let version = u16::from_le_bytes(r.array()?);
+        if version != Self::VERSION {
+            bail!("Unsupported replay version: {version}");
+        }
+        let speed = r.f32()?;
+        let note_scale = r.f32()?;
+        let aspect_ratio = r.f32()?;
+        let dpi = u32::from_le_bytes(r.array()?);
+        let judge_profile = r.profile()?;
+        let bits = r.take(1)?[0];
+        let mut modifiers = Modifiers::from_bits(bits);
+        if bits & (1 << RANDOM_SEED_BIT) != 0 {
+            modifiers.random_seed = Some(u64::from_le_bytes(r.array()?));
+        }
+        let lanes = r.take(1)?[0];
+        if lanes as usize > MAX_LANES {
+            bail!("Too many lanes in replay: {lanes}");
+        }
+        let key_flicks = r.take(1)?[0] != 0;
+        let scoring = match r.take(1)?[0] {
+            0 => Scoring::Phigros,
+            1 => Scoring::Ex,
+            2 => Scoring::Accuracy,
+            3 => Scoring::ApOnly,
+            x => bail!("Invalid scoring rule in replay: {x}"),
+        };
+        let body = miniz_oxide::inflate::decompress_to_vec(r.0)
+            .map_err(|err| anyhow::anyhow!("{err:?}"))
+            .context("Corrupted replay")?;
+
+        let mut r = Reader(&body);
+        let count = r.varint()? as usize;
+        let mut frames = Vec::with_capacity(count.min(body.len()));
+        for _ in 0..count {
+            let time = r.f32()?;
+            let touches = r.touches()?;
+            let events = r.touches()?;
+            let key_delta = unzigzag(r.varint()?) as i32;
+            let keys_down = r.varint()? as u32;
+            let lanes_down = r.varint()? as u32;
+            let lanes_held = r.varint()? as u32;
+            let flicks = r.varint()? as u32;
+            frames.push(ReplayFrame {
+                time,
+                touches,
+                events,
+                key_delta,
+                keys_down,
+                lanes_down,
+                lanes_held,
+                flicks,
+            });
+        }
+        Ok(Self {
+            speed,
+            note_scale,
+            aspect_ratio,
+            dpi,
+            judge_profile,
+            modifiers,
+            lanes,
+            key_flicks,
+            scoring,
+            frames,
+        })
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_touches(out: &mut Vec<u8>, touches: &[Touch]) {
+    write_varint(out, touches.len() as u64);
+    for touch in touches {
+        // mouse buttons use ids near u64::MAX, which zigzag encoding turns into small numbers
+        write_varint(out, zigzag(touch.id as i64));
+        out.push(match touch.phase {
+            TouchPhase::Started => 0,
+            TouchPhase::Stationary => 1,
+            TouchPhase::Moved => 2,
+            TouchPhase::Ended => 3,
+            TouchPhase::Cancelled => 4,
+        });
+        out.extend_from_slice(&touch.position.x.to_le_bytes());
+        out.extend_from_slice(&touch.position.y.to_le_bytes());
+    }
+}
+
+fn write_profile(out: &mut Vec<u8>, profile: &JudgeProfile) {
+    out.push(match profile {
+        JudgeProfile::Standard => 0,
+        JudgeProfile::Challenge => 1,
+        JudgeProfile::Casual => 2,
+        JudgeProfile::Custom(_) => 3,
+    });
+    if let JudgeProfile::Custom(windows) = profile {
+        for value in [
+            windows.perfect,
+            windows.good,
+            windows.bad,
+            windows.flick_speed_threshold,
+            windows.dist_factor,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("Unexpected end of replay");
+        }
+        let (res, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(res)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut res = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            res |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(res);
+            }
+        }
+        bail!("Invalid varint in replay")
+    }
+
+    fn profile(&mut self) -> Result<JudgeProfile> {
+        Ok(match self.take(1)?[0] {
+            0 => JudgeProfile::Standard,
+            1 => JudgeProfile::Challenge,
+            2 => JudgeProfile::Casual,
+            3 => JudgeProfile::Custom(JudgeWindows {
+                perfect: self.f32()?,
+                good: self.f32()?,
+                bad: self.f32()?,
+                flick_speed_threshold: self.f32()?,
+                dist_factor: self.f32()?,
+            }),
+            x => bail!("Invalid judge profile in replay: {x}"),
+        })
+    }
+
+    fn touches(&mut self) -> Result<Vec<Touch>> {
+        let count = self.varint()? as usize;
+        let mut res = Vec::with_capacity(count.min(self.0.len()));
+        for _ in 0..count {
+            let id = unzigzag(self.varint()?) as u64;
+            let phase = match self.take(1)?[0] {
+                0 => TouchPhase::Started,
+                1 => TouchPhase::Stationary,
+                2 => TouchPhase::Moved,
+                3 => TouchPhase::Ended,
+                4 => TouchPhase::Cancelled,
+                x => bail!("Invalid touch phase in replay: {x}"),
+            };
+            let x = self.f32()?;
+            let y = self.f32()?;
+            res.push(Touch {
+                id,
+                phase,
+                position: vec2(x, y),
+            });
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(id: u64, phase: TouchPhase, x: f32, y: f32) -> Touch {
+        Touch {
+            id,
+            phase,
+            position: vec2(x, y),
+        }
+    }
+
+    fn assert_touches_eq(a: &[Touch], b: &[Touch]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert_eq!((x.id, x.phase, x.position), (y.id, y.phase, y.position));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = Replay {
+            speed: 1.25,
+            note_scale: 0.8,
+            aspect_ratio: 4. / 3.,
+            dpi: 240,
+            judge_profile: JudgeProfile::Custom(JudgeWindows {
+                perfect: 0.05,
+                good: 0.1,
+                bad: 0.15,
+                flick_speed_threshold: 1.8,
+                dist_factor: 0.3,
+            }),
+            modifiers: Modifiers {
+                mirror: true,
+                random_seed: Some(u64::MAX - 7),
+                ..Default::default()
+            },
+            lanes: 4,
+            key_flicks: false,
+            scoring: Scoring::Ex,
+            frames: vec![
+                ReplayFrame {
+                    time: 0.016,
+                    touches: vec![
+                        touch(0, TouchPhase::Started, 0.1, 0.2),
+                        touch(u64::MAX - 1, TouchPhase::Stationary, -0.3, 0.4),
+                    ],
+                    events: vec![touch(0, TouchPhase::Started, 0.55, 0.6)],
+                    key_delta: -3,
+                    keys_down: 1,
+                    lanes_down: 0b1010,
+                    lanes_held: 0b1110,
+                    flicks: 2,
+                },
+                ReplayFrame {
+                    time: 1000.5,
+                    touches: Vec::new(),
+                    events: vec![touch(1, TouchPhase::Cancelled, 0., 1.)],
+                    key_delta: 300,
+                    keys_down: 0,
+                    lanes_down: 0,
+                    lanes_held: 0,
+                    flicks: 0,
+                },
+            ],
+        };
+        let res = Replay::decode(&replay.encode()).unwrap();
+        assert_eq!((res.speed, res.note_scale, res.aspect_ratio, res.dpi), (1.25, 0.8, 4. / 3., 240));
+        assert_eq!(res.judge_profile, replay.judge_profile);
+        assert_eq!(res.modifiers, replay.modifiers);
+        assert_eq!((res.lanes, res.key_flicks, res.scoring), (4, false, Scoring::Ex));
+        assert_eq!(res.frames.len(), 2);
+        for (x, y) in replay.frames.iter().zip(&res.frames) {
+            assert_eq!(x.time, y.time);
+            assert_touches_eq(&x.touches, &y.touches);
+            assert_touches_eq(&x.events, &y.events);
+            assert_eq!(
+                (x.key_delta, x.keys_down, x.lanes_down, x.lanes_held, x.flicks),
+                (y.key_delta, y.keys_down, y.lanes_down, y.lanes_held, y.flicks)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let bytes = Replay::default().encode();
+        assert!(Replay::decode(&bytes).is_ok());
+        assert!(Replay::decode(b"PRRQ\x01\x00").is_err());
+        for version in [0, Replay::VERSION + 1] {
+            let mut bytes = bytes.clone();
+            bytes[4..6].copy_from_slice(&version.to_le_bytes());
+            assert!(Replay::decode(&bytes).is_err());
+        }
+        assert!(Replay::decode(&bytes[..20]).is_err());
+        // lanes come after the standard profile and the modifiers
+        let mut lanes = bytes.clone();
+        lanes[24] = MAX_LANES as u8 + 1;
+        assert!(Replay::decode(&lanes).is_err());
+        let mut scoring = bytes;
+        scoring[26] = 4;
+        assert!(Replay::decode(&scoring).is_err());
+    }
+}
@@ -2,12 +2,14 @@ crate::tl_file!("ending");
 
 use super::{draw_background, draw_illustration, NextScene, Scene};
 use crate::{
+    calibration::OffsetEstimate,
     config::Config,
+    core::{JUDGE_LINE_GOOD_COLOR, JUDGE_LINE_PERFECT_COLOR},
     ext::{
         create_audio_manger, draw_parallelogram, draw_parallelogram_ex, draw_text_aligned, screen_aspect, SafeTexture, ScaleType, PARALLELOGRAM_SLOPE,
     },
     info::ChartInfo,
-    judge::{Judge, PlayResult},
+    judge::{Judge, JudgeWindows, Judgement, PhigrosScoring, PlayResult, ScoringRule, TimingStats},
     scene::show_message,
     task::Task,
     ui::{Dialog, MessageHandle, Ui},
@@ -46,6 +48,8 @@ pub struct EndingScene {
     next: u8, // 0 -> none, 1 -> pop, 2 -> exit
     update_state: Option<RecordUpdateState>,
     rated: bool,
+    timing: TimingPage,
+    show_timing: bool,
 
     upload_fn: Option<fn(String) -> Task<Result<RecordUpdateState>>>,
     upload_task: Option<(Task<Result<RecordUpdateState>>, MessageHandle)>,
@@ -97,6 +101,8 @@ impl EndingScene {
                 })
             },
             rated: upload_task.is_some(),
+            timing: TimingPage::new(&result, config),
+            show_timing: false,
 
             info,
             result,
@@ -119,6 +125,140 @@ thread_local! {
     static RE_UPLOAD: RefCell<bool> = RefCell::default();
 }
 
+/// Hit error statistics shown in place of the result panels.
+struct TimingPage {
+    stats: TimingStats,
+    windows: JudgeWindows,
+    histogram: Vec<u32>,
+    /// Accuracy after each judged note, along with the time of the note.
+    timeline: Vec<(f32, f32)>,
+    estimate: Option<OffsetEstimate>,
+}
+
+impl TimingPage {
+    const BINS: usize = 31;
+    const TIMELINE_POINTS: usize = 200;
+
+    fn new(result: &PlayResult, config: &Config) -> Self {
+        let windows = result.judge_profile.windows();
+        let stats = TimingStats::new(result.diffs());
+        let mut histogram = vec![0; Self::BINS];
+        for diff in result.diffs() {
+            let bin = ((diff / windows.bad + 1.) / 2. * Self::BINS as f32).floor();
+            histogram[(bin.max(0.) as usize).min(Self::BINS - 1)] += 1;
+        }
+        let mut hits: Vec<_> = result.hits.iter().collect();
+        hits.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let step = (hits.len() / Self::TIMELINE_POINTS).max(1);
+        let mut sum = 0.;
+        let mut timeline = Vec::new();
+        for (i, hit) in hits.iter().enumerate() {
+            sum += match hit.judgement {
+                Judgement::Perfect => 1.,
+                Judgement::Good => 0.65,
+                _ => 0.,
+            };
+            if (i + 1) % step == 0 || i + 1 == hits.len() {
+                timeline.push((hit.time, sum / (i + 1) as f32));
+            }
+        }
+        Self {
+            stats,
+            windows,
+            histogram,
+            timeline,
+            estimate: OffsetEstimate::new(result.diffs(), config),
+        }
+    }
+
+    fn render(&self, ui: &mut Ui, area: Rect, background: Color) {
+        let slope = PARALLELOGRAM_SLOPE;
+        draw_parallelogram(area, None, background, true);
+        // horizontal extent of an axis-aligned box inside the parallelogram
+        let span = |top: f32, bottom: f32| (area.x + (area.bottom() - top) * slope + 0.04, area.right() - (bottom - area.y) * slope - 0.04);
+
+        let y = area.y + 0.03;
+        let (l, r) = span(y, y + 0.05);
+        draw_text_aligned(ui, &tl!("timing-title"), l, y, (0., 0.), 0.42, WHITE);
+        if self.stats.count == 0 {
+            draw_text_aligned(ui, &tl!("timing-no-data"), (l + r) / 2., area.center().y, (0.5, 0.5), 0.4, WHITE);
+            return;
+        }
+        let ms = |t: f32| format!("{:+.1}ms", t * 1000.);
+        draw_text_aligned(
+            ui,
+            &tl!("timing-stats", "mean" => ms(self.stats.mean), "sd" => format!("{:.1}ms", self.stats.std_dev * 1000.)),
+            r,
+            y + 0.01,
+            (1., 0.),
+            0.3,
+            WHITE,
+        );
+
+        let hist = Rect::new(0., y + 0.09, 0., 0.2);
+        let (l, r) = span(hist.y, hist.bottom());
+        let hist = Rect::new(l, hist.y, r - l, hist.h);
+        let max = self.histogram.iter().copied().max().unwrap_or(1).max(1);
+        let bw = hist.w / Self::BINS as f32;
+        for (i, count) in self.histogram.iter().enumerate() {
+            let center = ((i as f32 + 0.5) / Self::BINS as f32 * 2. - 1.) * self.windows.bad;
+            let color = if center.abs() <= self.windows.perfect {
+                JUDGE_LINE_PERFECT_COLOR
+            } else if center.abs() <= self.windows.good {
+                JUDGE_LINE_GOOD_COLOR
+            } else {
+                Color::new(0.9, 0.35, 0.35, 0.9)
+            };
+            let h = hist.h * *count as f32 / max as f32;
+            draw_rectangle(hist.x + bw * i as f32 + bw * 0.1, hist.bottom() - h, bw * 0.8, h, color);
+        }
+        let zero = hist.center().x;
+        draw_line(zero, hist.y, zero, hist.bottom(), 0.003, Color::new(1., 1., 1., 0.6));
+        let mean = zero + self.stats.mean / self.windows.bad * hist.w / 2.;
+        draw_line(mean, hist.y, mean, hist.bottom(), 0.003, WHITE);
+        let y = hist.bottom() + 0.01;
+        draw_text_aligned(ui, &ms(-self.windows.bad), hist.x, y, (0., 0.), 0.24, WHITE);
+        draw_text_aligned(ui, "Early", hist.x + hist.w / 4., y, (0.5, 0.), 0.24, WHITE);
+        draw_text_aligned(ui, "Late", hist.right() - hist.w / 4., y, (0.5, 0.), 0.24, WHITE);
+        draw_text_aligned(ui, &ms(self.windows.bad), hist.right(), y, (1., 0.), 0.24, WHITE);
+
+        let y = y + 0.05;
+        if let Some(estimate) = &self.estimate {
+            let (l, _) = span(y, y + 0.04);
+            draw_text_aligned(
+                ui,
+                &tl!(
+                    "timing-suggested-offset",
+                    "offset" => format!("{:+.3}s", estimate.offset),
+                    "confidence" => format!("{:.0}%", estimate.confidence * 100.)
+                ),
+                l,
+                y,
+                (0., 0.),
+                0.3,
+                WHITE,
+            );
+        }
+
+        let graph = Rect::new(0., y + 0.07, 0., area.bottom() - y - 0.1);
+        let (l, r) = span(graph.y, graph.bottom());
+        let graph = Rect::new(l, graph.y, r - l, graph.h);
+        draw_rectangle_lines(graph.x, graph.y, graph.w, graph.h, 0.003, Color::new(1., 1., 1., 0.4));
+        let lowest = self.timeline.iter().map(|it| it.1).fold(1., f32::min);
+        let lo = ((lowest * 20.).floor() / 20.).min(0.95);
+        let (st, en) = (self.timeline[0].0, self.timeline.last().unwrap().0);
+        let to_point = |(time, acc): (f32, f32)| {
+            vec2(graph.x + if en > st { (time - st) / (en - st) } else { 1. } * graph.w, graph.bottom() - (acc - lo) / (1. - lo) * graph.h)
+        };
+        for pair in self.timeline.windows(2) {
+            let (a, b) = (to_point(pair[0]), to_point(pair[1]));
+            draw_line(a.x, a.y, b.x, b.y, 0.004, JUDGE_LINE_PERFECT_COLOR);
+        }
+        draw_text_aligned(ui, &format!("{:.0}%", lo * 100.), graph.x + 0.01, graph.bottom() - 0.005, (0., 1.), 0.22, WHITE);
+        draw_text_aligned(ui, &tl!("timing-timeline"), graph.x + 0.01, graph.y + 0.005, (0., 0.), 0.22, WHITE);
+    }
+}
+
 impl Scene for EndingScene {
     fn enter(&mut self, tm: &mut crate::time::TimeManager, target: Option<RenderTarget>) -> Result<()> {
         tm.reset();
@@ -225,106 +365,137 @@ impl Scene for EndingScene {
         let dx = 0.06;
         let c = Color::new(0., 0., 0., 0.6);
 
-        tran(gl, (1. - ran(now, 0.2, 1.3)).powi(3));
         let main = Rect::new(r.right() - 0.05, r.y, r.w * 0.84, r.h / 2.);
-        draw_parallelogram(main, None, c, true);
-        {
-            let spd = if (self.speed - 1.).abs() <= 1e-4 {
-                String::new()
-            } else {
-                format!(" {:.2}x", self.speed)
-            };
-            let text = if self.autoplay {
-                format!("PRPR[AUTOPLAY] {spd}")
-            } else if !self.rated {
-                format!("PRPR[UNRATED] {spd}")
-            } else if let Some(state) = &self.update_state {
-                format!(
-                    "PRPR {spd}  {}",
-                    if state.best {
-                        format!("NEW BEST +{:07}", state.improvement)
-                    } else {
-                        String::new()
-                    }
-                )
-            } else {
-                "Uploading…".to_owned()
-            };
-            let r = draw_text_aligned(ui, &text, main.x + dx, main.bottom() - 0.035, (0., 1.), 0.34, WHITE);
-            let r = draw_text_aligned(ui, &format!("{:07}", res.score), r.x, r.y - 0.023, (0., 1.), 1., WHITE);
-            let icon = match (res.score, res.num_of_notes == res.max_combo) {
-                (x, _) if x < 700000 => 0,
-                (x, _) if x < 820000 => 1,
-                (x, _) if x < 880000 => 2,
-                (x, _) if x < 920000 => 3,
-                (x, _) if x < 960000 => 4,
-                (1000000, _) => 7,
-                (_, false) => 5,
-                (_, true) => 6,
-            };
-            let p = ran(now, 1.4, 1.9).powi(2);
-            let s = main.h * 0.67;
-            let ct = (main.right() - main.h * slope - s / 2., r.bottom() + 0.02 - s / 2.);
-            let s = s + s * (1. - p) * 0.3;
-            draw_texture_ex(
-                *self.icons[icon],
-                ct.0 - s / 2.,
-                ct.1 - s / 2.,
-                Color::new(1., 1., 1., p),
-                DrawTextureParams {
-                    dest_size: Some(vec2(s, s)),
-                    ..Default::default()
-                },
-            );
-        }
-        gl.pop_model_matrix();
+        // the panels on the right, which are replaced by timing statistics when tapped
+        let area = Rect::new(main.x - main.h * slope, main.y, main.w, r.h);
+        if self.show_timing {
+            self.timing.render(ui, area, c);
+        } else {
+            tran(gl, (1. - ran(now, 0.2, 1.3)).powi(3));
+            draw_parallelogram(main, None, c, true);
+            {
+                let spd = if (self.speed - 1.).abs() <= 1e-4 {
+                    String::new()
+                } else {
+                    format!(" {:.2}x", self.speed)
+                };
+                let mut tags = format!("{spd} {}", res.judge_profile.name().to_uppercase());
+                if res.scoring != PhigrosScoring.name() {
+                    tags = format!("{tags} {}", res.scoring.to_uppercase());
+                }
+                for tag in res.modifiers.tags() {
+                    tags = format!("{tags} {tag}");
+                }
+                if res.failed {
+                    tags = format!("{tags} FAILED");
+                }
+                let text = if self.autoplay {
+                    format!("PRPR[AUTOPLAY] {tags}")
+                } else if !self.rated {
+                    format!("PRPR[UNRATED] {tags}")
+                } else if let Some(state) = &self.update_state {
+                    format!(
+                        "PRPR {tags}  {}",
+                        if state.best {
+                            format!("NEW BEST +{:07}", state.improvement)
+                        } else {
+                            String::new()
+                        }
+                    )
+                } else {
+                    "Uploading…".to_owned()
+                };
+                let r = draw_text_aligned(ui, &text, main.x + dx, main.bottom() - 0.035, (0., 1.), 0.34, WHITE);
+                let r = draw_text_aligned(ui, &res.score_text, r.x, r.y - 0.023, (0., 1.), 1., WHITE);
+                // ranks are based on the million-point scale
+                let score = (res.score as u64 * 1000000 / res.max_score.max(1) as u64) as u32;
+                let icon = match (score, res.num_of_notes == res.max_combo) {
+                    _ if res.failed => 0,
+                    (x, _) if x < 700000 => 0,
+                    (x, _) if x < 820000 => 1,
+                    (x, _) if x < 880000 => 2,
+                    (x, _) if x < 920000 => 3,
+                    (x, _) if x < 960000 => 4,
+                    (1000000, _) => 7,
+                    (_, false) => 5,
+                    (_, true) => 6,
+                };
+                let p = ran(now, 1.4, 1.9).powi(2);
+                let s = main.h * 0.67;
+                let ct = (main.right() - main.h * slope - s / 2., r.bottom() + 0.02 - s / 2.);
+                let s = s + s * (1. - p) * 0.3;
+                draw_texture_ex(
+                    *self.icons[icon],
+                    ct.0 - s / 2.,
+                    ct.1 - s / 2.,
+                    Color::new(1., 1., 1., p),
+                    DrawTextureParams {
+                        dest_size: Some(vec2(s, s)),
+                        ..Default::default()
+                    },
+                );
+            }
+            gl.pop_model_matrix();
 
-        tran(gl, (1. - ran(now, 0.4, 1.5)).powi(3));
-        let d = r.h / 16.;
-        let s1 = Rect::new(main.x - d * 4. * slope, main.bottom() + d, main.w - d * 5. * slope, d * 3.);
-        draw_parallelogram(s1, None, c, true);
-        {
-            let dy = 0.025;
-            let r = draw_text_aligned(ui, "Max Combo", s1.x + dx, s1.bottom() - dy, (0., 1.), 0.34, WHITE);
-            draw_text_aligned(ui, &res.max_combo.to_string(), r.x, r.y - 0.01, (0., 1.), 0.7, WHITE);
-            let r = draw_text_aligned(ui, "Accuracy", s1.right() - dx, s1.bottom() - dy, (1., 1.), 0.34, WHITE);
-            draw_text_aligned(ui, &format!("{:.2}%", res.accuracy * 100.), r.right(), r.y - 0.01, (1., 1.), 0.7, WHITE);
-        }
-        gl.pop_model_matrix();
+            tran(gl, (1. - ran(now, 0.4, 1.5)).powi(3));
+            let d = r.h / 16.;
+            let s1 = Rect::new(main.x - d * 4. * slope, main.bottom() + d, main.w - d * 5. * slope, d * 3.);
+            draw_parallelogram(s1, None, c, true);
+            {
+                let dy = 0.025;
+                let r = draw_text_aligned(ui, "Max Combo", s1.x + dx, s1.bottom() - dy, (0., 1.), 0.34, WHITE);
+                draw_text_aligned(ui, &res.max_combo.to_string(), r.x, r.y - 0.01, (0., 1.), 0.7, WHITE);
+                let r = draw_text_aligned(ui, "Accuracy", s1.right() - dx, s1.bottom() - dy, (1., 1.), 0.34, WHITE);
+                draw_text_aligned(ui, &format!("{:.2}%", res.accuracy * 100.), r.right(), r.y - 0.01, (1., 1.), 0.7, WHITE);
+            }
+            gl.pop_model_matrix();
 
-        tran(gl, (1. - ran(now, 0.5, 1.7)).powi(3));
-        let s2 = Rect::new(s1.x - d * 4. * slope, s1.bottom() + d, s1.w, s1.h);
-        draw_parallelogram(s2, None, c, true);
-        {
-            let dy = 0.025;
-            let dy2 = 0.015;
-            let bg = 0.57;
-            let sm = 0.26;
-            let draw_count = |ui: &mut Ui, ratio: f32, name: &str, count: u32| {
-                let r = draw_text_aligned(ui, name, s2.x + s2.w * ratio, s2.bottom() - dy, (0.5, 1.), sm, WHITE);
-                draw_text_aligned(ui, &count.to_string(), r.center().x, r.y - dy2, (0.5, 1.), bg, WHITE);
-            };
-            draw_count(ui, 0.14, "Perfect", res.counts[0]);
-            draw_count(ui, 0.33, "Good", res.counts[1]);
-            draw_count(ui, 0.46, "Bad", res.counts[2]);
-            draw_count(ui, 0.59, "Miss", res.counts[3]);
-
-            let sm = 0.3;
-            let l = s2.x + s2.w * 0.72;
-            let rt = s2.x + s2.w * 0.94;
-            let cy = s2.center().y;
-            let r = draw_text_aligned(ui, "Early", l, cy - dy2 / 2., (0., 1.), sm, WHITE);
-            draw_text_aligned(ui, &res.early.to_string(), rt, r.bottom(), (1., 1.), sm, WHITE);
-            let r = draw_text_aligned(ui, "Late", l, cy + dy2 / 2., (0., 0.), 0.3, WHITE);
-            draw_text_aligned(ui, &res.late.to_string(), rt, r.y, (1., 0.), sm, WHITE);
+            tran(gl, (1. - ran(now, 0.5, 1.7)).powi(3));
+            let s2 = Rect::new(s1.x - d * 4. * slope, s1.bottom() + d, s1.w, s1.h);
+            draw_parallelogram(s2, None, c, true);
+            {
+                let dy = 0.025;
+                let dy2 = 0.015;
+                let bg = 0.57;
+                let sm = 0.26;
+                let draw_count = |ui: &mut Ui, ratio: f32, name: &str, count: u32| {
+                    let r = draw_text_aligned(ui, name, s2.x + s2.w * ratio, s2.bottom() - dy, (0.5, 1.), sm, WHITE);
+                    draw_text_aligned(ui, &count.to_string(), r.center().x, r.y - dy2, (0.5, 1.), bg, WHITE);
+                };
+                draw_count(ui, 0.14, "Perfect", res.counts[0]);
+                draw_count(ui, 0.33, "Good", res.counts[1]);
+                draw_count(ui, 0.46, "Bad", res.counts[2]);
+                draw_count(ui, 0.59, "Miss", res.counts[3]);
+
+                let sm = 0.3;
+                let l = s2.x + s2.w * 0.72;
+                let rt = s2.x + s2.w * 0.94;
+                let cy = s2.center().y;
+                let r = draw_text_aligned(ui, "Early", l, cy - dy2 / 2., (0., 1.), sm, WHITE);
+                draw_text_aligned(ui, &res.early.to_string(), rt, r.bottom(), (1., 1.), sm, WHITE);
+                let r = draw_text_aligned(ui, "Late", l, cy + dy2 / 2., (0., 0.), 0.3, WHITE);
+                draw_text_aligned(ui, &res.late.to_string(), rt, r.y, (1., 0.), sm, WHITE);
+            }
+            gl.pop_model_matrix();
         }
-        gl.pop_model_matrix();
 
         fn touched(rect: Rect) -> bool {
             Judge::get_touches()
                 .iter()
                 .any(|touch| touch.phase == TouchPhase::Ended && rect.contains(touch.position))
         }
+        if now >= 2. && touched(area) {
+            self.show_timing = !self.show_timing;
+        }
+        draw_text_aligned(
+            ui,
+            &if self.show_timing { tl!("timing-back") } else { tl!("timing-hint") },
+            area.x + dx,
+            area.bottom() + 0.012,
+            (0., 0.),
+            0.26,
+            Color::new(1., 1., 1., ran(now, 2., 2.5) * 0.7),
+        );
 
         let dy = 0.006;
         let w = 0.17;
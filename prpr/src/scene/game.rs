@@ -4,13 +4,15 @@ crate::tl_file!("game");
 
 use super::{draw_background, ending::RecordUpdateState, request_input, return_input, show_message, take_input, EndingScene, NextScene, Scene};
 use crate::{
-    config::Config,
+    calibration::{self, OffsetEstimate},
+    config::{Config, Modifiers},
     core::{copy_fbo, BadNote, Chart, ChartExtra, Effect, Point, Resource, UIElement, Vector, JUDGE_LINE_GOOD_COLOR, JUDGE_LINE_PERFECT_COLOR},
     ext::{screen_aspect, RectExt, SafeTexture},
     fs::FileSystem,
     info::{ChartFormat, ChartInfo},
-    judge::Judge,
-    parse::{parse_extra, parse_pec, parse_phigros, parse_rpe},
+    judge::{input, Judge, JudgeProfile, Judgement, Scoring},
+    parse::{detect_format, parse_extra, parse_pec, parse_phigros, parse_rpe, ChartTransform},
+    replay::Replay,
     task::Task,
     time::TimeManager,
     ui::{RectButton, Ui},
@@ -19,7 +21,7 @@ use anyhow::{bail, Context, Result};
 use concat_string::concat_string;
 use lyon::path::Path;
 use macroquad::{prelude::*, window::InternalGlContext};
-use sasa::{Music, MusicParams};
+use sasa::{AudioClip, Music, MusicParams};
 use std::{
     io::ErrorKind,
     ops::{DerefMut, Range},
@@ -36,6 +38,8 @@ mod inner;
 #[cfg(feature = "closed")]
 use inner::*;
 
+mod practice;
+
 pub static FFMPEG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
 
 const WAIT_TIME: f32 = 0.5;
@@ -58,11 +62,15 @@ extern "C" {
     fn on_game_start();
 }
 
-#[derive(PartialEq, Eq)]
 pub enum GameMode {
     Normal,
     TweakOffset,
+    /// Loops a section of the chart, optionally slowed down.
     Exercise,
+    /// Plays back a recorded replay. Judgements only depend on the recorded input, not on the clock.
+    Replay(Replay),
+    /// Plays the metronome chart from [`calibration`] and pops with an `Option<OffsetEstimate>`.
+    Calibration,
 }
 
 #[derive(Clone)]
@@ -87,12 +95,24 @@ pub struct GameScene {
     chart_format: ChartFormat,
     info_offset: f32,
     compatible_mode: bool,
+    /// Whether an effect samples the previous frame, see [`Resource::keep_frame`].
+    keep_frame: bool,
     effects: Vec<Effect>,
 
     first_in: bool,
     exercise_range: Range<f32>,
     exercise_press: Option<(i8, u64)>,
     exercise_btns: (RectButton, RectButton),
+    practice_speed: f32,
+    ramp_up: bool,
+    /// The speed and range the current music is prepared for.
+    practice_music: Option<(f32, Range<f32>)>,
+    /// Pitch-corrected music being prepared for the given speed, played once ready if that's still the practice speed.
+    practice_task: Option<(f32, Task<AudioClip>)>,
+    /// Pitch-corrected music for the exercise range, by speed.
+    practice_clips: Vec<(f32, AudioClip)>,
+
+    replay_cursor: usize,
 
     pub music: Music,
 
@@ -113,6 +133,7 @@ macro_rules! reset {
         $self.bad_notes.clear();
         $self.judge.reset();
         $self.chart.reset();
+        $self.replay_cursor = 0;
         $res.judge_line_color = JUDGE_LINE_PERFECT_COLOR;
         $self.music.pause()?;
         $self.music.seek_to(0.)?;
@@ -156,22 +177,21 @@ impl GameScene {
             ChartExtra::default()
         };
         let text = String::from_utf8(Self::load_chart_bytes(fs, info).await.context("Failed to load chart")?)?;
-        let format = info.format.clone().unwrap_or_else(|| {
-            if text.starts_with('{') {
-                if text.contains("\"META\"") {
-                    ChartFormat::Rpe
-                } else {
-                    ChartFormat::Pgr
-                }
-            } else {
-                ChartFormat::Pec
-            }
-        });
+        let format = info.format.clone().unwrap_or_else(|| detect_format(&text));
         let mut chart = match format {
             ChartFormat::Rpe => parse_rpe(&text, fs, extra).await,
             ChartFormat::Pgr => parse_phigros(&text, extra),
             ChartFormat::Pec => parse_pec(&text, extra),
         }?;
+        if let Some(target) = chart.extra.effects.iter().filter_map(|it| it.target).find(|it| *it >= chart.lines.len()) {
+            bail!("Effect targets judge line #{target}, but there are only {} lines", chart.lines.len());
+        }
+        if let Some(id) = chart.extra.lines.keys().find(|it| **it >= chart.lines.len()) {
+            bail!("Expressions are given for judge line #{id}, but there are only {} lines", chart.lines.len());
+        }
+        for (id, exprs) in std::mem::take(&mut chart.extra.lines) {
+            exprs.apply(&mut chart.lines[id].object);
+        }
         chart.settings.hold_partial_cover = info.hold_partial_cover;
         Ok((chart, text, format))
     }
@@ -194,9 +214,44 @@ impl GameScene {
             GameMode::Exercise => {
                 config.autoplay = false;
             }
+            GameMode::Replay(ref replay) => {
+                config.autoplay = false;
+                config.speed = replay.speed;
+                config.note_scale = replay.note_scale;
+                config.aspect_ratio = Some(replay.aspect_ratio);
+                config.modifiers = replay.modifiers;
+                config.scoring = replay.scoring;
+            }
+            GameMode::Calibration => {
+                config.autoplay = false;
+                config.speed = 1.;
+                config.modifiers = Modifiers::default();
+                fs = calibration::patch_fs(fs);
+            }
             _ => {}
         }
-        let (mut chart, chart_str, chart_format) = Self::load_chart(fs.deref_mut(), &info).await?;
+        let judge_profile = match &mode {
+            GameMode::Replay(replay) => replay.judge_profile,
+            // wide windows, so that taps with a large latency are still counted
+            GameMode::Calibration => JudgeProfile::Casual,
+            _ => info.judge_profile.unwrap_or(config.judge_profile),
+        };
+        // plays with non-standard windows, scores or modifiers are not comparable with others
+        let upload_fn = if matches!(mode, GameMode::Replay(_) | GameMode::Calibration)
+            || judge_profile != JudgeProfile::Standard
+            || config.scoring != Scoring::Phigros
+            || !config.modifiers.is_empty()
+        {
+            None
+        } else {
+            upload_fn
+        };
+        let (mut chart, chart_str, chart_format) = if matches!(mode, GameMode::Calibration) {
+            (calibration::chart(), String::new(), ChartFormat::Pgr)
+        } else {
+            Self::load_chart(fs.deref_mut(), &info).await?
+        };
+        ChartTransform::new(&config.modifiers).apply(&mut chart);
         let effects = std::mem::take(&mut chart.extra.global_effects);
         if config.fxaa {
             chart
@@ -205,14 +260,30 @@ impl GameScene {
                 .push(Effect::new(0.0..f32::INFINITY, include_str!("fxaa.glsl"), Vec::new(), false).unwrap());
         }
 
+        let keep_frame = chart.extra.effects.iter().chain(&effects).any(Effect::uses_previous_frame);
+
         let info_offset = info.offset;
         let (avatar, player) = player;
         let mut res = Resource::new(config, info, fs, avatar, background, illustration, chart.extra.effects.is_empty() && effects.is_empty())
             .await
             .context("Failed to load resources")?;
+        if let GameMode::Replay(replay) = &mode {
+            res.dpi = replay.dpi;
+        }
         let exercise_range = (chart.offset + info_offset + res.config.offset)..res.track_length;
+        let practice_speed = res.config.speed.clamp(practice::MIN_SPEED, 1.);
 
-        let judge = Judge::new(&chart);
+        let mut judge = Judge::new(&chart, judge_profile, res.config.modifiers, res.config.scoring.rule(&judge_profile.windows()));
+        if let GameMode::Replay(replay) = &mode {
+            judge.lanes = replay.lanes as usize;
+            judge.key_flicks = replay.key_flicks;
+        } else {
+            for source in input::sources(&res.config) {
+                if let Err(err) = judge.add_source(source) {
+                    warn!("Ignored input source: {:?}", err);
+                }
+            }
+        }
 
         let music = Self::new_music(&mut res)?;
         Ok(Self {
@@ -228,6 +299,7 @@ impl GameScene {
             chart_str,
             chart_format,
             compatible_mode: false,
+            keep_frame,
             effects,
             info_offset,
 
@@ -235,6 +307,13 @@ impl GameScene {
             exercise_range,
             exercise_press: None,
             exercise_btns: (RectButton::new(), RectButton::new()),
+            practice_speed,
+            ramp_up: false,
+            practice_music: None,
+            practice_task: None,
+            practice_clips: Vec::new(),
+
+            replay_cursor: 0,
 
             music,
 
@@ -251,6 +330,84 @@ impl GameScene {
         })
     }
 
+    /// Recreates the music for the practice speed in exercise mode, keeping its position. Slowed music is pitch-corrected around the
+    /// exercise range in the background and cached per speed; it plays uncorrected until that is done.
+    fn prepare_practice(&mut self, tm: &mut TimeManager) -> Result<()> {
+        if !matches!(self.mode, GameMode::Exercise) {
+            return Ok(());
+        }
+        let key = (self.practice_speed, self.exercise_range.clone());
+        if self.practice_music.as_ref() == Some(&key) {
+            return Ok(());
+        }
+        if self.practice_music.as_ref().map_or(true, |it| it.1 != key.1) {
+            self.practice_task = None;
+            self.practice_clips.clear();
+        }
+        let speed = self.practice_speed;
+        self.res.config.speed = speed;
+        tm.speed = speed as _;
+        let clip = if speed < 1. { self.practice_clip(speed) } else { None };
+        let clip = clip.unwrap_or_else(|| self.res.music.clone());
+        self.set_music(clip)?;
+        self.practice_music = Some(key);
+        self.prefetch_practice();
+        Ok(())
+    }
+
+    /// When ramping up, prepares the music for the next speed step while the current one is played.
+    fn prefetch_practice(&mut self) {
+        let next = self.practice_speed + practice::SPEED_STEP;
+        if self.ramp_up && self.practice_task.is_none() && next < 1. {
+            self.practice_clip(next);
+        }
+    }
+
+    /// Returns the pitch-corrected music for `speed` if it's ready, and starts preparing it otherwise.
+    fn practice_clip(&mut self, speed: f32) -> Option<AudioClip> {
+        let same = |it: f32| (it - speed).abs() < 1e-4;
+        if let Some((_, clip)) = self.practice_clips.iter().find(|it| same(it.0)) {
+            return Some(clip.clone());
+        }
+        if self.practice_task.as_ref().map_or(true, |it| !same(it.0)) {
+            let clip = self.res.music.clone();
+            // a bit of margin for the countdown after resuming
+            let range = (self.exercise_range.start - 3.)..(self.exercise_range.end + 1.);
+            self.practice_task = Some((
+                speed,
+                Task::new(async move {
+                    tokio::task::spawn_blocking(move || practice::pitch_compensate(&clip, speed, range))
+                        .await
+                        .unwrap()
+                }),
+            ));
+        }
+        None
+    }
+
+    /// Replaces the music with `clip` at the current speed, keeping its position and whether it's playing.
+    fn set_music(&mut self, clip: AudioClip) -> Result<()> {
+        let pos = self.music.position();
+        let playing = !self.music.paused();
+        self.music = self.res.audio.create_music(
+            clip,
+            MusicParams {
+                amplifier: self.res.config.volume_music as _,
+                playback_rate: self.res.config.speed as _,
+                ..Default::default()
+            },
+        )?;
+        self.music.seek_to(pos)?;
+        if playing {
+            self.music.play()?;
+        }
+        Ok(())
+    }
+
+    fn beat_at(&self, time: f32) -> f32 {
+        self.chart.bpm_list.borrow_mut().beat(time - self.offset())
+    }
+
     fn new_music(res: &mut Resource) -> Result<Music> {
         res.audio.create_music(
             res.music.clone(),
@@ -315,7 +472,7 @@ impl GameScene {
         let margin = 0.03;
 
         self.chart.with_element(ui, res, UIElement::Score, |ui, color, scale| {
-            ui.text(format!("{:07}", self.judge.score()))
+            ui.text(self.judge.score_text())
                 .pos(1. - margin, top + eps * 2.2 - (1. - p) * 0.4)
                 .anchor(1., 0.)
                 .size(0.8)
@@ -398,7 +555,7 @@ impl GameScene {
         if tm.paused() {
             let h = 1. / res.aspect_ratio;
             draw_rectangle(-1., -h, 2., h * 2., Color::new(0., 0., 0., 0.6));
-            let o = if self.mode == GameMode::Exercise { -0.3 } else { 0. };
+            let o = if matches!(self.mode, GameMode::Exercise) { -0.3 } else { 0. };
             let s = 0.06;
             let w = 0.05;
             draw_texture_ex(
@@ -456,8 +613,10 @@ impl GameScene {
                         reset!(self, res, tm);
                     }
                     Some(1) => {
+                        self.prepare_practice(tm)?;
+                        let res = &mut self.res;
                         let mut pos = self.music.position();
-                        if self.mode == GameMode::Exercise && tm.now() > self.exercise_range.end as f64 {
+                        if matches!(self.mode, GameMode::Exercise) && tm.now() > self.exercise_range.end as f64 {
                             tm.seek_to(self.exercise_range.start as f64);
                             self.music.seek_to(self.exercise_range.start)?;
                             pos = self.exercise_range.start;
@@ -478,7 +637,7 @@ impl GameScene {
                     _ => {}
                 }
             }
-            if self.mode == GameMode::Exercise {
+            if matches!(self.mode, GameMode::Exercise) {
                 ui.dy(0.06);
                 let hw = 0.7;
                 let h = 0.06;
@@ -563,6 +722,12 @@ impl GameScene {
                 tx.ui
                     .fill_rect(re.feather(0.01), Color::new(1., 1., 1., if self.exercise_btns.0.touching() { 0.5 } else { 1. }));
                 tx.draw();
+                let beat = self.beat_at(self.exercise_range.start);
+                ui.text(format!("{beat:.2}b"))
+                    .pos(re.center().x, re.bottom() + 0.02)
+                    .anchor(0.5, 0.)
+                    .size(0.4)
+                    .draw();
 
                 let mut tx = ui
                     .text(fmt_time(self.exercise_range.end))
@@ -574,6 +739,20 @@ impl GameScene {
                 tx.ui
                     .fill_rect(re.feather(0.01), Color::new(1., 1., 1., if self.exercise_btns.1.touching() { 0.5 } else { 1. }));
                 tx.draw();
+                let beat = self.beat_at(self.exercise_range.end);
+                ui.text(format!("{beat:.2}b"))
+                    .pos(re.center().x, re.bottom() + 0.02)
+                    .anchor(0.5, 0.)
+                    .size(0.4)
+                    .draw();
+
+                ui.dy(0.16);
+                ui.scope(|ui| {
+                    ui.dx(-0.5);
+                    let r = ui.slider(tl!("ex-speed"), practice::MIN_SPEED..1., practice::SPEED_STEP, &mut self.practice_speed, Some(0.4));
+                    ui.dx(r.w + 0.08);
+                    ui.checkbox(tl!("ex-ramp-up"), &mut self.ramp_up);
+                });
             }
         }
         if let Some(time) = self.pause_rewind {
@@ -591,6 +770,11 @@ impl GameScene {
         Ok(())
     }
 
+    /// The replay of the current play.
+    pub fn replay(&self) -> Replay {
+        Replay::new(&self.res, &self.judge)
+    }
+
     fn interactive(res: &Resource, state: &State) -> bool {
         res.config.interactive && matches!(state, State::Playing)
     }
@@ -661,6 +845,9 @@ impl Scene for GameScene {
         #[cfg(target_arch = "wasm32")]
         on_game_start();
         self.music = Self::new_music(&mut self.res)?;
+        self.practice_music = None;
+        self.practice_task = None;
+        self.practice_clips.clear();
         self.res.camera.render_target = target;
         tm.speed = self.res.config.speed as _;
         reset!(self, self.res, tm);
@@ -687,10 +874,29 @@ impl Scene for GameScene {
 
     fn update(&mut self, tm: &mut TimeManager) -> Result<()> {
         self.res.audio.recover_if_needed()?;
+        if let Some((speed, clip)) = self.practice_task.as_mut().and_then(|(speed, task)| Some((*speed, task.take()?))) {
+            self.practice_task = None;
+            if self.practice_clips.len() >= practice::CACHED_CLIPS {
+                self.practice_clips.remove(0);
+            }
+            self.practice_clips.push((speed, clip.clone()));
+            if self.practice_music.as_ref().map_or(false, |it| it.0 == speed) {
+                self.set_music(clip)?;
+            }
+            self.prefetch_practice();
+        }
         if matches!(self.state, State::Playing) {
             tm.update(self.music.position() as f64);
         }
-        if self.mode == GameMode::Exercise && tm.now() > self.exercise_range.end as f64 && !tm.paused() {
+        if matches!(self.mode, GameMode::Exercise) && tm.now() > self.exercise_range.end as f64 && !tm.paused() {
+            let offset = self.offset();
+            let start = self.exercise_range.start;
+            let mut hits = self.judge.hits().iter().filter(|it| it.time + offset >= start).peekable();
+            let clean = hits.peek().is_some() && hits.all(|it| matches!(it.judgement, Judgement::Perfect | Judgement::Good));
+            if self.ramp_up && clean && self.practice_speed < 1. {
+                self.practice_speed = (self.practice_speed + practice::SPEED_STEP).min(1.);
+                show_message(tl!("ex-speed-up", "speed" => format!("{:.2}x", self.practice_speed))).ok();
+            }
             let state = self.state.clone();
             reset!(self, self.res, tm);
             self.state = state;
@@ -706,20 +912,20 @@ impl Scene for GameScene {
                     self.res.alpha = 1.;
                     self.state = State::BeforeMusic;
                     tm.reset();
-                    tm.seek_to(if self.mode == GameMode::Exercise {
+                    tm.seek_to(if matches!(self.mode, GameMode::Exercise) {
                         self.exercise_range.start as f64
                     } else {
                         offset.min(0.) as f64
                     });
                     self.last_update_time = tm.real_time();
-                    if self.first_in && self.mode == GameMode::Exercise {
+                    if self.first_in && matches!(self.mode, GameMode::Exercise) {
                         tm.pause();
                         self.first_in = false;
                     }
                     tm.now() as f32
                 } else {
                     self.res.alpha = 1. - (1. - time / Self::BEFORE_TIME).powi(3);
-                    if self.mode == GameMode::Exercise {
+                    if matches!(self.mode, GameMode::Exercise) {
                         self.exercise_range.start
                     } else {
                         offset
@@ -728,6 +934,7 @@ impl Scene for GameScene {
             }
             State::BeforeMusic => {
                 if time >= 0.0 {
+                    self.prepare_practice(tm)?;
                     self.music.seek_to(time)?;
                     if !tm.paused() {
                         self.music.play()?;
@@ -739,6 +946,10 @@ impl Scene for GameScene {
             State::Playing => {
                 if time > self.res.track_length + WAIT_TIME {
                     self.state = State::Ending;
+                } else if self.judge.failed() && matches!(self.mode, GameMode::Normal | GameMode::Replay(_)) {
+                    self.music.pause()?;
+                    tm.seek_to((self.res.track_length + WAIT_TIME) as f64);
+                    self.state = State::Ending;
                 }
                 time
             }
@@ -758,8 +969,20 @@ impl Scene for GameScene {
                             }
                         }
                     }
+                    if matches!(self.mode, GameMode::Normal) && !self.res.config.autoplay {
+                        if let Some(path) = &self.res.config.replay_path {
+                            if let Err(err) = std::fs::write(path, self.replay().encode()) {
+                                warn!("Failed to save replay: {:?}", err);
+                            }
+                        }
+                    }
+                    if let (Some(path), Some(debug)) = (&self.res.config.trace_path, &self.judge.debug) {
+                        if let Err(err) = std::fs::write(path, debug.to_csv()) {
+                            warn!("Failed to save judge trace: {:?}", err);
+                        }
+                    }
                     self.next_scene = match self.mode {
-                        GameMode::Normal => Some(NextScene::Overlay(Box::new(EndingScene::new(
+                        GameMode::Normal | GameMode::Replay(_) => Some(NextScene::Overlay(Box::new(EndingScene::new(
                             self.res.background.clone(),
                             self.res.illustration.clone(),
                             self.res.player.clone(),
@@ -776,6 +999,9 @@ impl Scene for GameScene {
                         )?))),
                         GameMode::TweakOffset => Some(NextScene::PopWithResult(Box::new(None::<f32>))),
                         GameMode::Exercise => None,
+                        GameMode::Calibration => {
+                            Some(NextScene::PopWithResult(Box::new(OffsetEstimate::new(self.judge.result().diffs(), &self.res.config))))
+                        }
                     };
                 }
                 self.res.alpha = 1. - (t / AFTER_TIME).min(1.).powi(2);
@@ -786,7 +1012,16 @@ impl Scene for GameScene {
         self.res.time = time;
         if !tm.paused() && self.pause_rewind.is_none() {
             self.gl.quad_gl.viewport(self.res.camera.viewport);
-            self.judge.update(&mut self.res, &mut self.chart, &mut self.bad_notes);
+            if let GameMode::Replay(replay) = &self.mode {
+                for frame in replay.frames[self.replay_cursor..].iter().take_while(|it| it.time <= time) {
+                    self.res.time = frame.time;
+                    self.judge.update_with(&mut self.res, &mut self.chart, &mut self.bad_notes, frame);
+                    self.replay_cursor += 1;
+                }
+                self.res.time = time;
+            } else {
+                self.judge.update(&mut self.res, &mut self.chart, &mut self.bad_notes);
+            }
             self.gl.quad_gl.viewport(None);
         }
         let counts = self.judge.counts();
@@ -801,10 +1036,10 @@ impl Scene for GameScene {
         };
         self.res.judge_line_color.a *= self.res.alpha;
         self.chart.update(&mut self.res);
-        let res = &mut self.res;
-        if res.config.interactive && is_key_pressed(KeyCode::Space) {
+        if self.res.config.interactive && is_key_pressed(KeyCode::Space) {
             if tm.paused() {
                 if matches!(self.state, State::Playing) {
+                    self.prepare_practice(tm)?;
                     self.music.play()?;
                     tm.resume();
                 }
@@ -815,14 +1050,17 @@ impl Scene for GameScene {
                 tm.pause();
             }
         }
+        let res = &mut self.res;
         if Self::interactive(res, &self.state) {
-            if is_key_pressed(KeyCode::Left) {
+            // seeking would desync the replay from the chart
+            let seekable = !matches!(self.mode, GameMode::Replay(_));
+            if seekable && is_key_pressed(KeyCode::Left) {
                 res.time -= 1.;
                 let dst = (self.music.position() - 1.).max(0.);
                 self.music.seek_to(dst)?;
                 tm.seek_to(dst as f64);
             }
-            if is_key_pressed(KeyCode::Right) {
+            if seekable && is_key_pressed(KeyCode::Right) {
                 res.time += 5.;
                 let dst = (self.music.position() + 5.).min(res.track_length);
                 self.music.seek_to(dst)?;
@@ -831,15 +1069,36 @@ impl Scene for GameScene {
             if is_key_pressed(KeyCode::Q) {
                 self.should_exit = true;
             }
+            if res.config.debug && is_key_pressed(KeyCode::F3) {
+                // cycles the debug overlay through the line groups, then back to all lines
+                let mut groups = Vec::new();
+                for group in self.chart.lines.iter().filter_map(|it| it.group.as_ref()) {
+                    if !groups.contains(&group) {
+                        groups.push(group);
+                    }
+                }
+                let next = match res.config.debug_groups.as_slice() {
+                    [current] => groups.iter().position(|it| *it == current).and_then(|it| groups.get(it + 1)),
+                    _ => groups.first(),
+                };
+                res.config.debug_groups = next.map(|it| (*it).clone()).into_iter().collect();
+            }
         }
         for e in &mut self.effects {
             e.update(&self.res);
         }
         if let Some((id, text)) = take_input() {
+            let chart_offset = self.offset();
+            let bpm_list = &self.chart.bpm_list;
+            // either hh:mm:ss or a beat like `32b`
             let parse_time = |s: &str| -> Option<f32> {
                 if s.is_empty() {
                     return None;
                 }
+                if let Some(beats) = s.strip_suffix('b') {
+                    let beats = beats.trim().parse::<f32>().ok().filter(|it| *it >= 0.)?;
+                    return Some(bpm_list.borrow_mut().time_beats(beats) + chart_offset);
+                }
                 let r = s.split(':').collect::<Vec<_>>();
                 if r.len() > 3 {
                     return None;
@@ -890,7 +1149,7 @@ impl Scene for GameScene {
     }
 
     fn touch(&mut self, tm: &mut TimeManager, touch: &Touch) -> Result<bool> {
-        if self.mode == GameMode::Exercise && tm.paused() {
+        if matches!(self.mode, GameMode::Exercise) && tm.paused() {
             if self.exercise_btns.0.touch(touch) {
                 request_input("exercise_start", &fmt_time(self.exercise_range.start));
                 return Ok(true);
@@ -945,6 +1204,9 @@ impl Scene for GameScene {
         );
 
         self.bad_notes.retain(|dummy| dummy.render(res));
+        if res.config.debug {
+            self.judge.render_debug(ui, res, &self.chart);
+        }
         let t = tm.real_time();
         let dt = (t - std::mem::replace(&mut self.last_update_time, t)) as f32;
         if res.config.particle {
@@ -953,7 +1215,7 @@ impl Scene for GameScene {
         self.ui(ui, tm)?;
         self.overlay_ui(ui, tm)?;
 
-        if self.mode == GameMode::TweakOffset {
+        if matches!(self.mode, GameMode::TweakOffset) {
             push_camera_state();
             self.gl.quad_gl.viewport(None);
             set_camera(&Camera2D {
@@ -976,6 +1238,9 @@ impl Scene for GameScene {
             }
             pop_camera_state();
         }
+        if self.keep_frame && !self.compatible_mode && !self.res.keep_frame() {
+            self.compatible_mode = true;
+        }
         if msaa || !self.res.no_effect {
             // render the texture onto screen
             if let Some(target) = &self.res.chart_target {
@@ -1024,8 +1289,9 @@ impl Scene for GameScene {
             }
             tm.speed = 1.0;
             match self.mode {
-                GameMode::Normal | GameMode::Exercise => NextScene::Pop,
+                GameMode::Normal | GameMode::Exercise | GameMode::Replay(_) => NextScene::Pop,
                 GameMode::TweakOffset => NextScene::PopWithResult(Box::new(None::<f32>)),
+                GameMode::Calibration => NextScene::PopWithResult(Box::new(None::<OffsetEstimate>)),
             }
         } else if let Some(next_scene) = self.next_scene.take() {
             tm.speed = 1.0;
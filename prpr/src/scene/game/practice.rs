@@ -0,0 +1,163 @@
+use sasa::{AudioClip, Frame};
+use std::ops::Range;
+
+pub const MIN_SPEED: f32 = 0.5;
+/// Speed gained by each clean loop when ramping up.
+pub const SPEED_STEP: f32 = 0.05;
+/// Number of pitch-corrected clips kept around, each a full copy of the music.
+pub const CACHED_CLIPS: usize = 3;
+
+const GRAIN: usize = 2048;
+const HOP: usize = GRAIN / 4;
+/// How far (in frames) a grain may be moved to line up with the ones before it.
+const TOLERANCE: i32 = (HOP / 2) as i32;
+/// Only every `STRIDE`-th frame is compared when lining up grains.
+const STRIDE: usize = 4;
+
+fn sample(frames: &[Frame], pos: f32) -> Frame {
+    if pos < 0. {
+        return Frame::default();
+    }
+    let index = pos as usize;
+    let Some(a) = frames.get(index) else {
+        return Frame::default();
+    };
+    let b = frames.get(index + 1).unwrap_or(a);
+    let f = pos - index as f32;
+    Frame(a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f)
+}
+
+/// Raises the pitch of `range` (in seconds) of `clip` by `1 / speed` without changing its length, so that it sounds at the
+/// original pitch when played at `speed`. Audio outside the range is kept as is.
+///
+/// This is a grain-resampling pitch shift, not a time-stretch: each windowed grain is resampled around its center, which keeps
+/// the audio aligned with the chart, and the grains are overlapped back together. Each grain is moved by up to [`TOLERANCE`]
+/// frames to best match the grains already laid down, so that they don't cancel each other out. It is slow for long ranges,
+/// so run it off the main thread.
+pub fn pitch_compensate(clip: &AudioClip, speed: f32, range: Range<f32>) -> AudioClip {
+    let frames = clip.frames();
+    let rate = clip.sample_rate() as f32;
+    let start = (range.start.max(0.) * rate) as usize;
+    let end = ((range.end * rate) as usize).min(frames.len());
+    let mut res = frames.to_vec();
+    if start >= end {
+        return AudioClip::from_raw(res, clip.sample_rate());
+    }
+    let scale = 1. / speed;
+    let window: Vec<f32> = (0..GRAIN)
+        .map(|i| 0.5 - 0.5 * (2. * std::f32::consts::PI * i as f32 / GRAIN as f32).cos())
+        .collect();
+    let mut acc = vec![Frame::default(); end - start];
+    let mut weight = vec![0f32; end - start];
+    let mut grain = start.saturating_sub(GRAIN - HOP);
+    while grain < end {
+        let center = (grain + GRAIN / 2) as f32;
+        let source = |offset: f32, k: usize| sample(frames, center + offset + (k as f32 - GRAIN as f32 / 2.) * scale);
+        let overlap: Vec<usize> = (0..GRAIN - HOP)
+            .step_by(STRIDE)
+            .filter(|k| (start..end).contains(&(grain + k)) && weight[grain + k - start] > 0.)
+            .collect();
+        let score = |offset: f32| {
+            overlap
+                .iter()
+                .map(|&k| {
+                    let (a, b) = (acc[grain + k - start], source(offset, k));
+                    (a.0 + a.1) * (b.0 + b.1)
+                })
+                .sum::<f32>()
+        };
+        let mut offset = 0.;
+        if !overlap.is_empty() {
+            let mut best = score(0.);
+            for it in -TOLERANCE..=TOLERANCE {
+                let value = score(it as f32);
+                if value > best {
+                    best = value;
+                    offset = it as f32;
+                }
+            }
+        }
+        for (k, w) in window.iter().enumerate() {
+            let pos = grain + k;
+            if pos < start {
+                continue;
+            }
+            if pos >= end {
+                break;
+            }
+            let src = source(offset, k);
+            let dst = &mut acc[pos - start];
+            dst.0 += src.0 * w;
+            dst.1 += src.1 * w;
+            weight[pos - start] += w;
+        }
+        grain += HOP;
+    }
+    for ((dst, frame), w) in res[start..end].iter_mut().zip(acc).zip(weight) {
+        if w > 1e-3 {
+            *dst = Frame(frame.0 / w, frame.1 / w);
+        }
+    }
+    AudioClip::from_raw(res, clip.sample_rate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 44100;
+
+    fn tone(freq: f32, secs: f32) -> AudioClip {
+        let frames = (0..(secs * RATE as f32) as usize)
+            .map(|i| {
+                let v = (2. * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin();
+                Frame(v, v)
+            })
+            .collect();
+        AudioClip::from_raw(frames, RATE)
+    }
+
+    fn frequency(frames: &[Frame]) -> f32 {
+        let crossings = frames.windows(2).filter(|it| (it[0].0 < 0.) != (it[1].0 < 0.)).count();
+        crossings as f32 / 2. / (frames.len() as f32 / RATE as f32)
+    }
+
+    fn same(a: &[Frame], b: &[Frame]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(a, b)| a.0.to_bits() == b.0.to_bits() && a.1.to_bits() == b.1.to_bits())
+    }
+
+    #[test]
+    fn pitch_shift() {
+        let clip = tone(440., 2.);
+        let rate = RATE as usize;
+        for speed in [0.5, 0.75, 0.9] {
+            let res = pitch_compensate(&clip, speed, 0.5..1.5);
+            let (frames, orig) = (res.frames(), clip.frames());
+            assert_eq!(frames.len(), orig.len());
+            assert_eq!(res.sample_rate(), RATE);
+
+            // away from the ends of the range, where the grains are cut off
+            let inner = &frames[rate * 3 / 4..rate * 5 / 4];
+            let freq = frequency(inner);
+            assert!((freq - 440. / speed).abs() < 5., "speed {speed}: {freq} Hz");
+            let quietest = inner
+                .chunks(200)
+                .map(|it| it.iter().map(|it| it.0.abs()).fold(0., f32::max))
+                .fold(1., f32::min);
+            assert!(quietest > 0.9, "speed {speed}: grains cancel out ({quietest})");
+
+            assert!(same(&frames[..rate / 2], &orig[..rate / 2]));
+            assert!(same(&frames[rate * 3 / 2..], &orig[rate * 3 / 2..]));
+        }
+    }
+
+    #[test]
+    fn empty_range() {
+        let clip = tone(440., 1.);
+        let res = pitch_compensate(&clip, 0.5, 2.0..3.0);
+        assert!(same(res.frames(), clip.frames()));
+    }
+}